@@ -9,6 +9,9 @@ SET xmloption = content;
 SET client_min_messages = warning;
 SET row_security = off;
 
+DELETE FROM public.upgrade_job;
+DELETE FROM public.shop_bundle_item;
+DELETE FROM public.shop_bundle;
 DELETE FROM public.artifact;
 DELETE FROM public.map_spaces;
 DELETE FROM public.available_blocks;
@@ -33,99 +36,102 @@ COPY public.user FROM stdin;
 1	Bot	donwick32@gmail.com	bot	true	0	0	1000	0	500
 \.
 
-COPY public.map_layout FROM stdin;
-1	1	1	t
+COPY public.map_layout (id, player, level_id, is_valid, "name", is_active, version, is_latest) FROM stdin;
+1	1	1	t	Main	t	1	t
 \.
 
-COPY public.building_type FROM stdin;
-0	Road	1	1	0	0	0	0
-1	Bank	3	3	2147483647	1	10	120
-2	Building_2	4	4	120	1	10	140
-3	Building_3	5	5	140	1	10	160
-4	Building_4	3	3	90	1	10	110
-5	Building_5	4	4	110	1	10	130
-6	Building_6	5	5	130	1	10	150
-7	Building_7	3	3	80	1	10	100
-8	Building_8	4	4	100	1	10	120
-9	Building_9	5	5	120	1	10	140
-10	Building_10	3	3	70	1	10	90
-11	Building_11	4	4	90	1	10	110
-12	Building_12	5	5	110	1	10	130
-13	Building_13	3	3	60	1	10	80
-14	Building_14	4	4	80	1	10	100
-15	Building_15	5	5	100	1	10	120
-16	Bank	3	3	2147483647	2	50	140
-17	Building_2	4	4	130	2	75	160
-18	Building_3	5	5	150	2	100	180
-19	Building_4	3	3	100	2	25	120
-20	Building_5	4	4	120	2	50	140
-21	Building_6	5	5	140	2	75	160
-22	Building_7	3	3	90	2	20	110
-23	Building_8	4	4	110	2	45	130
-24	Building_9	5	5	130	2	70	150
-25	Building_10	3	3	80	2	15	100
-26	Building_11	4	4	100	2	40	120
-27	Building_12	5	5	120	2	65	140
-28	Building_13	3	3	70	2	10	90
-29	Building_14	4	4	90	2	35	110
-30	Building_15	5	5	110	2	60	130
-31	Bank	3	3	2147483647	3	-1	160
-32	Building_2	4	4	140	3	-1	180
-33	Building_3	5	5	160	3	-1	200
-34	Building_4	3	3	110	3	-1	140
-35	Building_5	4	4	130	3	-1	160
-36	Building_6	5	5	150	3	-1	180
-37	Building_7	3	3	100	3	-1	120
-38	Building_8	4	4	120	3	-1	140
-39	Building_9	5	5	140	3	-1	160
-40	Building_10	3	3	90	3	-1	110
-41	Building_11	4	4	110	3	-1	130
-42	Building_12	5	5	130	3	-1	150
-43	Building_13	3	3	80	3	-1	100
-44	Building_14	4	4	100	3	-1	120
-45	Building_15	5	5	120	3	-1	140
+COPY public.building_type (id, "name", width, height, capacity, level, cost, hp, max_level, next_level_id) FROM stdin;
+0	Road	1	1	0	0	0	0	0	\N
+1	Bank	3	3	2147483647	1	10	120	3	16
+2	Building_2	4	4	120	1	10	140	3	17
+3	Building_3	5	5	140	1	10	160	3	18
+4	Building_4	3	3	90	1	10	110	3	19
+5	Building_5	4	4	110	1	10	130	3	20
+6	Building_6	5	5	130	1	10	150	3	21
+7	Building_7	3	3	80	1	10	100	3	22
+8	Building_8	4	4	100	1	10	120	3	23
+9	Building_9	5	5	120	1	10	140	3	24
+10	Building_10	3	3	70	1	10	90	3	25
+11	Building_11	4	4	90	1	10	110	3	26
+12	Building_12	5	5	110	1	10	130	3	27
+13	Building_13	3	3	60	1	10	80	3	28
+14	Building_14	4	4	80	1	10	100	3	29
+15	Building_15	5	5	100	1	10	120	3	30
+16	Bank	3	3	2147483647	2	50	140	3	31
+17	Building_2	4	4	130	2	75	160	3	32
+18	Building_3	5	5	150	2	100	180	3	33
+19	Building_4	3	3	100	2	25	120	3	34
+20	Building_5	4	4	120	2	50	140	3	35
+21	Building_6	5	5	140	2	75	160	3	36
+22	Building_7	3	3	90	2	20	110	3	37
+23	Building_8	4	4	110	2	45	130	3	38
+24	Building_9	5	5	130	2	70	150	3	39
+25	Building_10	3	3	80	2	15	100	3	40
+26	Building_11	4	4	100	2	40	120	3	41
+27	Building_12	5	5	120	2	65	140	3	42
+28	Building_13	3	3	70	2	10	90	3	43
+29	Building_14	4	4	90	2	35	110	3	44
+30	Building_15	5	5	110	2	60	130	3	45
+31	Bank	3	3	2147483647	3	-1	160	3	\N
+32	Building_2	4	4	140	3	-1	180	3	\N
+33	Building_3	5	5	160	3	-1	200	3	\N
+34	Building_4	3	3	110	3	-1	140	3	\N
+35	Building_5	4	4	130	3	-1	160	3	\N
+36	Building_6	5	5	150	3	-1	180	3	\N
+37	Building_7	3	3	100	3	-1	120	3	\N
+38	Building_8	4	4	120	3	-1	140	3	\N
+39	Building_9	5	5	140	3	-1	160	3	\N
+40	Building_10	3	3	90	3	-1	110	3	\N
+41	Building_11	4	4	110	3	-1	130	3	\N
+42	Building_12	5	5	130	3	-1	150	3	\N
+43	Building_13	3	3	80	3	-1	100	3	\N
+44	Building_14	4	4	100	3	-1	120	3	\N
+45	Building_15	5	5	120	3	-1	140	3	\N
+46	Wall	1	1	0	1	5	60	3	47
+47	Wall	1	1	0	2	10	80	3	48
+48	Wall	1	1	0	3	-1	100	3	\N
 \.
 
-COPY public.defender_type FROM stdin;
-1	4	50	8	1	10	Defender_1
-2	4	40	10	1	10	Defender_2
-3	4	30	6	1	10	Defender_3
-4	4	60	7	2	150	Defender_1
-5	4	50	8	2	250	Defender_2
-6	4	40	9	2	350	Defender_3
-7	4	70	9	3	-1	Defender_1
-8	4	60	10	3	-1	Defender_2
-9	4	50	8	3	-1	Defender_3
+COPY public.defender_type (id, speed, damage, radius, level, cost, "name", max_level, next_level_id, attack_type_id) FROM stdin;
+1	4	50	8	1	10	Defender_1	3	4	\N
+2	4	40	10	1	10	Defender_2	3	5	\N
+3	4	30	6	1	10	Defender_3	3	6	\N
+4	4	60	7	2	150	Defender_1	3	7	\N
+5	4	50	8	2	250	Defender_2	3	8	\N
+6	4	40	9	2	350	Defender_3	3	9	\N
+7	4	70	9	3	-1	Defender_1	3	\N	\N
+8	4	60	10	3	-1	Defender_2	3	\N	\N
+9	4	50	8	3	-1	Defender_3	3	\N	\N
 \.
 
-COPY public.emp_type FROM stdin;
-1	Bomb_1	3	20	10	Bomb_1	1
-2	Bomb_2	5	30	10	Bomb_2	1
-3	Bomb_3	4	25	10	Bomb_3	1
-4	Bomb_1	4	30	120	Bomb_1	2
-5	Bomb_2	6	40	180	Bomb_2	2
-6	Bomb_3	5	35	150	Bomb_3	2
-7	Bomb_1	5	40	-1	Bomb_1	3
-8	Bomb_2	7	50	-1	Bomb_2	3
-9	Bomb_3	6	45	-1	Bomb_3	3
+COPY public.emp_type (id, att_type, attack_radius, attack_damage, cost, "name", level, max_level, next_level_id, disable_duration) FROM stdin;
+1	damage	3	20	10	Bomb_1	1	3	4	0
+2	damage	5	30	10	Bomb_2	1	3	5	0
+3	damage	4	25	10	Bomb_3	1	3	6	0
+4	damage	4	30	120	Bomb_1	2	3	7	0
+5	damage	6	40	180	Bomb_2	2	3	8	0
+6	damage	5	35	150	Bomb_3	2	3	9	0
+7	damage	5	40	-1	Bomb_1	3	3	\N	0
+8	damage	7	50	-1	Bomb_2	3	3	\N	0
+9	damage	6	45	-1	Bomb_3	3	3	\N	0
 \.
 
-COPY public.mine_type FROM stdin;
-1	5	50	1	10	Mine_1
-2	6	70	2	120	Mine_1
-3	7	90	3	-1	Mine_1
+COPY public.mine_type (id, radius, damage, level, cost, "name", max_level, next_level_id, effect, effect_duration) FROM stdin;
+1	5	50	1	10	Mine_1	3	2	none	0
+2	6	70	2	120	Mine_1	3	3	none	0
+3	7	90	3	-1	Mine_1	3	\N	none	0
 \.
 
-COPY public.attacker_type FROM stdin;
-1	100	4	10	1	10	Attacker_1
-2	120	4	12	1	10	Attacker_2
-3	80	4	8	1	10	Attacker_3
-4	150	4	15	2	80	Attacker_1
-5	180	4	18	2	100	Attacker_2
-6	120	4	12	2	60	Attacker_3
-7	200	4	20	3	-1	Attacker_1
-8	240	4	24	3	-1	Attacker_2
-9	160	4	16	3	-1	Attacker_3
+COPY public.attacker_type (id, max_health, speed, amt_of_emps, level, cost, "name", max_level, next_level_id, ability, ability_power, ability_duration, ability_cooldown, ability_charges) FROM stdin;
+1	100	4	10	1	10	Attacker_1	3	4	none	0	0	0	0
+2	120	4	12	1	10	Attacker_2	3	5	none	0	0	0	0
+3	80	4	8	1	10	Attacker_3	3	6	none	0	0	0	0
+4	150	4	15	2	80	Attacker_1	3	7	none	0	0	0	0
+5	180	4	18	2	100	Attacker_2	3	8	none	0	0	0	0
+6	120	4	12	2	60	Attacker_3	3	9	none	0	0	0	0
+7	200	4	20	3	-1	Attacker_1	3	\N	none	0	0	0	0
+8	240	4	24	3	-1	Attacker_2	3	\N	none	0	0	0	0
+9	160	4	16	3	-1	Attacker_3	3	\N	none	0	0	0	0
 \.
 
 COPY public.block_type FROM stdin;
@@ -187,9 +193,12 @@ COPY public.block_type FROM stdin;
 55	\N	1	mine	0
 56	\N	2	mine	0
 57	\N	3	mine	0
+58	\N	\N	wall	46
+59	\N	\N	wall	47
+60	\N	\N	wall	48
 \.
 
-COPY public.available_blocks FROM stdin;
+COPY public.available_blocks (block_type_id, user_id, attacker_type_id, emp_type_id, category, id) FROM stdin;
 0	1	\N	\N	block	0
 1	1	\N	\N	block	1
 2	1	\N	\N	block	2
@@ -454,6 +463,9 @@ COPY public.level_constraints FROM stdin;
 1	6	55
 1	6	56
 1	6	57
+1	20	58
+1	20	59
+1	20	60
 \.
 
 SELECT pg_catalog.setval('public.user_id_seq', 2, false);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE public.upgrade_job(
+    id serial NOT NULL,
+    user_id INTEGER NOT NULL,
+    item_type VARCHAR(255) NOT NULL,
+    from_id INTEGER NOT NULL,
+    to_id INTEGER NOT NULL,
+    cost INTEGER NOT NULL,
+    started_at TIMESTAMP NOT NULL,
+    finishes_at TIMESTAMP NOT NULL,
+    CONSTRAINT upgrade_job_id_primary PRIMARY KEY (id),
+    CONSTRAINT upgrade_job_fk0 FOREIGN KEY (user_id) REFERENCES public.user(id),
+    CONSTRAINT upgrade_job_item_unique UNIQUE (user_id, item_type, from_id)
+) WITH (
+  OIDS=FALSE
+);
@@ -0,0 +1,30 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE public.upgrade_job
+ALTER COLUMN item_type TYPE VARCHAR(255) USING item_type::text;
+
+DROP TYPE item_kind;
+
+ALTER TABLE public.attacker_type
+DROP CONSTRAINT attacker_type_next_level_fk,
+DROP max_level,
+DROP next_level_id;
+
+ALTER TABLE public.building_type
+DROP CONSTRAINT building_type_next_level_fk,
+DROP max_level,
+DROP next_level_id;
+
+ALTER TABLE public.defender_type
+DROP CONSTRAINT defender_type_next_level_fk,
+DROP max_level,
+DROP next_level_id;
+
+ALTER TABLE public.emp_type
+DROP CONSTRAINT emp_type_next_level_fk,
+DROP max_level,
+DROP next_level_id;
+
+ALTER TABLE public.mine_type
+DROP CONSTRAINT mine_type_next_level_fk,
+DROP max_level,
+DROP next_level_id;
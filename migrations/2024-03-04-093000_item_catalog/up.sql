@@ -0,0 +1,54 @@
+-- Your SQL goes here
+
+--explicit upgrade chains and max level for every item type
+ALTER TABLE public.attacker_type
+ADD max_level INTEGER NOT NULL DEFAULT 1,
+ADD next_level_id INTEGER,
+ADD CONSTRAINT attacker_type_next_level_fk FOREIGN KEY (next_level_id) REFERENCES public.attacker_type(id);
+
+ALTER TABLE public.building_type
+ADD max_level INTEGER NOT NULL DEFAULT 1,
+ADD next_level_id INTEGER,
+ADD CONSTRAINT building_type_next_level_fk FOREIGN KEY (next_level_id) REFERENCES public.building_type(id);
+
+ALTER TABLE public.defender_type
+ADD max_level INTEGER NOT NULL DEFAULT 1,
+ADD next_level_id INTEGER,
+ADD CONSTRAINT defender_type_next_level_fk FOREIGN KEY (next_level_id) REFERENCES public.defender_type(id);
+
+ALTER TABLE public.emp_type
+ADD max_level INTEGER NOT NULL DEFAULT 1,
+ADD next_level_id INTEGER,
+ADD CONSTRAINT emp_type_next_level_fk FOREIGN KEY (next_level_id) REFERENCES public.emp_type(id);
+
+ALTER TABLE public.mine_type
+ADD max_level INTEGER NOT NULL DEFAULT 1,
+ADD next_level_id INTEGER,
+ADD CONSTRAINT mine_type_next_level_fk FOREIGN KEY (next_level_id) REFERENCES public.mine_type(id);
+
+--build the chains from the existing name and level pairs
+UPDATE public.attacker_type AS cur
+SET max_level = (SELECT MAX(other.level) FROM public.attacker_type AS other WHERE other.name = cur.name),
+    next_level_id = (SELECT nxt.id FROM public.attacker_type AS nxt WHERE nxt.name = cur.name AND nxt.level = cur.level + 1);
+
+UPDATE public.building_type AS cur
+SET max_level = (SELECT MAX(other.level) FROM public.building_type AS other WHERE other.name = cur.name),
+    next_level_id = (SELECT nxt.id FROM public.building_type AS nxt WHERE nxt.name = cur.name AND nxt.level = cur.level + 1);
+
+UPDATE public.defender_type AS cur
+SET max_level = (SELECT MAX(other.level) FROM public.defender_type AS other WHERE other.name = cur.name),
+    next_level_id = (SELECT nxt.id FROM public.defender_type AS nxt WHERE nxt.name = cur.name AND nxt.level = cur.level + 1);
+
+UPDATE public.emp_type AS cur
+SET max_level = (SELECT MAX(other.level) FROM public.emp_type AS other WHERE other.name = cur.name),
+    next_level_id = (SELECT nxt.id FROM public.emp_type AS nxt WHERE nxt.name = cur.name AND nxt.level = cur.level + 1);
+
+UPDATE public.mine_type AS cur
+SET max_level = (SELECT MAX(other.level) FROM public.mine_type AS other WHERE other.name = cur.name),
+    next_level_id = (SELECT nxt.id FROM public.mine_type AS nxt WHERE nxt.name = cur.name AND nxt.level = cur.level + 1);
+
+--typed item kinds for upgrade jobs
+CREATE TYPE item_kind AS ENUM ('attacker', 'building', 'defender', 'emp', 'mine');
+
+ALTER TABLE public.upgrade_job
+ALTER COLUMN item_type TYPE item_kind USING item_type::item_kind;
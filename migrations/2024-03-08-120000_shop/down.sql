@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE public.shop_bundle_item;
+DROP TABLE public.shop_bundle;
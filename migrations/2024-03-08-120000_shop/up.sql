@@ -0,0 +1,27 @@
+-- Your SQL goes here
+CREATE TABLE public.shop_bundle(
+    id serial NOT NULL,
+    "name" VARCHAR(255) NOT NULL,
+    cost INTEGER NOT NULL,
+    level_id INTEGER NOT NULL,
+    CONSTRAINT shop_bundle_id_primary PRIMARY KEY (id),
+    CONSTRAINT shop_bundle_fk0 FOREIGN KEY (level_id) REFERENCES public.levels_fixture(id)
+) WITH (
+  OIDS=FALSE
+);
+
+CREATE TABLE public.shop_bundle_item(
+    id serial NOT NULL,
+    bundle_id INTEGER NOT NULL,
+    category item_category NOT NULL,
+    block_type_id INTEGER,
+    attacker_type_id INTEGER,
+    emp_type_id INTEGER,
+    CONSTRAINT shop_bundle_item_id_primary PRIMARY KEY (id),
+    CONSTRAINT shop_bundle_item_fk0 FOREIGN KEY (bundle_id) REFERENCES public.shop_bundle(id),
+    CONSTRAINT shop_bundle_item_fk1 FOREIGN KEY (block_type_id) REFERENCES public.block_type(id),
+    CONSTRAINT shop_bundle_item_fk2 FOREIGN KEY (attacker_type_id) REFERENCES public.attacker_type(id),
+    CONSTRAINT shop_bundle_item_fk3 FOREIGN KEY (emp_type_id) REFERENCES public.emp_type(id)
+) WITH (
+  OIDS=FALSE
+);
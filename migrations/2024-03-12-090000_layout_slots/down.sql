@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX map_layout_active_player_key;
+
+ALTER TABLE public.map_layout
+DROP CONSTRAINT map_layout_player_level_id_name_key,
+DROP "name",
+DROP is_active,
+ADD CONSTRAINT map_layout_level_id_player_key UNIQUE (level_id, player);
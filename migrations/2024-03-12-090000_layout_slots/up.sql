@@ -0,0 +1,12 @@
+-- Your SQL goes here
+ALTER TABLE public.map_layout
+DROP CONSTRAINT map_layout_level_id_player_key,
+ADD "name" VARCHAR(255) NOT NULL DEFAULT 'Main',
+ADD is_active BOOLEAN NOT NULL DEFAULT FALSE,
+ADD CONSTRAINT map_layout_player_level_id_name_key UNIQUE (player, level_id, "name");
+
+--the latest layout of every player is the one being defended
+UPDATE public.map_layout SET is_active = TRUE
+WHERE id IN (SELECT MAX(id) FROM public.map_layout GROUP BY player);
+
+CREATE UNIQUE INDEX map_layout_active_player_key ON public.map_layout (player) WHERE is_active;
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX map_layout_latest_slot_key;
+
+ALTER TABLE public.map_layout
+DROP CONSTRAINT map_layout_player_level_id_name_version_key,
+DROP version,
+DROP is_latest,
+ADD CONSTRAINT map_layout_player_level_id_name_key UNIQUE (player, level_id, "name");
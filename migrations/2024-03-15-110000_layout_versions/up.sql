@@ -0,0 +1,8 @@
+-- Your SQL goes here
+ALTER TABLE public.map_layout
+DROP CONSTRAINT map_layout_player_level_id_name_key,
+ADD version INTEGER NOT NULL DEFAULT 1,
+ADD is_latest BOOLEAN NOT NULL DEFAULT TRUE,
+ADD CONSTRAINT map_layout_player_level_id_name_version_key UNIQUE (player, level_id, "name", version);
+
+CREATE UNIQUE INDEX map_layout_latest_slot_key ON public.map_layout (player, level_id, "name") WHERE is_latest;
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+--fails while wall blocks still exist
+ALTER TYPE block_category RENAME TO block_category_old;
+CREATE TYPE block_category AS ENUM ('defender', 'mine', 'building');
+ALTER TABLE public.block_type
+ALTER COLUMN category TYPE block_category USING category::text::block_category;
+DROP TYPE block_category_old;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+--walls are placed like buildings, their hp and size come from building_type
+ALTER TYPE block_category ADD VALUE IF NOT EXISTS 'wall';
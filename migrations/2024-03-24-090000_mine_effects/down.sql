@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE public.mine_type
+DROP COLUMN effect,
+DROP COLUMN effect_duration;
+
+DROP TYPE mine_effect;
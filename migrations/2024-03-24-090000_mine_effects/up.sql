@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+CREATE TYPE mine_effect AS ENUM ('none', 'slow', 'root', 'disarm');
+
+--effect_duration is counted in frames
+ALTER TABLE public.mine_type
+ADD COLUMN effect mine_effect NOT NULL DEFAULT 'none',
+ADD COLUMN effect_duration INTEGER NOT NULL DEFAULT 0;
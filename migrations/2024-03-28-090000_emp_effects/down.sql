@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE public.emp_type
+ALTER COLUMN att_type TYPE VARCHAR(255) USING name,
+DROP COLUMN disable_duration;
+
+DROP TYPE emp_effect;
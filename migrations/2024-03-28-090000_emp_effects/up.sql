@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+CREATE TYPE emp_effect AS ENUM ('damage', 'disable', 'both');
+
+--att_type only ever held the bomb's name, every existing bomb keeps dealing damage
+--disable_duration is counted in frames
+ALTER TABLE public.emp_type
+ALTER COLUMN att_type TYPE emp_effect USING 'damage'::emp_effect,
+ADD COLUMN disable_duration INTEGER NOT NULL DEFAULT 0;
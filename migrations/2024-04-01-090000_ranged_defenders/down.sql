@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE public.defender_type
+DROP CONSTRAINT defender_type_attack_type_fk,
+DROP COLUMN attack_type_id;
+
+ALTER TABLE public.attack_type
+DROP COLUMN projectile_speed;
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+--projectile_speed is counted in tiles per frame
+ALTER TABLE public.attack_type
+ADD COLUMN projectile_speed INTEGER NOT NULL DEFAULT 4;
+
+--defenders without an attack type have to reach the attacker's tile to hit them
+ALTER TABLE public.defender_type
+ADD COLUMN attack_type_id INTEGER,
+ADD CONSTRAINT defender_type_attack_type_fk FOREIGN KEY (attack_type_id) REFERENCES public.attack_type(id);
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE public.attacker_type
+DROP COLUMN ability,
+DROP COLUMN ability_power,
+DROP COLUMN ability_duration,
+DROP COLUMN ability_cooldown,
+DROP COLUMN ability_charges;
+
+DROP TYPE attacker_ability;
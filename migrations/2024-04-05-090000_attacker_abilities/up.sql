@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TYPE attacker_ability AS ENUM ('none', 'heal', 'shield', 'sprint');
+
+--ability_power is health healed per frame, damage the shield absorbs or extra tiles per frame
+--ability_duration and ability_cooldown are counted in frames, ability_charges per attack
+ALTER TABLE public.attacker_type
+ADD COLUMN ability attacker_ability NOT NULL DEFAULT 'none',
+ADD COLUMN ability_power INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN ability_duration INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN ability_cooldown INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN ability_charges INTEGER NOT NULL DEFAULT 0;
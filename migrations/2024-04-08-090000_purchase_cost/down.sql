@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE public.available_blocks
+DROP COLUMN purchase_cost;
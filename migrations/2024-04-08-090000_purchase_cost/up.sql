@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+--artifacts paid in the shop for the item, items given at sign up cost nothing
+ALTER TABLE public.available_blocks
+ADD COLUMN purchase_cost INTEGER NOT NULL DEFAULT 0;
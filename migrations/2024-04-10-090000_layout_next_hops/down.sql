@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE public.layout_next_hops;
+
+CREATE TABLE public.shortest_path (
+    base_id INTEGER NOT NULL,
+    source_x INTEGER NOT NULL,
+    source_y INTEGER NOT NULL,
+    dest_x INTEGER NOT NULL,
+    dest_y INTEGER NOT NULL,
+    next_hop_x INTEGER NOT NULL,
+    next_hop_y INTEGER NOT NULL,
+    CONSTRAINT ShortestPath_pk PRIMARY KEY (base_id,source_x,source_y,dest_x,dest_y),
+    CONSTRAINT ShortestPath_fk0 FOREIGN KEY (base_id) REFERENCES public.map_layout(id)
+) WITH (
+  OIDS=FALSE
+);
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+--one serialized NextHopMatrix per layout version instead of a row per pair of roads
+DROP TABLE public.shortest_path;
+
+CREATE TABLE public.layout_next_hops (
+    map_id INTEGER NOT NULL,
+    next_hops BYTEA NOT NULL,
+    CONSTRAINT layout_next_hops_pk PRIMARY KEY (map_id),
+    CONSTRAINT layout_next_hops_fk0 FOREIGN KEY (map_id) REFERENCES public.map_layout(id)
+) WITH (
+  OIDS=FALSE
+);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+--written in the same transaction as the spend it guards, so a retry never charges twice
+CREATE TABLE public.idempotency_key (
+    user_id INTEGER NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    scope VARCHAR(255) NOT NULL,
+    response TEXT,
+    created_at TIMESTAMP NOT NULL,
+    CONSTRAINT idempotency_key_pk PRIMARY KEY (user_id, key),
+    CONSTRAINT idempotency_key_fk0 FOREIGN KEY (user_id) REFERENCES public.user(id)
+) WITH (
+  OIDS=FALSE
+);
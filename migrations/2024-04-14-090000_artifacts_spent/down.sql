@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE public.available_blocks
+RENAME COLUMN artifacts_spent TO purchase_cost;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+--artifacts paid for the item in the shop and for every upgrade done since, selling refunds a share of it
+ALTER TABLE public.available_blocks
+RENAME COLUMN purchase_cost TO artifacts_spent;
@@ -1,18 +1,24 @@
-use self::util::{get_valid_road_paths, AttackResponse, GameLog, ResultResponse};
-use super::auth::session::AuthUser;
-use super::defense::shortest_path::run_shortest_paths;
+use self::util::{get_valid_road_paths, AttackQuery, AttackResponse, GameLog, ResultResponse};
+use super::auth::session::{authenticate_token, AuthUser};
+use super::defense::shortest_path::{get_shortest_paths, NextHopMatrix};
 use super::defense::util::{
-    AttackBaseResponse, DefenseResponse, MineTypeResponseWithoutBlockId, SimulationBaseResponse,
+    fetch_attack_map_spaces, AttackBaseResponse, DefenseResponse, MineTypeResponseWithoutBlockId,
+    SimulationBaseResponse,
 };
 use super::user::util::fetch_user;
 use super::{error, PgPool, RedisPool};
-use crate::api::attack::socket::{BuildingResponse, ResultType, SocketRequest, SocketResponse};
+use crate::api::attack::socket::{
+    BuildingResponse, ResultType, SocketCloseCode, SocketFrame, SocketProtocol, SocketQuery,
+    SocketResponse, SOCKET_PROTOCOL,
+};
 use crate::api::util::HistoryboardQuery;
 use crate::constants::{GAME_AGE_IN_MINUTES, MAX_BOMBS_PER_ATTACK};
 use crate::models::{AttackerType, User};
+use crate::validator::fog::{BlockFootprint, FogOfWar};
+use crate::validator::pathfinding::{DefenderPathfinder, TerrainGrid};
+use crate::validator::placement::{spawn_zone, Placement};
 use crate::validator::state::State;
 use crate::validator::util::{BombType, BuildingDetails, DefenderDetails, MineDetails};
-use crate::validator::util::{Coords, SourceDestXY};
 use actix_rt;
 use actix_web::error::ErrorBadRequest;
 use actix_web::web::{Data, Json};
@@ -22,7 +28,9 @@ use std::collections::{HashMap, HashSet};
 use std::time;
 
 use crate::validator::game_handler;
-use actix_ws::Message;
+use actix_session::SessionExt;
+use actix_web::http::header::{self, HeaderValue};
+use actix_ws::{CloseCode, CloseReason, Closed, Message, MessageStream, Session};
 use futures_util::stream::StreamExt;
 
 mod rating;
@@ -39,9 +47,11 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
 async fn init_attack(
     pool: web::Data<PgPool>,
     redis_pool: Data<RedisPool>,
+    query: web::Query<AttackQuery>,
     user: AuthUser,
 ) -> Result<impl Responder> {
     let attacker_id = user.0;
+    let fog_of_war = query.fog_of_war;
 
     log::info!("Attacker:{} is trying to initiate an attack", attacker_id);
     let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
@@ -99,6 +109,7 @@ async fn init_attack(
             opponent_id,
             &mut conn,
             attacker_id,
+            fog_of_war,
         )?) as anyhow::Result<(i32, DefenseResponse)>
     })
     .await?
@@ -122,6 +133,14 @@ async fn init_attack(
 
     let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
 
+    let roads = web::block(move || {
+        Ok(get_valid_road_paths(map_id, &mut conn)?) as anyhow::Result<HashSet<(i32, i32)>>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+
     let user_details =
         web::block(move || Ok(fetch_user(&mut conn, opponent_id)?) as anyhow::Result<Option<User>>)
             .await?
@@ -144,9 +163,22 @@ async fn init_attack(
         opponent_id
     );
 
-    //Generate attack token to validate the /attack/start
-    let attack_token = util::encode_attack_token(attacker_id, opponent_id, game_id)
+    //Generate a one time attack token, bound to the attacker's device, to validate the /attack/start
+    let mut redis_conn = redis_pool
+        .get()
         .map_err(|err| error::handle_error(err.into()))?;
+    let device = util::get_session_device(attacker_id, &mut redis_conn)
+        .map_err(|err| error::handle_error(err.into()))?
+        .ok_or_else(|| ErrorBadRequest("Session not found"))?;
+    let attack_token = util::encode_attack_token(
+        attacker_id,
+        opponent_id,
+        game_id,
+        device,
+        fog_of_war,
+        &mut redis_conn,
+    )
+    .map_err(|err| error::handle_error(err.into()))?;
     let response: AttackResponse = AttackResponse {
         user: user_details,
         max_bombs: MAX_BOMBS_PER_ATTACK,
@@ -164,6 +196,8 @@ async fn init_attack(
                     cost: mine_type.cost,
                     level: mine_type.level,
                     radius: mine_type.radius,
+                    effect: mine_type.effect,
+                    effect_duration: mine_type.effect_duration,
                 })
                 .collect(),
         },
@@ -173,6 +207,7 @@ async fn init_attack(
         attacker_types: opponent_base.attacker_types,
         bomb_types: opponent_base.bomb_types,
         game_id,
+        spawn_zone: spawn_zone(&roads).into_iter().collect(),
     };
 
     log::info!(
@@ -183,21 +218,111 @@ async fn init_attack(
     Ok(Json(response))
 }
 
+//upgrades the connection, agreeing on SOCKET_PROTOCOL when the client offered it
+fn upgrade_socket(
+    req: &HttpRequest,
+    body: web::Payload,
+) -> Result<(HttpResponse, Session, MessageStream), Error> {
+    let (mut response, session, msg_stream) = actix_ws::handle(req, body)?;
+    if util::get_offered_protocols(req)
+        .iter()
+        .any(|protocol| protocol == SOCKET_PROTOCOL)
+    {
+        response.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(SOCKET_PROTOCOL),
+        );
+    }
+    Ok((response, session, msg_stream))
+}
+
+async fn send_frame(session: &mut Session, frame: SocketFrame) -> Result<(), Closed> {
+    match frame {
+        SocketFrame::Text(text) => session.text(text).await,
+        SocketFrame::Binary(bytes) => session.binary(bytes).await,
+    }
+}
+
+//upgrades only to close right away, browsers can read a close code but not a failed handshake
+fn reject_socket(
+    req: &HttpRequest,
+    body: web::Payload,
+    code: SocketCloseCode,
+    description: &str,
+) -> Result<HttpResponse, Error> {
+    let (response, session, _) = upgrade_socket(req, body)?;
+    let reason = CloseReason {
+        code: CloseCode::Other(code as u16),
+        description: Some(description.to_string()),
+    };
+    actix_rt::spawn(async move {
+        if session.close(Some(reason)).await.is_err() {
+            log::info!("Error closing a rejected socket connection");
+        }
+    });
+    Ok(response)
+}
+
 async fn socket_handler(
     pool: web::Data<PgPool>,
     redis_pool: Data<RedisPool>,
     req: HttpRequest,
     body: web::Payload,
 ) -> Result<HttpResponse, Error> {
-    let query_params = req.query_string().split('&').collect::<Vec<&str>>();
-    let user_token = query_params[0].split('=').collect::<Vec<&str>>()[1];
-    let attack_token = query_params[1].split('=').collect::<Vec<&str>>()[1];
-
-    let attacker_id =
-        util::decode_user_token(user_token).map_err(|err| error::handle_error(err.into()))?;
-    let attack_token_data =
-        util::decode_attack_token(attack_token).map_err(|err| error::handle_error(err.into()))?;
+    //a malformed query is treated like missing tokens
+    let query = web::Query::<SocketQuery>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default();
+    let protocol = SocketProtocol::from_query(&query);
+    let (user_token, attack_token) = util::get_handshake_tokens(&req, query);
+    //browsers that can't pass the user token fall back to the session cookie
+    let user_token = user_token.or_else(|| req.get_session().get::<String>("token").ok().flatten());
+
+    let mut redis_conn = redis_pool
+        .get()
+        .map_err(|err| error::handle_error(err.into()))?;
+
+    let protocol = match protocol {
+        Some(protocol) => protocol,
+        None => {
+            log::info!("Socket handshake with an unsupported protocol");
+            return reject_socket(
+                &req,
+                body,
+                SocketCloseCode::UnsupportedProtocol,
+                "Unsupported protocol version or encoding",
+            );
+        }
+    };
+
+    let user_token_data = match user_token.map(|token| authenticate_token(&token, &mut redis_conn))
+    {
+        Some(Ok(claims)) => claims,
+        _ => {
+            log::info!("Socket handshake with an invalid user token");
+            return reject_socket(
+                &req,
+                body,
+                SocketCloseCode::AuthFailed,
+                "User not authorised",
+            );
+        }
+    };
+    let attacker_id = user_token_data.id;
+    let attack_token_data = match attack_token.map(|token| util::decode_attack_token(&token)) {
+        Some(Ok(attack_token_data)) => attack_token_data,
+        _ => {
+            log::info!("Attacker:{} sent an invalid attack token", attacker_id);
+            return reject_socket(
+                &req,
+                body,
+                SocketCloseCode::AuthFailed,
+                "Invalid attack token",
+            );
+        }
+    };
     let game_id = attack_token_data.game_id;
+    let fog_of_war = attack_token_data.fog_of_war;
 
     log::info!(
         "Attacker:{} is trying to start an attack with game:{}",
@@ -213,27 +338,58 @@ async fn socket_handler(
             attacker_id,
             game_id
         );
-        return Err(ErrorBadRequest("User not authorised"));
+        return reject_socket(
+            &req,
+            body,
+            SocketCloseCode::AuthFailed,
+            "User not authorised",
+        );
+    }
+
+    //the token only works from the session that started the attack
+    if user_token_data.device != attack_token_data.device {
+        log::info!(
+            "Attacker:{} is trying to start game:{} from another device",
+            attacker_id,
+            game_id
+        );
+        return reject_socket(
+            &req,
+            body,
+            SocketCloseCode::AuthFailed,
+            "User not authorised",
+        );
     }
 
     let defender_id = attack_token_data.defender_id;
     if attacker_id == defender_id {
         log::info!("Attacker:{} is trying to attack himself", attacker_id);
-        return Err(ErrorBadRequest("Can't attack yourself"));
+        return reject_socket(
+            &req,
+            body,
+            SocketCloseCode::AuthFailed,
+            "Can't attack yourself",
+        );
     }
 
-    let mut redis_conn = redis_pool
-        .get()
-        .map_err(|err| error::handle_error(err.into()))?;
-
     if let Ok(Some(_)) = util::get_game_id_from_redis(attacker_id, &mut redis_conn, true) {
         log::info!("Attacker:{} has an ongoing game", attacker_id);
-        return Err(ErrorBadRequest("Attacker has an ongoing game"));
+        return reject_socket(
+            &req,
+            body,
+            SocketCloseCode::AttackerBusy,
+            "Attacker has an ongoing game",
+        );
     }
 
     if let Ok(Some(_)) = util::get_game_id_from_redis(defender_id, &mut redis_conn, false) {
         log::info!("Defender:{} has an ongoing game", defender_id);
-        return Err(ErrorBadRequest("Defender has an ongoing game"));
+        return reject_socket(
+            &req,
+            body,
+            SocketCloseCode::DefenderBusy,
+            "Defender has an ongoing game",
+        );
     }
 
     if util::check_and_remove_incomplete_game(&attacker_id, &defender_id, &game_id, &mut conn)
@@ -266,13 +422,13 @@ async fn socket_handler(
     let map_id = if let Some(map) = map {
         map
     } else {
-        return Err(ErrorBadRequest("Invalid base"));
+        return reject_socket(&req, body, SocketCloseCode::GameNotFound, "Invalid base");
     };
 
     let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
 
     let shortest_paths = web::block(move || {
-        Ok(run_shortest_paths(&mut conn, map_id)?) as anyhow::Result<HashMap<SourceDestXY, Coords>>
+        Ok(get_shortest_paths(&mut conn, map_id)?) as anyhow::Result<NextHopMatrix>
     })
     .await?
     .map_err(|err| error::handle_error(err.into()))?;
@@ -306,6 +462,19 @@ async fn socket_handler(
     .await?
     .map_err(|err| error::handle_error(err.into()))?;
 
+    //the attacker starts out seeing only the blocks init_attack sent them
+    let fog = if fog_of_war {
+        let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+        let footprints = web::block(move || {
+            Ok(fetch_attack_map_spaces(&mut conn, map_id)?) as anyhow::Result<Vec<BlockFootprint>>
+        })
+        .await?
+        .map_err(|err| error::handle_error(err.into()))?;
+        Some(FogOfWar::new(footprints).0)
+    } else {
+        None
+    };
+
     let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
     let bomb_types =
         web::block(move || Ok(util::get_bomb_types(&mut conn)?) as anyhow::Result<Vec<BombType>>)
@@ -319,6 +488,13 @@ async fn socket_handler(
     .await?
     .map_err(|err| error::handle_error(err.into()))?;
 
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let owned_attackers = web::block(move || {
+        Ok(util::get_owned_attacker_types(&mut conn, attacker_id)?) as anyhow::Result<HashSet<i32>>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
     let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
 
     let attacker_user_details =
@@ -345,18 +521,46 @@ async fn socket_handler(
     .map_err(|err| error::handle_error(err.into()))?;
 
     if attacker_user_details.is_none() || defender_user_details.is_none() {
-        return Err(ErrorBadRequest("User details not found"));
+        return reject_socket(
+            &req,
+            body,
+            SocketCloseCode::GameNotFound,
+            "User details not found",
+        );
     }
 
-    let redis_conn = redis_pool
+    let mut redis_conn = redis_pool
         .get()
         .map_err(|err| error::handle_error(err.into()))?;
 
+    match util::consume_attack_token(&attack_token_data.jti, &mut redis_conn) {
+        Ok(true) => {}
+        Ok(false) => {
+            log::info!(
+                "Attack token for game:{} has already been used or has expired",
+                game_id
+            );
+            return reject_socket(
+                &req,
+                body,
+                SocketCloseCode::AuthFailed,
+                "Attack token already used",
+            );
+        }
+        Err(err) => return Err(error::handle_error(err.into())),
+    }
+
     if util::add_game_id_to_redis(attacker_id, defender_id, game_id, redis_conn).is_err() {
         println!("Cannot add game:{} to redis", game_id);
         return Err(ErrorBadRequest("Internal Server Error"));
     }
 
+    let placement = Placement::new(&roads, owned_attackers);
+    let mut pathfinder = DefenderPathfinder {
+        terrain: TerrainGrid::new(&roads, &buildings, &defenders, &mines),
+        road_paths: shortest_paths,
+    };
+
     let mut damaged_buildings: Vec<BuildingResponse> = Vec::new();
 
     let game_log = GameLog {
@@ -384,7 +588,7 @@ async fn socket_handler(
         defender_id
     );
 
-    let (response, session, mut msg_stream) = actix_ws::handle(&req, body)?;
+    let (response, session, mut msg_stream) = upgrade_socket(&req, body)?;
 
     log::info!(
         "Socket connection established for Game:{}, Attacker:{} and Defender:{}",
@@ -399,6 +603,10 @@ async fn socket_handler(
     actix_rt::spawn(async move {
         let mut game_state = State::new(attacker_id, defender_id, defenders, mines, buildings);
         game_state.set_total_hp_buildings();
+        if let Some(fog) = fog {
+            game_state.set_fog_of_war(fog);
+        }
+        game_state.set_placement(placement);
 
         let game_logs = &mut game_log.clone();
 
@@ -413,7 +621,7 @@ async fn socket_handler(
             .map_err(|err| error::handle_error(err.into()))
             .unwrap();
 
-        let shortest_path = &shortest_paths.clone();
+        let shortest_path = &mut pathfinder;
         let roads = &roads.clone();
         let bomb_types = &bomb_types.clone();
         let attacker_type = &attacker_type.clone();
@@ -432,8 +640,8 @@ async fn socket_handler(
                         return;
                     }
                 }
-                Message::Text(s) => {
-                    if let Ok(socket_request) = serde_json::from_str::<SocketRequest>(&s) {
+                Message::Text(_) | Message::Binary(_) => {
+                    if let Ok(socket_request) = protocol.decode_request(&msg) {
                         let response_result = game_handler(
                             attacker_type,
                             socket_request,
@@ -445,10 +653,9 @@ async fn socket_handler(
                         );
                         match response_result {
                             Some(Ok(response)) => {
-                                if let Ok(response_json) = serde_json::to_string(&response) {
-                                    // println!("Response Json ---- {}", response_json);
+                                if let Ok(frame) = protocol.encode_response(&response) {
                                     if response.result_type == ResultType::GameOver {
-                                        if session_clone1.text(response_json).await.is_err() {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
                                             return;
                                         }
                                         if (session_clone1.clone().close(None).await).is_err() {
@@ -465,16 +672,16 @@ async fn socket_handler(
                                             log::info!("Error terminating the game 1 for game:{} and attacker:{} and opponent:{}", game_id, attacker_id, defender_id);
                                         }
                                     } else if response.result_type == ResultType::MinesExploded {
-                                        if session_clone1.text(response_json).await.is_err() {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
                                             return;
                                         }
                                     } else if response.result_type == ResultType::DefendersDamaged {
-                                        if session_clone1.text(response_json).await.is_err() {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
                                             return;
                                         }
                                     } else if response.result_type == ResultType::DefendersTriggered
                                     {
-                                        if session_clone1.text(response_json).await.is_err() {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
                                             return;
                                         }
                                     } else if response.result_type == ResultType::BuildingsDamaged {
@@ -488,15 +695,27 @@ async fn socket_handler(
                                         // {
                                         //     log::info!("Failed to deduct artifacts from building for game:{} and attacker:{} and opponent:{}", game_id, attacker_id, defender_id);
                                         // }
-                                        if session_clone1.text(response_json).await.is_err() {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
                                             return;
                                         }
                                     } else if response.result_type == ResultType::PlacedAttacker {
-                                        if session_clone1.text(response_json).await.is_err() {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
+                                            return;
+                                        }
+                                    } else if response.result_type == ResultType::TilesRevealed {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
+                                            return;
+                                        }
+                                    } else if response.result_type == ResultType::TargetsDisabled {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
+                                            return;
+                                        }
+                                    } else if response.result_type == ResultType::AbilityUsed {
+                                        if send_frame(&mut session_clone1, frame).await.is_err() {
                                             return;
                                         }
                                     } else if response.result_type == ResultType::Nothing
-                                        && session_clone1.text(response_json).await.is_err()
+                                        && send_frame(&mut session_clone1, frame).await.is_err()
                                     {
                                         return;
                                     }
@@ -519,13 +738,13 @@ async fn socket_handler(
                         }
                     } else {
                         log::info!(
-                            "Error parsing JSON for game:{} and attacker:{} and opponent:{}",
+                            "Error parsing request for game:{} and attacker:{} and opponent:{}",
                             game_id,
                             attacker_id,
                             defender_id
                         );
 
-                        if session_clone1.text("Error parsing JSON").await.is_err() {
+                        if session_clone1.text("Error parsing request").await.is_err() {
                             return;
                         }
                     }
@@ -577,21 +796,26 @@ async fn socket_handler(
                     defender_id
                 );
 
-                let response_json = serde_json::to_string(&SocketResponse {
-                    frame_number: 0,
-                    result_type: ResultType::GameOver,
-                    is_alive: None,
-                    attacker_health: None,
-                    exploded_mines: None,
-                    defender_damaged: None,
-                    damaged_buildings: None,
-                    total_damage_percentage: None,
-                    is_sync: false,
-                    is_game_over: true,
-                    message: Some("Connection timed out".to_string()),
-                })
-                .unwrap();
-                if session_clone2.text(response_json).await.is_err() {
+                let frame = protocol
+                    .encode_response(&SocketResponse {
+                        frame_number: 0,
+                        result_type: ResultType::GameOver,
+                        is_alive: None,
+                        attacker_health: None,
+                        exploded_mines: None,
+                        defender_damaged: None,
+                        damaged_buildings: None,
+                        revealed_blocks: None,
+                        squad: None,
+                        stunned_defenders: None,
+                        disarmed_mines: None,
+                        total_damage_percentage: None,
+                        is_sync: false,
+                        is_game_over: true,
+                        message: Some("Connection timed out".to_string()),
+                    })
+                    .unwrap();
+                if send_frame(&mut session_clone2, frame).await.is_err() {
                     return;
                 }
 
@@ -1,11 +1,192 @@
+use actix_ws::Message;
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
 // use crate::validator::util::Coords;
+use super::util::Direction;
 use crate::{
+    models::MapSpaces,
     validator::util::Coords,
-    validator::util::{Attacker, BombType, BuildingDetails, DefenderDetails, MineDetails},
+    validator::util::{AbilityState, Attacker, BuildingDetails, DefenderDetails, MineDetails},
 };
 
+//subprotocol a client offers when it passes its tokens as subprotocols,
+//as "aot, user-token.<token>, attack-token.<token>"
+pub const SOCKET_PROTOCOL: &str = "aot";
+pub const USER_TOKEN_PROTOCOL_PREFIX: &str = "user-token.";
+pub const ATTACK_TOKEN_PROTOCOL_PREFIX: &str = "attack-token.";
+
+#[derive(Deserialize, Debug, Default)]
+pub struct SocketQuery {
+    pub user_token: Option<String>,
+    pub attack_token: Option<String>,
+    pub version: Option<u8>,
+    pub encoding: Option<String>,
+}
+
+//full paths in every request
+pub const SOCKET_PROTOCOL_VERSION_FULL: u8 = 1;
+//paths as direction codes, responses without empty fields
+pub const SOCKET_PROTOCOL_VERSION_COMPACT: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SocketEncoding {
+    Json,
+    MessagePack,
+}
+
+//what a client negotiated on connect, old clients get version 1 over json
+#[derive(Debug, Clone, Copy)]
+pub struct SocketProtocol {
+    pub version: u8,
+    pub encoding: SocketEncoding,
+}
+
+pub enum SocketFrame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl SocketProtocol {
+    pub fn from_query(query: &SocketQuery) -> Option<SocketProtocol> {
+        let version = query.version.unwrap_or(SOCKET_PROTOCOL_VERSION_FULL);
+        if !(SOCKET_PROTOCOL_VERSION_FULL..=SOCKET_PROTOCOL_VERSION_COMPACT).contains(&version) {
+            return None;
+        }
+        let encoding = match query.encoding.as_deref() {
+            None | Some("json") => SocketEncoding::Json,
+            Some("msgpack") => SocketEncoding::MessagePack,
+            Some(_) => return None,
+        };
+        Some(SocketProtocol { version, encoding })
+    }
+
+    //text frames are read as json and binary frames as messagepack
+    pub fn decode_request(&self, msg: &Message) -> Result<SocketRequest> {
+        let compact = self.version >= SOCKET_PROTOCOL_VERSION_COMPACT;
+        match msg {
+            Message::Text(text) if compact => {
+                serde_json::from_str::<CompactSocketRequest>(text)?.into_socket_request()
+            }
+            Message::Text(text) => Ok(serde_json::from_str(text)?),
+            Message::Binary(bytes) if compact => {
+                rmp_serde::from_slice::<CompactSocketRequest>(bytes)?.into_socket_request()
+            }
+            Message::Binary(bytes) => Ok(rmp_serde::from_slice(bytes)?),
+            _ => Err(anyhow::anyhow!("Unsupported socket message")),
+        }
+    }
+
+    pub fn encode_response(&self, response: &SocketResponse) -> Result<SocketFrame> {
+        let mut value = serde_json::to_value(response)?;
+        if self.version >= SOCKET_PROTOCOL_VERSION_COMPACT {
+            if let serde_json::Value::Object(fields) = &mut value {
+                fields.retain(|_, field| !field.is_null());
+            }
+        }
+
+        Ok(match self.encoding {
+            SocketEncoding::Json => SocketFrame::Text(serde_json::to_string(&value)?),
+            SocketEncoding::MessagePack => SocketFrame::Binary(rmp_serde::to_vec_named(&value)?),
+        })
+    }
+}
+
+//a request whose path is the start position followed by one direction code per step
+#[derive(Deserialize, Debug)]
+pub struct CompactSocketRequest {
+    pub frame_number: i32,
+    pub action_type: ActionType,
+    #[serde(default)]
+    pub attacker_id: Option<i32>,
+    #[serde(default)]
+    pub bomb_id: Option<i32>,
+    #[serde(default)]
+    pub start_position: Option<Coords>,
+    #[serde(default)]
+    pub path: Vec<u8>,
+    #[serde(default)]
+    pub bomb_position: Option<Coords>,
+    #[serde(default)]
+    pub is_game_over: Option<bool>,
+    #[serde(default)]
+    pub unit_id: Option<i32>,
+    #[serde(default)]
+    pub unit_paths: Vec<CompactUnitPath>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CompactUnitPath {
+    pub unit_id: i32,
+    pub start_position: Coords,
+    #[serde(default)]
+    pub path: Vec<u8>,
+}
+
+//the start position followed by the tile reached after every step
+fn expand_path(start_position: Coords, codes: &[u8]) -> Result<Vec<Coords>> {
+    let mut path = vec![start_position];
+    let mut position = start_position;
+    for &code in codes {
+        let direction = Direction::from_code(code)
+            .ok_or_else(|| anyhow::anyhow!("Invalid direction code {code}"))?;
+        position = direction.step(position);
+        path.push(position);
+    }
+    Ok(path)
+}
+
+impl CompactSocketRequest {
+    pub fn into_socket_request(self) -> Result<SocketRequest> {
+        let attacker_path = match self.start_position {
+            Some(start_position) => expand_path(start_position, &self.path)?,
+            None if !self.path.is_empty() => {
+                return Err(anyhow::anyhow!("Path without a start position"))
+            }
+            None => Vec::new(),
+        };
+        let unit_paths = self
+            .unit_paths
+            .iter()
+            .map(|unit_path| {
+                Ok(UnitPath {
+                    unit_id: unit_path.unit_id,
+                    path: expand_path(unit_path.start_position, &unit_path.path)?,
+                })
+            })
+            .collect::<Result<Vec<UnitPath>>>()?;
+
+        Ok(SocketRequest {
+            frame_number: self.frame_number,
+            action_type: self.action_type,
+            attacker_id: self.attacker_id,
+            bomb_id: self.bomb_id,
+            start_position: self.start_position,
+            attacker_path,
+            //an off map position that never matches a placed bomb
+            bomb_position: self.bomb_position.unwrap_or(Coords { x: -1, y: -1 }),
+            is_game_over: self.is_game_over,
+            unit_id: self.unit_id,
+            unit_paths,
+        })
+    }
+}
+
+/// Close codes sent when an attack socket is rejected after the upgrade
+/// - 4001: the user or attack token is missing, invalid, expired or already used
+/// - 4002: the attacker already has a game in progress
+/// - 4003: the defender is being attacked by someone else
+/// - 4004: the game, the defender's base or one of the players no longer exists
+/// - 4005: the requested protocol version or encoding is not supported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SocketCloseCode {
+    AuthFailed = 4001,
+    AttackerBusy = 4002,
+    DefenderBusy = 4003,
+    GameNotFound = 4004,
+    UnsupportedProtocol = 4005,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SocketRequest {
     pub frame_number: i32,
@@ -16,6 +197,18 @@ pub struct SocketRequest {
     pub attacker_path: Vec<Coords>,
     pub bomb_position: Coords,
     pub is_game_over: Option<bool>,
+    //the squad unit acting, the last one placed when missing
+    #[serde(default)]
+    pub unit_id: Option<i32>,
+    //moves several units in one frame, attacker_path moves just the acting unit
+    #[serde(default)]
+    pub unit_paths: Vec<UnitPath>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnitPath {
+    pub unit_id: i32,
+    pub path: Vec<Coords>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -28,6 +221,13 @@ pub struct SocketResponse {
     // pub triggered_defenders: Option<Vec<DefenderResponse>>,
     pub defender_damaged: Option<Vec<DefenderResponse>>,
     pub damaged_buildings: Option<Vec<BuildingResponse>>,
+    //blocks uncovered from the fog of war this frame
+    pub revealed_blocks: Option<Vec<MapSpaces>>,
+    //every unit placed so far, dead ones included
+    pub squad: Option<Vec<UnitResponse>>,
+    //defenders frozen and mines disarmed by an emp this frame
+    pub stunned_defenders: Option<Vec<DisabledResponse>>,
+    pub disarmed_mines: Option<Vec<DisabledResponse>>,
     pub total_damage_percentage: Option<f32>,
     pub is_sync: bool,
     // pub state: Option<GameStateResponse>,
@@ -44,6 +244,8 @@ pub enum ActionType {
     Idle,
     Terminate,
     SelfDestruct,
+    Scout,
+    UseAbility,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -54,6 +256,9 @@ pub enum ResultType {
     BuildingsDamaged,
     GameOver,
     PlacedAttacker,
+    TilesRevealed,
+    TargetsDisabled,
+    AbilityUsed,
     Nothing,
 }
 
@@ -70,6 +275,28 @@ pub struct DefenderResponse {
     pub id: i32,
     pub position: Coords,
     pub damage: i32,
+    pub unit_id: i32,
+    //hit by a projectile, the defender is still alive
+    pub ranged: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct DisabledResponse {
+    pub id: i32,
+    pub position: Coords,
+    pub frames: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct UnitResponse {
+    pub unit_id: i32,
+    pub attacker_id: i32,
+    pub position: Coords,
+    pub health: i32,
+    pub bomb_count: i32,
+    pub bomb_id: Option<i32>,
+    pub is_alive: bool,
+    pub ability: AbilityState,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -91,9 +318,8 @@ pub struct GameStateResponse {
     pub frame_no: i32,
     pub attacker_user_id: i32,
     pub defender_user_id: i32,
-    pub attacker: Option<Attacker>,
+    pub attackers: Vec<Attacker>,
     pub attacker_death_count: i32,
-    pub bombs: BombType,
     pub damage_percentage: f32,
     pub artifacts: i32,
     pub defenders: Vec<DefenderDetails>,
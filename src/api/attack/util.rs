@@ -1,5 +1,4 @@
 use crate::api::attack::rating::new_rating;
-use crate::api::auth::TokenClaims;
 use crate::api::defense::util::{
     fetch_map_layout, get_map_details_for_attack, get_map_details_for_simulation,
     AttackBaseResponse, DefenseResponse, SimulationBaseResponse,
@@ -15,25 +14,36 @@ use crate::api::{self, RedisConn};
 use crate::constants::*;
 use crate::error::DieselError;
 use crate::models::{
-    Artifact, AttackerType, AvailableBlocks, BlockCategory, BlockType, BuildingType, DefenderType,
-    EmpType, Game, LevelsFixture, MapLayout, MapSpaces, MineType, NewAttackerPath, NewGame, User,
+    Artifact, AttackType, AttackerType, AvailableBlocks, BlockCategory, BlockType, BuildingType,
+    DefenderType, EmpType, Game, ItemCategory, LevelsFixture, MapLayout, MapSpaces, MineType,
+    NewAttackerPath, NewGame, User,
 };
 use crate::schema::user;
 use crate::util::function;
 use crate::validator::util::Coords;
-use crate::validator::util::{BombType, BuildingDetails, DefenderDetails, MineDetails};
+use crate::validator::util::{
+    BombType, BuildingDetails, DefenderDetails, MineDetails, RangedAttack,
+};
 use ::serde::{Deserialize, Serialize};
+use actix_web::http::header;
+use actix_web::HttpRequest;
 use anyhow::Result;
 use chrono;
 use diesel::prelude::*;
 use diesel::PgConnection;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use rand::distributions::Alphanumeric;
 use rand::seq::IteratorRandom;
+use rand::Rng;
 use redis::Commands;
 use std::collections::{HashMap, HashSet};
 use std::env;
 
-use super::socket::BuildingResponse;
+use super::socket::{
+    BuildingResponse, SocketQuery, ATTACK_TOKEN_PROTOCOL_PREFIX, USER_TOKEN_PROTOCOL_PREFIX,
+};
 
 #[derive(Debug, Serialize)]
 pub struct DefensePosition {
@@ -57,12 +67,23 @@ pub struct NewAttacker {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AttackToken {
+    pub jti: String,
     pub game_id: i32,
     pub attacker_id: i32,
     pub defender_id: i32,
+    //device of the session that started the attack
+    pub device: String,
+    #[serde(default)]
+    pub fog_of_war: bool,
     pub iat: usize,
     pub exp: usize,
 }
+
+#[derive(Deserialize)]
+pub struct AttackQuery {
+    #[serde(default)]
+    pub fog_of_war: bool,
+}
 #[derive(Serialize, Clone, Debug)]
 pub enum Direction {
     Up,
@@ -71,14 +92,50 @@ pub enum Direction {
     Right,
 }
 
+impl Direction {
+    //direction codes of compact socket paths
+    pub fn from_code(code: u8) -> Option<Direction> {
+        match code {
+            0 => Some(Direction::Up),
+            1 => Some(Direction::Down),
+            2 => Some(Direction::Left),
+            3 => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    pub fn step(&self, from: Coords) -> Coords {
+        match self {
+            Direction::Up => Coords {
+                x: from.x - 1,
+                y: from.y,
+            },
+            Direction::Down => Coords {
+                x: from.x + 1,
+                y: from.y,
+            },
+            Direction::Left => Coords {
+                x: from.x,
+                y: from.y + 1,
+            },
+            Direction::Right => Coords {
+                x: from.x,
+                y: from.y - 1,
+            },
+        }
+    }
+}
+
 #[derive(Serialize, Clone, Debug)]
 pub struct EventResponse {
     // pub attacker_initial_position: Option<Coords>,
     pub attacker_id: Option<i32>,
+    pub unit_id: Option<i32>,
     pub bomb_id: Option<i32>,
     pub coords: Coords,
     pub direction: Direction,
     pub is_bomb: bool,
+    pub is_ability: bool,
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -107,6 +164,7 @@ pub fn get_map_id(defender_id: &i32, conn: &mut PgConnection) -> Result<Option<i
     use crate::schema::map_layout;
     let map_id = map_layout::table
         .filter(map_layout::player.eq(defender_id))
+        .filter(map_layout::is_active.eq(true))
         .filter(map_layout::is_valid.eq(true))
         .select(map_layout::id)
         .first::<i32>(conn)
@@ -276,12 +334,37 @@ pub fn get_attacker_types(conn: &mut PgConnection) -> Result<HashMap<i32, Attack
                     amt_of_emps: attacker.amt_of_emps,
                     level: attacker.level,
                     cost: attacker.cost,
+                    max_level: attacker.max_level,
+                    next_level_id: attacker.next_level_id,
+                    ability: attacker.ability,
+                    ability_power: attacker.ability_power,
+                    ability_duration: attacker.ability_duration,
+                    ability_cooldown: attacker.ability_cooldown,
+                    ability_charges: attacker.ability_charges,
                 },
             )
         })
         .collect::<HashMap<i32, AttackerType>>())
 }
 
+//attacker types in the user's inventory
+pub fn get_owned_attacker_types(conn: &mut PgConnection, user_id: i32) -> Result<HashSet<i32>> {
+    use crate::schema::available_blocks;
+    Ok(available_blocks::table
+        .filter(available_blocks::user_id.eq(user_id))
+        .filter(available_blocks::category.eq(ItemCategory::Attacker))
+        .filter(available_blocks::attacker_type_id.is_not_null())
+        .select(available_blocks::attacker_type_id.assume_not_null())
+        .load::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "available_blocks",
+            function: function!(),
+            error: err,
+        })?
+        .into_iter()
+        .collect())
+}
+
 #[derive(Serialize)]
 pub struct ShortestPathResponse {
     pub source: Coords,
@@ -300,6 +383,7 @@ pub struct AttackResponse {
     pub obtainable_artifacts: i32,
     pub attack_token: String,
     pub game_id: i32,
+    pub spawn_zone: Vec<Coords>,
 }
 
 pub fn get_random_opponent_id(
@@ -396,11 +480,12 @@ pub fn get_opponent_base_details_for_attack(
     defender_id: i32,
     conn: &mut PgConnection,
     attacker_id: i32,
+    fog_of_war: bool,
 ) -> Result<(i32, DefenseResponse)> {
     let map = fetch_map_layout(conn, &defender_id)?;
     let map_id = map.id;
 
-    let response = get_map_details_for_attack(conn, map, attacker_id)?;
+    let response = get_map_details_for_attack(conn, map, attacker_id, fog_of_war)?;
 
     Ok((map_id, response))
 }
@@ -423,7 +508,7 @@ pub fn add_game_id_to_redis(
     mut redis_conn: RedisConn,
 ) -> Result<()> {
     redis_conn
-        .set_ex(
+        .set_ex::<_, _, ()>(
             format!("Attacker:{}", attacker_id),
             game_id,
             GAME_AGE_IN_MINUTES * 60,
@@ -431,7 +516,7 @@ pub fn add_game_id_to_redis(
         .map_err(|err| anyhow::anyhow!("Failed to set attacker key: {}", err))?;
 
     redis_conn
-        .set_ex(
+        .set_ex::<_, _, ()>(
             format!("Defender:{}", defender_id),
             game_id,
             GAME_AGE_IN_MINUTES * 60,
@@ -465,32 +550,64 @@ pub fn delete_game_id_from_redis(
     redis_conn: &mut RedisConn,
 ) -> Result<()> {
     redis_conn
-        .del(format!("Attacker:{}", attacker_id))
+        .del::<_, ()>(format!("Attacker:{}", attacker_id))
         .map_err(|err| anyhow::anyhow!("Failed to delete attacker key: {}", err))?;
     redis_conn
-        .del(format!("Defender:{}", defender_id))
+        .del::<_, ()>(format!("Defender:{}", defender_id))
         .map_err(|err| anyhow::anyhow!("Failed to delete defender key: {}", err))?;
 
     Ok(())
 }
 
-pub fn encode_attack_token(attacker_id: i32, defender_id: i32, game_id: i32) -> Result<String> {
-    let jwt_secret = env::var("COOKIE_KEY").expect("COOKIE_KEY must be set!");
+//attack tokens have their own signing keys, listed as kid:secret with the signing key first
+fn attack_token_keys() -> Vec<(String, String)> {
+    env::var("ATTACK_TOKEN_KEYS")
+        .expect("ATTACK_TOKEN_KEYS must be set!")
+        .split(',')
+        .filter_map(|key| key.trim().split_once(':'))
+        .map(|(kid, secret)| (kid.to_string(), secret.to_string()))
+        .collect()
+}
+
+pub fn encode_attack_token(
+    attacker_id: i32,
+    defender_id: i32,
+    game_id: i32,
+    device: String,
+    fog_of_war: bool,
+    redis_conn: &mut RedisConn,
+) -> Result<String> {
+    let (kid, jwt_secret) = attack_token_keys()
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No attack token signing key"))?;
     let now = chrono::Local::now();
     let iat = now.timestamp() as usize;
     let jwt_max_age: i64 = ATTACK_TOKEN_AGE_IN_MINUTES * 60;
     let token_expiring_time = now + chrono::Duration::seconds(jwt_max_age);
     let exp = (token_expiring_time).timestamp() as usize;
+    let jti: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
     let token: AttackToken = AttackToken {
+        jti: jti.clone(),
         game_id,
         attacker_id,
         defender_id,
+        device,
+        fog_of_war,
         exp,
         iat,
     };
 
+    let header = Header {
+        kid: Some(kid),
+        ..Header::default()
+    };
     let token_result = encode(
-        &Header::default(),
+        &header,
         &token,
         &EncodingKey::from_secret(jwt_secret.as_ref()),
     );
@@ -499,37 +616,83 @@ pub fn encode_attack_token(attacker_id: i32, defender_id: i32, game_id: i32) ->
         Err(e) => return Err(e.into()),
     };
 
+    redis_conn
+        .set_ex::<_, _, ()>(
+            format!("AttackToken:{}", jti),
+            game_id,
+            jwt_max_age as usize,
+        )
+        .map_err(|err| anyhow::anyhow!("Failed to set attack token key: {}", err))?;
+
     Ok(token)
 }
 
-pub fn decode_user_token(token: &str) -> Result<i32> {
-    let jwt_secret = env::var("COOKIE_KEY").expect("COOKIE_KEY must be set!");
-    let token_data = decode::<TokenClaims>(
+//tokens signed with a retired key stop working once it is dropped from ATTACK_TOKEN_KEYS
+pub fn decode_attack_token(token: &str) -> Result<AttackToken> {
+    let kid = decode_header(token)
+        .map_err(|err| anyhow::anyhow!("Failed to decode token: {}", err))?
+        .kid
+        .ok_or_else(|| anyhow::anyhow!("Attack token has no key id"))?;
+    let (_, jwt_secret) = attack_token_keys()
+        .into_iter()
+        .find(|(key_id, _)| *key_id == kid)
+        .ok_or_else(|| anyhow::anyhow!("Attack token signed with an unknown key"))?;
+    let token_data = decode::<AttackToken>(
         token,
         &DecodingKey::from_secret(jwt_secret.as_str().as_ref()),
         &Validation::new(Algorithm::HS256),
     )
     .map_err(|err| anyhow::anyhow!("Failed to decode token: {}", err))?;
 
-    let now = chrono::Local::now();
-    let iat = now.timestamp() as usize;
-    if iat > token_data.claims.exp {
-        return Err(anyhow::anyhow!("Attack token expired"));
-    }
+    Ok(token_data.claims)
+}
 
-    Ok(token_data.claims.id)
+pub fn get_offered_protocols(req: &HttpRequest) -> Vec<String> {
+    req.headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|protocol| protocol.trim().to_string())
+        .collect()
 }
 
-pub fn decode_attack_token(token: &str) -> Result<AttackToken> {
-    let jwt_secret = env::var("COOKIE_KEY").expect("COOKIE_KEY must be set!");
-    let token_data = decode::<AttackToken>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_str().as_ref()),
-        &Validation::new(Algorithm::HS256),
+//user and attack tokens of a socket handshake, from the query or the offered subprotocols
+pub fn get_handshake_tokens(
+    req: &HttpRequest,
+    query: SocketQuery,
+) -> (Option<String>, Option<String>) {
+    let protocols = get_offered_protocols(req);
+    let from_protocols = |prefix: &str| {
+        protocols
+            .iter()
+            .find_map(|protocol| protocol.strip_prefix(prefix))
+            .map(|token| token.to_string())
+    };
+
+    (
+        query
+            .user_token
+            .or_else(|| from_protocols(USER_TOKEN_PROTOCOL_PREFIX)),
+        query
+            .attack_token
+            .or_else(|| from_protocols(ATTACK_TOKEN_PROTOCOL_PREFIX)),
     )
-    .map_err(|err| anyhow::anyhow!("Failed to decode token: {}", err))?;
+}
 
-    Ok(token_data.claims)
+//the device the user is currently logged in with
+pub fn get_session_device(user_id: i32, redis_conn: &mut RedisConn) -> Result<Option<String>> {
+    let device: Option<String> = redis_conn
+        .get(user_id)
+        .map_err(|err| anyhow::anyhow!("Failed to get key: {}", err))?;
+    Ok(device)
+}
+
+//deleting the key is atomic, so only the first handshake with a token gets through
+pub fn consume_attack_token(jti: &str, redis_conn: &mut RedisConn) -> Result<bool> {
+    let removed: i32 = redis_conn
+        .del(format!("AttackToken:{}", jti))
+        .map_err(|err| anyhow::anyhow!("Failed to delete attack token key: {}", err))?;
+    Ok(removed == 1)
 }
 
 pub fn get_mines(conn: &mut PgConnection, map_id: i32) -> Result<Vec<MineDetails>> {
@@ -547,6 +710,9 @@ pub fn get_mines(conn: &mut PgConnection, map_id: i32) -> Result<Vec<MineDetails
             id: mine_id as i32,
             damage: mine_type.damage,
             radius: mine_type.radius,
+            effect: mine_type.effect,
+            effect_duration: mine_type.effect_duration,
+            disarmed_frames: 0,
             position: Coords {
                 x: map_space.x_coordinate,
                 y: map_space.y_coordinate,
@@ -562,22 +728,34 @@ pub fn get_defenders(
     map_id: i32,
     user_id: i32,
 ) -> Result<Vec<DefenderDetails>> {
-    use crate::schema::{available_blocks, block_type, building_type, defender_type, map_spaces};
+    use crate::schema::{
+        attack_type, available_blocks, block_type, building_type, defender_type, map_spaces,
+    };
     let result: Vec<(
         MapSpaces,
-        (BlockType, AvailableBlocks, BuildingType, DefenderType),
+        (
+            BlockType,
+            AvailableBlocks,
+            BuildingType,
+            (DefenderType, Option<AttackType>),
+        ),
     )> = map_spaces::table
         .inner_join(
             block_type::table
                 .inner_join(available_blocks::table)
                 .inner_join(building_type::table)
-                .inner_join(defender_type::table),
+                .inner_join(defender_type::table.left_join(attack_type::table)),
         )
         .filter(map_spaces::map_id.eq(map_id))
         .filter(available_blocks::user_id.eq(user_id))
         .load::<(
             MapSpaces,
-            (BlockType, AvailableBlocks, BuildingType, DefenderType),
+            (
+                BlockType,
+                AvailableBlocks,
+                BuildingType,
+                (DefenderType, Option<AttackType>),
+            ),
         )>(conn)
         .map_err(|err| DieselError {
             table: "map_spaces",
@@ -587,7 +765,7 @@ pub fn get_defenders(
 
     let mut defenders: Vec<DefenderDetails> = Vec::new();
 
-    for (map_space, (_, _, _, defender_type)) in result.iter() {
+    for (map_space, (_, _, _, (defender_type, attack_type))) in result.iter() {
         let (hut_x, hut_y) = (map_space.x_coordinate, map_space.y_coordinate);
         // let path: Vec<(i32, i32)> = vec![(hut_x, hut_y)];
         defenders.push(DefenderDetails {
@@ -599,7 +777,15 @@ pub fn get_defenders(
             is_alive: true,
             damage_dealt: false,
             target_id: None,
+            target_unit: None,
             path_in_current_frame: Vec::new(),
+            stunned_frames: 0,
+            ranged: attack_type.as_ref().map(|attack_type| RangedAttack {
+                range: attack_type.attack_radius,
+                damage: attack_type.attack_damage,
+                projectile_speed: attack_type.projectile_speed,
+            }),
+            pending_hit: None,
         })
     }
     // Sorted to handle multiple defenders attack same attacker at same frame
@@ -623,7 +809,7 @@ pub fn get_buildings(conn: &mut PgConnection, map_id: i32) -> Result<Vec<Buildin
             error: err,
         })?
         .into_iter()
-        .map(|(map_space, (_, building_type))| BuildingDetails {
+        .map(|(map_space, (block_type, building_type))| BuildingDetails {
             id: map_space.id,
             current_hp: building_type.hp,
             total_hp: building_type.hp,
@@ -633,6 +819,8 @@ pub fn get_buildings(conn: &mut PgConnection, map_id: i32) -> Result<Vec<Buildin
                 y: map_space.y_coordinate,
             },
             width: building_type.width,
+            height: building_type.height,
+            is_wall: block_type.category == BlockCategory::Wall,
         })
         .collect();
     update_buidling_artifacts(conn, map_id, buildings)
@@ -653,6 +841,8 @@ pub fn get_bomb_types(conn: &mut PgConnection) -> Result<Vec<BombType>> {
             radius: emp.attack_radius,
             damage: emp.attack_damage,
             total_count: 0,
+            effect: emp.att_type,
+            disable_duration: emp.disable_duration,
         })
         .collect();
     Ok(bomb_types)
@@ -72,7 +72,7 @@ async fn logout(
         .map_err(|err| error::handle_error(err.into()))?;
     // delete user id from redis db
     redis_conn
-        .del(user_id)
+        .del::<_, ()>(user_id)
         .map_err(|err| error::handle_error(err.into()))?;
 
     // clear the session cookie
@@ -136,7 +136,7 @@ async fn login(
 
     //set device id in redis db
     redis_conn
-        .set(user.id, device + &expiring_time)
+        .set::<_, _, ()>(user.id, device + &expiring_time)
         .map_err(|err| error::handle_error(err.into()))?;
 
     // insert the jwt token in the session cookie
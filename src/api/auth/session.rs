@@ -8,7 +8,7 @@ use actix_web::{dev::Payload, web::Data, FromRequest, HttpRequest};
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
 use redis::Commands;
 
-use crate::api::{error::AuthError, RedisPool};
+use crate::api::{error::AuthError, RedisConn, RedisPool};
 
 use super::TokenClaims;
 
@@ -21,10 +21,6 @@ impl FromRequest for AuthUser {
     fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
         let session = req.get_session();
         let redis_pool: Data<RedisPool> = req.app_data::<Data<RedisPool>>().unwrap().clone();
-        let mut redis_conn = match redis_pool.get() {
-            Ok(conn) => conn,
-            Err(_) => return ready(Err(Self::Error::Session)),
-        };
 
         let auth_token: String = match session.get::<String>("token") {
             Ok(auth_token) => match auth_token {
@@ -38,27 +34,38 @@ impl FromRequest for AuthUser {
             return ready(Err(Self::Error::Session));
         }
 
-        let secret: String = env::var("COOKIE_KEY").unwrap_or("".to_string());
-
-        let token = match decode::<TokenClaims>(
-            &auth_token,
-            &DecodingKey::from_secret(secret.as_str().as_ref()),
-            &Validation::new(Algorithm::HS256),
-        ) {
-            Ok(token) => token,
+        let mut redis_conn = match redis_pool.get() {
+            Ok(conn) => conn,
             Err(_) => return ready(Err(Self::Error::Session)),
         };
 
-        let user_id = token.claims.id;
-        let device = token.claims.device;
-        let device_from_token: String = match redis_conn.get(user_id) {
-            Ok(device_id) => device_id,
-            Err(_) => return ready(Err(Self::Error::Session)),
-        };
-        if device != *device_from_token {
-            ready(Err(Self::Error::Session))
-        } else {
-            ready(Ok(AuthUser(user_id)))
+        match authenticate_token(&auth_token, &mut redis_conn) {
+            Ok(claims) => ready(Ok(AuthUser(claims.id))),
+            Err(err) => ready(Err(err)),
         }
     }
 }
+
+//checks a session token and that it belongs to the device the user last logged in with
+pub fn authenticate_token(
+    token: &str,
+    redis_conn: &mut RedisConn,
+) -> Result<TokenClaims, AuthError> {
+    let secret: String = env::var("COOKIE_KEY").unwrap_or("".to_string());
+
+    let token = decode::<TokenClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_str().as_ref()),
+        &Validation::new(Algorithm::HS256),
+    )
+    .map_err(|_| AuthError::Session)?;
+
+    let device_from_token: String = redis_conn
+        .get(token.claims.id)
+        .map_err(|_| AuthError::Session)?;
+    if token.claims.device != device_from_token {
+        return Err(AuthError::Session);
+    }
+
+    Ok(token.claims)
+}
@@ -0,0 +1,168 @@
+/// Shareable text codes for base layouts, made of block names and levels instead of database ids
+use super::MapSpacesEntry;
+use crate::api::error::LayoutError;
+use crate::error::DieselError;
+use crate::models::{BlockCategory, ItemKind};
+use crate::schema::{available_blocks, block_type, building_type, defender_type, mine_type};
+use crate::util::function;
+use anyhow::Result;
+use diesel::{prelude::*, PgConnection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const LAYOUT_CODE_PREFIX: &str = "aot";
+const LAYOUT_CODE_VERSION: i32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LayoutCodeBlock {
+    pub kind: ItemKind,
+    pub name: String,
+    pub level: i32,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Debug)]
+struct BlockDescriptor {
+    kind: ItemKind,
+    name: String,
+    level: i32,
+}
+
+//kind, name and level of the given block types, or of every block type if none are given
+fn fetch_block_descriptors(
+    conn: &mut PgConnection,
+    block_type_ids: Option<&[i32]>,
+) -> Result<HashMap<i32, BlockDescriptor>> {
+    let mut descriptors = HashMap::new();
+
+    let mut buildings = block_type::table
+        .inner_join(building_type::table)
+        .filter(block_type::category.eq_any([BlockCategory::Building, BlockCategory::Wall]))
+        .select((block_type::id, building_type::name, building_type::level))
+        .into_boxed();
+    let mut defenders = block_type::table
+        .inner_join(defender_type::table)
+        .filter(block_type::category.eq(BlockCategory::Defender))
+        .select((block_type::id, defender_type::name, defender_type::level))
+        .into_boxed();
+    let mut mines = block_type::table
+        .inner_join(mine_type::table)
+        .filter(block_type::category.eq(BlockCategory::Mine))
+        .select((block_type::id, mine_type::name, mine_type::level))
+        .into_boxed();
+    if let Some(block_type_ids) = block_type_ids {
+        buildings = buildings.filter(block_type::id.eq_any(block_type_ids));
+        defenders = defenders.filter(block_type::id.eq_any(block_type_ids));
+        mines = mines.filter(block_type::id.eq_any(block_type_ids));
+    }
+
+    for (kind, rows) in [
+        (
+            ItemKind::Building,
+            buildings.load::<(i32, String, i32)>(conn),
+        ),
+        (
+            ItemKind::Defender,
+            defenders.load::<(i32, String, i32)>(conn),
+        ),
+        (ItemKind::Mine, mines.load::<(i32, String, i32)>(conn)),
+    ] {
+        let rows = rows.map_err(|err| DieselError {
+            table: "block_type",
+            function: function!(),
+            error: err,
+        })?;
+        for (id, name, level) in rows {
+            descriptors.insert(id, BlockDescriptor { kind, name, level });
+        }
+    }
+
+    Ok(descriptors)
+}
+
+pub fn encode_layout(conn: &mut PgConnection, map_spaces: &[MapSpacesEntry]) -> Result<String> {
+    let descriptors = fetch_block_descriptors(conn, None)?;
+
+    let blocks = map_spaces
+        .iter()
+        .map(|map_space| {
+            let block_type_id = map_space.block_type_id;
+            let descriptor = descriptors
+                .get(&block_type_id)
+                .ok_or_else(|| anyhow::anyhow!("Unknown block type {block_type_id}"))?;
+            Ok(LayoutCodeBlock {
+                kind: descriptor.kind,
+                name: descriptor.name.clone(),
+                level: descriptor.level,
+                x: map_space.x_coordinate,
+                y: map_space.y_coordinate,
+            })
+        })
+        .collect::<Result<Vec<LayoutCodeBlock>>>()?;
+
+    let json = serde_json::to_vec(&blocks)?;
+    Ok(format!(
+        "{LAYOUT_CODE_PREFIX}{LAYOUT_CODE_VERSION}:{}",
+        base64::encode(json)
+    ))
+}
+
+pub fn decode_layout(code: &str) -> Result<Vec<LayoutCodeBlock>> {
+    let (header, payload) = code
+        .trim()
+        .split_once(':')
+        .ok_or(LayoutError::InvalidCode)?;
+
+    let version = header
+        .strip_prefix(LAYOUT_CODE_PREFIX)
+        .and_then(|version| version.parse::<i32>().ok())
+        .ok_or(LayoutError::InvalidCode)?;
+    if version != LAYOUT_CODE_VERSION {
+        return Err(LayoutError::InvalidCode.into());
+    }
+
+    let json = base64::decode(payload).map_err(|_| LayoutError::InvalidCode)?;
+    Ok(serde_json::from_slice(&json).map_err(|_| LayoutError::InvalidCode)?)
+}
+
+//maps every block to the closest level the player owns, returning the blocks they lack
+pub fn resolve_layout(
+    conn: &mut PgConnection,
+    player: &i32,
+    blocks: &[LayoutCodeBlock],
+) -> Result<(Vec<MapSpacesEntry>, Vec<LayoutCodeBlock>)> {
+    let owned_block_type_ids: Vec<i32> = available_blocks::table
+        .filter(available_blocks::user_id.eq(player))
+        .filter(available_blocks::block_type_id.is_not_null())
+        .select(available_blocks::block_type_id.assume_not_null())
+        .load::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "available_blocks",
+            function: function!(),
+            error: err,
+        })?;
+    let owned = fetch_block_descriptors(conn, Some(&owned_block_type_ids))?;
+
+    let mut map_spaces = Vec::new();
+    let mut missing = Vec::new();
+    for block in blocks {
+        //ties go to the lower level
+        let closest = owned
+            .iter()
+            .filter(|(_, owned)| owned.kind == block.kind && owned.name == block.name)
+            .min_by_key(|(_, owned)| ((owned.level - block.level).abs(), owned.level));
+
+        match closest {
+            Some((block_type_id, _)) => map_spaces.push(MapSpacesEntry {
+                x_coordinate: block.x,
+                y_coordinate: block.y,
+                block_type_id: *block_type_id,
+                artifacts: 0,
+            }),
+            None => missing.push(block.clone()),
+        }
+    }
+
+    Ok((map_spaces, missing))
+}
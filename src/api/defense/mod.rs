@@ -6,15 +6,16 @@ use super::inventory::util::get_user_artifacts;
 use super::user::util::fetch_user;
 use super::PgPool;
 use super::RedisPool;
-use crate::api::error;
-use crate::api::util::HistoryboardQuery;
+use crate::api::error::{self, LayoutError};
+use crate::api::util::{is_under_attack, run_idempotent, HistoryboardQuery};
 use crate::models::*;
 use actix_web::error::{ErrorBadRequest, ErrorNotFound};
 use actix_web::web::{self, Data, Json};
-use actix_web::{Responder, Result};
+use actix_web::{HttpRequest, Responder, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+mod layout_code;
 pub mod shortest_path;
 pub mod util;
 mod validate;
@@ -28,8 +29,20 @@ pub fn routes(cfg: &mut web::ServiceConfig) {
     .service(web::resource("/top").route(web::get().to(get_top_defenses)))
     .service(web::resource("/transfer").route(web::post().to(post_transfer_artifacts)))
     .service(web::resource("/save").route(web::put().to(confirm_base_details)))
+    .service(web::resource("/validate").route(web::post().to(validate_base_details)))
     .service(web::resource("/game/{id}").route(web::get().to(get_game_base_details)))
     .service(web::resource("/history").route(web::get().to(defense_history)))
+    .service(web::resource("/layouts").route(web::get().to(get_layout_slots)))
+    .service(web::resource("/layouts/{name}").route(web::put().to(save_layout_draft)))
+    .service(web::resource("/layouts/{name}/activate").route(web::post().to(activate_layout_slot)))
+    .service(web::resource("/layouts/{name}/versions").route(web::get().to(get_layout_versions)))
+    .service(web::resource("/layouts/{name}/export").route(web::get().to(export_layout)))
+    .service(web::resource("/layouts/{name}/import").route(web::post().to(import_layout)))
+    .service(web::resource("/layouts/{name}/diff").route(web::get().to(get_layout_diff)))
+    .service(
+        web::resource("/layouts/{name}/versions/{version}/rollback")
+            .route(web::post().to(rollback_layout_version)),
+    )
     .service(web::resource("/{defender_id}").route(web::get().to(get_other_base_details)))
     .app_data(Data::new(web::JsonConfig::default().limit(1024 * 1024)));
 }
@@ -57,25 +70,21 @@ pub struct TransferArtifactResponse {
 }
 
 async fn post_transfer_artifacts(
+    req: HttpRequest,
     transfer: Json<TransferArtifactEntry>,
     pg_pool: Data<PgPool>,
     redis_pool: Data<RedisPool>,
     user: AuthUser,
 ) -> Result<impl Responder> {
     let user_id = user.0;
+    let transfer = transfer.into_inner();
 
-    let mut redis_conn = redis_pool
-        .get()
-        .map_err(|err| error::handle_error(err.into()))?;
-
-    if let Ok(Some(_)) = get_game_id_from_redis(user_id, &mut redis_conn, false) {
+    if is_under_attack(user_id, &redis_pool)? {
         return Err(ErrorBadRequest(
             "You are under attack. Cannot transfer artifacts",
         ));
     }
 
-    let transfer = transfer.into_inner();
-
     let mut conn = pg_pool
         .get()
         .map_err(|err| error::handle_error(err.into()))?;
@@ -118,79 +127,22 @@ async fn post_transfer_artifacts(
         return Err(ErrorBadRequest("Cannot transfer to the same building"));
     }
 
-    let mut conn = pg_pool
-        .get()
-        .map_err(|err| error::handle_error(err.into()))?;
-    let bank_artifact_count = web::block(move || {
-        util::get_building_artifact_count(&mut conn, &current_layout_id, &bank_map_space_id)
-    })
-    .await?
-    .map_err(|err| error::handle_error(err.into()))?;
-
-    if transfer.artifacts_differ > bank_artifact_count {
-        return Err(ErrorBadRequest("Not enough artifacts in the bank"));
-    }
-
-    let mut conn = pg_pool
-        .get()
-        .map_err(|err| error::handle_error(err.into()))?;
-    let mut building_artifact_count = web::block(move || {
-        util::get_building_artifact_count(&mut conn, &current_layout_id, &transfer.map_space_id)
-    })
-    .await?
-    .map_err(|err| error::handle_error(err.into()))?;
-
-    if building_artifact_count == -1 {
-        let mut conn = pg_pool
-            .get()
-            .map_err(|err| error::handle_error(err.into()))?;
-        web::block(move || util::create_artifact_record(&mut conn, &transfer.map_space_id, &0))
-            .await?
-            .map_err(|err| error::handle_error(err.into()))?;
-        building_artifact_count = 0;
-    }
-
-    if transfer.artifacts_differ + building_artifact_count < 0 {
-        return Err(ErrorBadRequest("Not enough artifacts in the building"));
-    }
-
-    let mut conn = pg_pool
-        .get()
-        .map_err(|err| error::handle_error(err.into()))?;
-    let building_capacity =
-        web::block(move || util::get_building_capacity(&mut conn, &transfer.map_space_id))
-            .await?
-            .map_err(|err| error::handle_error(err.into()))?;
-
-    if building_capacity < transfer.artifacts_differ + building_artifact_count {
-        return Err(ErrorBadRequest("Building capacity not sufficient"));
-    }
-
-    let new_building_artifact_count = building_artifact_count + transfer.artifacts_differ;
-    let new_bank_artifact_count = bank_artifact_count - transfer.artifacts_differ;
-
-    //Transfer Artifacts
-    let mut conn = pg_pool
-        .get()
-        .map_err(|err| error::handle_error(err.into()))?;
-    web::block(move || {
-        util::transfer_artifacts_building(
-            &mut conn,
-            &transfer.map_space_id,
-            &bank_map_space_id,
-            &new_building_artifact_count,
-            &new_bank_artifact_count,
-        )
-    })
-    .await?
-    .map_err(|err| error::handle_error(err.into()))?;
-
-    Ok(web::Json(TransferArtifactResponse {
-        building_map_space_id: transfer.map_space_id,
-        artifacts_in_building: new_building_artifact_count,
-        bank_map_space_id,
-        artifacts_in_bank: new_bank_artifact_count,
-    }))
+    run_idempotent(
+        &req,
+        pg_pool,
+        user_id,
+        "transfer",
+        move |conn| {
+            util::transfer_artifacts_building(
+                conn,
+                &user_id,
+                &transfer.map_space_id,
+                &transfer.artifacts_differ,
+            )
+        },
+        error::handle_spend_error,
+    )
+    .await
 }
 
 async fn get_user_base_details(pool: Data<PgPool>, user: AuthUser) -> Result<impl Responder> {
@@ -231,7 +183,7 @@ async fn get_other_base_details(
 
     let response = web::block(move || {
         let mut conn = pool.get()?;
-        util::get_map_details_for_attack(&mut conn, map, defender_id)
+        util::get_map_details_for_attack(&mut conn, map, defender_id, false)
     })
     .await?
     .map_err(|err| error::handle_error(err.into()))?;
@@ -265,10 +217,20 @@ async fn get_game_base_details(
 
 async fn set_base_details(
     map_spaces: Json<Vec<MapSpacesEntry>>,
+    redis_pool: Data<RedisPool>,
     pool: Data<PgPool>,
     user: AuthUser,
 ) -> Result<impl Responder> {
     let defender_id = user.0;
+
+    let mut redis_conn = redis_pool
+        .get()
+        .map_err(|err| error::handle_error(err.into()))?;
+
+    if let Ok(Some(_)) = get_game_id_from_redis(defender_id, &mut redis_conn, false) {
+        return Err(ErrorBadRequest("You are under attack. Cannot update base"));
+    }
+
     let map_spaces = map_spaces.into_inner();
     let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
     let (map, blocks, buildings) = web::block(move || {
@@ -285,8 +247,7 @@ async fn set_base_details(
 
     web::block(move || {
         let mut conn = pool.get()?;
-        // util::set_map_invalid(&mut conn, map.id)?;
-        util::put_base_details(&map_spaces, &map, &mut conn)
+        util::put_base_details(&map_spaces, &map, map.is_valid, &mut conn)
     })
     .await?
     .map_err(|err| error::handle_error(err.into()))?;
@@ -349,9 +310,8 @@ async fn confirm_base_details(
 
     web::block(move || {
         let mut conn = pool.get()?;
-        util::put_base_details(&map_spaces, &map, &mut conn)
-        // util::calculate_shortest_paths(&mut conn, map.id)?;
-        // util::set_map_valid(&mut conn, map.id)
+        let map = util::put_base_details(&map_spaces, &map, true, &mut conn)?;
+        shortest_path::store_shortest_paths(&mut conn, map.id).map(|_| ())
     })
     .await?
     .map_err(|err| error::handle_error(err.into()))?;
@@ -359,6 +319,506 @@ async fn confirm_base_details(
     Ok("Saved successfully")
 }
 
+#[derive(Serialize)]
+struct ValidateBaseResponse {
+    pub is_valid: bool,
+    pub diagnostics: Vec<validate::LayoutDiagnostic>,
+}
+
+//reports every problem that would stop the base from being saved, without saving it
+async fn validate_base_details(
+    map_spaces: Json<Vec<MapSpacesEntry>>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let map_spaces = map_spaces.into_inner();
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let (blocks, mut level_constraints, buildings, defenders, mines, user_artifacts) =
+        web::block(move || {
+            let map = util::fetch_map_layout(&mut conn, &defender_id)?;
+            Ok((
+                util::fetch_blocks(&mut conn, &defender_id)?,
+                util::get_level_constraints(&mut conn, map.level_id, &defender_id)?,
+                util::fetch_buildings(&mut conn)?,
+                util::fetch_defender_types(&mut conn, &defender_id)?,
+                util::fetch_mine_types(&mut conn, &defender_id)?,
+                get_user_artifacts(defender_id, &mut conn)?,
+            ))
+                as anyhow::Result<(
+                    HashMap<i32, BlockType>,
+                    HashMap<i32, i32>,
+                    Vec<BuildingType>,
+                    Vec<DefenderTypeResponse>,
+                    Vec<MineTypeResponse>,
+                    i32,
+                )>
+        })
+        .await?
+        .map_err(|err| error::handle_error(err.into()))?;
+
+    let diagnostics = validate::diagnose_save_layout(
+        &map_spaces,
+        &mut level_constraints,
+        &blocks,
+        &buildings,
+        &defenders,
+        &mines,
+        &user_artifacts,
+    );
+
+    Ok(Json(ValidateBaseResponse {
+        is_valid: diagnostics.is_empty(),
+        diagnostics,
+    }))
+}
+
+#[derive(Serialize)]
+struct LayoutSlotResponse {
+    pub id: i32,
+    pub name: String,
+    pub level_id: i32,
+    pub is_valid: bool,
+    pub is_active: bool,
+}
+
+fn check_layout_name(name: &str) -> Result<()> {
+    if name.trim().is_empty() || name.len() > 32 {
+        return Err(ErrorBadRequest(
+            "Layout name must be 1 to 32 characters long",
+        ));
+    }
+    Ok(())
+}
+
+async fn get_layout_slots(pool: Data<PgPool>, user: AuthUser) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let layouts = web::block(move || {
+        let mut conn = pool.get()?;
+        util::fetch_layout_slots(&mut conn, &defender_id)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    let response: Vec<LayoutSlotResponse> = layouts
+        .into_iter()
+        .map(|layout| LayoutSlotResponse {
+            id: layout.id,
+            name: layout.name,
+            level_id: layout.level_id,
+            is_valid: layout.is_valid,
+            is_active: layout.is_active,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+async fn save_layout_draft(
+    name: web::Path<String>,
+    map_spaces: Json<Vec<MapSpacesEntry>>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let name = name.into_inner();
+    check_layout_name(&name)?;
+
+    let map_spaces = map_spaces.into_inner();
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let (blocks, buildings) = web::block(move || {
+        Ok((
+            util::fetch_blocks(&mut conn, &defender_id)?,
+            util::fetch_buildings(&mut conn)?,
+        )) as anyhow::Result<(HashMap<i32, BlockType>, Vec<BuildingType>)>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    //drafts only need to fit on the map
+    validate::is_valid_update_layout(&map_spaces, &blocks, &buildings)?;
+
+    web::block(move || {
+        let mut conn = pool.get()?;
+        util::save_layout_draft(&mut conn, &defender_id, &name, &map_spaces)
+    })
+    .await?
+    .map_err(error::handle_layout_error)?;
+
+    Ok("Draft saved successfully")
+}
+
+async fn activate_layout_slot(
+    name: web::Path<String>,
+    redis_pool: Data<RedisPool>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let name = name.into_inner();
+
+    let mut redis_conn = redis_pool
+        .get()
+        .map_err(|err| error::handle_error(err.into()))?;
+
+    if let Ok(Some(_)) = get_game_id_from_redis(defender_id, &mut redis_conn, false) {
+        return Err(ErrorBadRequest(
+            "You are under attack. Cannot switch layouts",
+        ));
+    }
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let slot = web::block(move || {
+        let active_map = util::fetch_map_layout(&mut conn, &defender_id)?;
+        util::fetch_layout_slot(&mut conn, &defender_id, &active_map.level_id, &name)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    let slot = match slot {
+        Some(slot) => slot,
+        None => return Err(LayoutError::SlotNotFound.into()),
+    };
+    if slot.is_active {
+        return Ok("Layout is already active");
+    }
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let (map_spaces, blocks, mut level_constraints, buildings, defenders, mines, user_artifacts) =
+        web::block(move || {
+            Ok((
+                util::fetch_map_spaces_entries(&mut conn, &slot.id)?,
+                util::fetch_blocks(&mut conn, &defender_id)?,
+                util::get_level_constraints(&mut conn, slot.level_id, &defender_id)?,
+                util::fetch_buildings(&mut conn)?,
+                util::fetch_defender_types(&mut conn, &defender_id)?,
+                util::fetch_mine_types(&mut conn, &defender_id)?,
+                get_user_artifacts(defender_id, &mut conn)?,
+            ))
+                as anyhow::Result<(
+                    Vec<MapSpacesEntry>,
+                    HashMap<i32, BlockType>,
+                    HashMap<i32, i32>,
+                    Vec<BuildingType>,
+                    Vec<DefenderTypeResponse>,
+                    Vec<MineTypeResponse>,
+                    i32,
+                )>
+        })
+        .await?
+        .map_err(|err| error::handle_error(err.into()))?;
+
+    validate::is_valid_save_layout(
+        &map_spaces,
+        &mut level_constraints,
+        &blocks,
+        &buildings,
+        &defenders,
+        &mines,
+        &user_artifacts,
+    )?;
+
+    web::block(move || {
+        let mut conn = pool.get()?;
+        util::activate_layout_slot(&mut conn, &defender_id, &slot.id)?;
+        shortest_path::ensure_shortest_paths(&mut conn, slot.id)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    Ok("Layout activated successfully")
+}
+
+#[derive(Serialize)]
+struct LayoutVersionResponse {
+    pub id: i32,
+    pub version: i32,
+    pub is_valid: bool,
+    pub is_latest: bool,
+}
+
+async fn get_layout_versions(
+    name: web::Path<String>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let name = name.into_inner();
+    let versions = web::block(move || {
+        let mut conn = pool.get()?;
+        let active_map = util::fetch_map_layout(&mut conn, &defender_id)?;
+        util::fetch_layout_versions(&mut conn, &defender_id, &active_map.level_id, &name)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    if versions.is_empty() {
+        return Err(LayoutError::SlotNotFound.into());
+    }
+
+    let response: Vec<LayoutVersionResponse> = versions
+        .into_iter()
+        .map(|layout| LayoutVersionResponse {
+            id: layout.id,
+            version: layout.version,
+            is_valid: layout.is_valid,
+            is_latest: layout.is_latest,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+#[derive(Deserialize)]
+struct LayoutDiffQuery {
+    pub from: i32,
+    pub to: Option<i32>,
+}
+
+async fn get_layout_diff(
+    name: web::Path<String>,
+    query: web::Query<LayoutDiffQuery>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let name = name.into_inner();
+    let query = query.into_inner();
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let (from, to) = web::block(move || {
+        let active_map = util::fetch_map_layout(&mut conn, &defender_id)?;
+        let level_id = active_map.level_id;
+        let from =
+            util::fetch_layout_version(&mut conn, &defender_id, &level_id, &name, &query.from)?;
+        //compare against the latest version when no target version is given
+        let to = match query.to {
+            Some(to) => util::fetch_layout_version(&mut conn, &defender_id, &level_id, &name, &to)?,
+            None => util::fetch_layout_slot(&mut conn, &defender_id, &level_id, &name)?,
+        };
+        Ok((from, to)) as anyhow::Result<(Option<MapLayout>, Option<MapLayout>)>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err(LayoutError::VersionNotFound.into()),
+    };
+
+    let response = web::block(move || {
+        let mut conn = pool.get()?;
+        util::diff_layout_versions(&mut conn, &from, &to)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    Ok(Json(response))
+}
+
+async fn rollback_layout_version(
+    path: web::Path<(String, i32)>,
+    redis_pool: Data<RedisPool>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let (name, version) = path.into_inner();
+
+    let mut redis_conn = redis_pool
+        .get()
+        .map_err(|err| error::handle_error(err.into()))?;
+
+    if let Ok(Some(_)) = get_game_id_from_redis(defender_id, &mut redis_conn, false) {
+        return Err(ErrorBadRequest(
+            "You are under attack. Cannot roll back layouts",
+        ));
+    }
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let (latest, target) = web::block(move || {
+        let active_map = util::fetch_map_layout(&mut conn, &defender_id)?;
+        let level_id = active_map.level_id;
+        Ok((
+            util::fetch_layout_slot(&mut conn, &defender_id, &level_id, &name)?,
+            util::fetch_layout_version(&mut conn, &defender_id, &level_id, &name, &version)?,
+        )) as anyhow::Result<(Option<MapLayout>, Option<MapLayout>)>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    let (latest, target) = match (latest, target) {
+        (Some(latest), Some(target)) => (latest, target),
+        _ => return Err(LayoutError::VersionNotFound.into()),
+    };
+    if latest.id == target.id {
+        return Err(ErrorBadRequest("Layout is already at this version"));
+    }
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let latest_map = latest.clone();
+    let target_id = target.id;
+    let (map_spaces, blocks, mut level_constraints, buildings, defenders, mines, user_artifacts) =
+        web::block(move || {
+            Ok((
+                util::get_rollback_entries(&mut conn, &defender_id, &latest_map, &target)?,
+                util::fetch_blocks(&mut conn, &defender_id)?,
+                util::get_level_constraints(&mut conn, latest_map.level_id, &defender_id)?,
+                util::fetch_buildings(&mut conn)?,
+                util::fetch_defender_types(&mut conn, &defender_id)?,
+                util::fetch_mine_types(&mut conn, &defender_id)?,
+                get_user_artifacts(defender_id, &mut conn)?,
+            ))
+                as anyhow::Result<(
+                    Vec<MapSpacesEntry>,
+                    HashMap<i32, BlockType>,
+                    HashMap<i32, i32>,
+                    Vec<BuildingType>,
+                    Vec<DefenderTypeResponse>,
+                    Vec<MineTypeResponse>,
+                    i32,
+                )>
+        })
+        .await?
+        .map_err(error::handle_layout_error)?;
+
+    //the active layout is defended, so it has to be a complete base
+    if latest.is_active {
+        validate::is_valid_save_layout(
+            &map_spaces,
+            &mut level_constraints,
+            &blocks,
+            &buildings,
+            &defenders,
+            &mines,
+            &user_artifacts,
+        )?;
+    } else {
+        validate::is_valid_update_layout(&map_spaces, &blocks, &buildings)?;
+    }
+
+    let is_valid = latest.is_active;
+    web::block(move || {
+        let mut conn = pool.get()?;
+        let map = util::put_base_details(&map_spaces, &latest, is_valid, &mut conn)?;
+        let is_copied = shortest_path::copy_shortest_paths(&mut conn, target_id, map.id)?;
+        if !is_copied && map.is_active {
+            shortest_path::store_shortest_paths(&mut conn, map.id)?;
+        }
+        Ok(()) as anyhow::Result<()>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    Ok("Layout rolled back successfully")
+}
+
+#[derive(Serialize)]
+struct LayoutCodeResponse {
+    pub code: String,
+}
+
+async fn export_layout(
+    name: web::Path<String>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let name = name.into_inner();
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let (slot, blocks, buildings) = web::block(move || {
+        let active_map = util::fetch_map_layout(&mut conn, &defender_id)?;
+        Ok((
+            util::fetch_layout_slot(&mut conn, &defender_id, &active_map.level_id, &name)?,
+            util::fetch_blocks(&mut conn, &defender_id)?,
+            util::fetch_buildings(&mut conn)?,
+        ))
+            as anyhow::Result<(
+                Option<MapLayout>,
+                HashMap<i32, BlockType>,
+                Vec<BuildingType>,
+            )>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    let slot = match slot {
+        Some(slot) => slot,
+        None => return Err(LayoutError::SlotNotFound.into()),
+    };
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let map_spaces = web::block(move || util::fetch_map_spaces_entries(&mut conn, &slot.id))
+        .await?
+        .map_err(|err| error::handle_error(err.into()))?;
+
+    validate::is_valid_update_layout(&map_spaces, &blocks, &buildings)?;
+
+    let code = web::block(move || {
+        let mut conn = pool.get()?;
+        layout_code::encode_layout(&mut conn, &map_spaces)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    Ok(Json(LayoutCodeResponse { code }))
+}
+
+#[derive(Deserialize)]
+struct ImportLayoutRequest {
+    pub code: String,
+}
+
+#[derive(Serialize)]
+struct ImportLayoutResponse {
+    pub imported: usize,
+    pub missing: Vec<layout_code::LayoutCodeBlock>,
+}
+
+async fn import_layout(
+    name: web::Path<String>,
+    request: Json<ImportLayoutRequest>,
+    pool: Data<PgPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let defender_id = user.0;
+    let name = name.into_inner();
+    check_layout_name(&name)?;
+
+    let code_blocks =
+        layout_code::decode_layout(&request.code).map_err(error::handle_layout_error)?;
+
+    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
+    let ((map_spaces, missing), blocks, buildings) = web::block(move || {
+        Ok((
+            layout_code::resolve_layout(&mut conn, &defender_id, &code_blocks)?,
+            util::fetch_blocks(&mut conn, &defender_id)?,
+            util::fetch_buildings(&mut conn)?,
+        ))
+            as anyhow::Result<(
+                (Vec<MapSpacesEntry>, Vec<layout_code::LayoutCodeBlock>),
+                HashMap<i32, BlockType>,
+                Vec<BuildingType>,
+            )>
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    //imported layouts are saved as drafts, so they only need to fit on the map
+    validate::is_valid_update_layout(&map_spaces, &blocks, &buildings)?;
+
+    let imported = map_spaces.len();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        util::save_layout_draft(&mut conn, &defender_id, &name, &map_spaces)
+    })
+    .await?
+    .map_err(error::handle_layout_error)?;
+
+    Ok(Json(ImportLayoutResponse { imported, missing }))
+}
+
 async fn defense_history(
     user: AuthUser,
     query: web::Query<HistoryboardQuery>,
@@ -1,22 +1,181 @@
 use crate::constants::*;
 use crate::error::DieselError;
-use crate::schema::{block_type, map_spaces};
+use crate::models::NewLayoutNextHops;
+use crate::schema::{block_type, layout_next_hops, map_spaces};
 use crate::util::function;
-use crate::validator::util::{Coords, SourceDestXY};
+use crate::validator::util::{tile_index, Coords};
 use anyhow::Result;
-use array2d::Array2D;
 use diesel::prelude::*;
 use diesel::RunQueryDsl;
 use diesel::{PgConnection, QueryDsl};
-use std::collections::{HashMap, HashSet, VecDeque};
+use rayon::prelude::*;
+use std::collections::VecDeque;
 
-const NO_BLOCK: i32 = -1;
+//index of a road tile in NextHopMatrix::roads, a 40x40 map has at most 1600 roads
+type RoadIndex = u16;
+
+const NO_ROAD: RoadIndex = RoadIndex::MAX;
+
+//next hop from every road tile towards every other road tile
+pub struct NextHopMatrix {
+    roads: Vec<Coords>,
+    //road index of every tile of the map, row major
+    road_index: Vec<RoadIndex>,
+    //road index of the next hop, one row of roads.len() entries per source
+    next_hops: Vec<RoadIndex>,
+}
+
+impl NextHopMatrix {
+    fn with_roads(roads: &[(i32, i32)]) -> Self {
+        let mut road_index = vec![NO_ROAD; MAP_SIZE * MAP_SIZE];
+        let mut unique_roads: Vec<Coords> = Vec::new();
+        for &(x, y) in roads {
+            if let Some(tile) = tile_index(x, y) {
+                if road_index[tile] == NO_ROAD {
+                    road_index[tile] = unique_roads.len() as RoadIndex;
+                    unique_roads.push(Coords { x, y });
+                }
+            }
+        }
+
+        let size = unique_roads.len();
+        NextHopMatrix {
+            roads: unique_roads,
+            road_index,
+            next_hops: vec![NO_ROAD; size * size],
+        }
+    }
+
+    //breadth first search from every road, one source per rayon task
+    pub fn new(roads: &[(i32, i32)]) -> Self {
+        let mut matrix = NextHopMatrix::with_roads(roads);
+        let size = matrix.roads.len();
+        if size == 0 {
+            return matrix;
+        }
+
+        let neighbors: Vec<[RoadIndex; 4]> = matrix
+            .roads
+            .iter()
+            .map(|road| {
+                let mut road_neighbors = [NO_ROAD; 4];
+                for (neighbor, (dx, dy)) in
+                    road_neighbors
+                        .iter_mut()
+                        .zip([(1, 0), (0, 1), (-1, 0), (0, -1)])
+                {
+                    *neighbor = matrix
+                        .index_of(road.x + dx, road.y + dy)
+                        .map_or(NO_ROAD, |index| index as RoadIndex);
+                }
+                road_neighbors
+            })
+            .collect();
+
+        matrix
+            .next_hops
+            .par_chunks_mut(size)
+            .enumerate()
+            .for_each(|(source, row)| {
+                let mut queue: VecDeque<usize> = VecDeque::new();
+                for &neighbor in neighbors[source].iter().filter(|&&n| n != NO_ROAD) {
+                    let neighbor = neighbor as usize;
+                    if neighbor != source && row[neighbor] == NO_ROAD {
+                        row[neighbor] = neighbor as RoadIndex;
+                        queue.push_back(neighbor);
+                    }
+                }
+
+                //every other road inherits the first step taken towards it
+                while let Some(current) = queue.pop_front() {
+                    for &neighbor in neighbors[current].iter().filter(|&&n| n != NO_ROAD) {
+                        let neighbor = neighbor as usize;
+                        if neighbor != source && row[neighbor] == NO_ROAD {
+                            row[neighbor] = row[current];
+                            queue.push_back(neighbor);
+                        }
+                    }
+                }
+            });
+
+        matrix
+    }
+
+    //road count, then x and y of every road, then the next hops, all little endian
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + self.roads.len() * 2 + self.next_hops.len() * 2);
+        bytes.extend_from_slice(&(self.roads.len() as RoadIndex).to_le_bytes());
+        for road in self.roads.iter() {
+            bytes.push(road.x as u8);
+            bytes.push(road.y as u8);
+        }
+        for next_hop in self.next_hops.iter() {
+            bytes.extend_from_slice(&next_hop.to_le_bytes());
+        }
+        bytes
+    }
+
+    //None if the bytes were not written by to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (size, bytes) = bytes.split_at_checked(2)?;
+        let size = RoadIndex::from_le_bytes([size[0], size[1]]) as usize;
+        let (roads, next_hops) = bytes.split_at_checked(size * 2)?;
+        if next_hops.len() != size * size * 2 {
+            return None;
+        }
+
+        let roads: Vec<(i32, i32)> = roads
+            .chunks_exact(2)
+            .map(|road| (road[0] as i32, road[1] as i32))
+            .collect();
+        let mut matrix = NextHopMatrix::with_roads(&roads);
+        if matrix.roads.len() != size {
+            return None;
+        }
+        matrix.next_hops = next_hops
+            .chunks_exact(2)
+            .map(|next_hop| RoadIndex::from_le_bytes([next_hop[0], next_hop[1]]))
+            .collect();
+        if matrix
+            .next_hops
+            .iter()
+            .any(|&next_hop| next_hop != NO_ROAD && next_hop as usize >= size)
+        {
+            return None;
+        }
+
+        Some(matrix)
+    }
+
+    fn index_of(&self, x: i32, y: i32) -> Option<usize> {
+        tile_index(x, y)
+            .map(|tile| self.road_index[tile])
+            .filter(|&index| index != NO_ROAD)
+            .map(|index| index as usize)
+    }
+
+    pub fn next_hop(&self, source: Coords, dest: Coords) -> Option<Coords> {
+        let source = self.index_of(source.x, source.y)?;
+        let dest = self.index_of(dest.x, dest.y)?;
+        match self.next_hops[source * self.roads.len() + dest] {
+            NO_ROAD => None,
+            next_hop => Some(self.roads[next_hop as usize]),
+        }
+    }
+
+    //heap bytes held by the matrix
+    pub fn memory_usage(&self) -> usize {
+        self.roads.capacity() * std::mem::size_of::<Coords>()
+            + (self.road_index.capacity() + self.next_hops.capacity())
+                * std::mem::size_of::<RoadIndex>()
+    }
+}
 
 //running shortest path simulation
 pub fn run_shortest_paths(
     conn: &mut PgConnection,
     input_map_layout_id: i32,
-) -> Result<HashMap<SourceDestXY, Coords>> {
+) -> Result<NextHopMatrix> {
     let roads_list: Vec<(i32, i32)> = map_spaces::table
         .inner_join(block_type::table)
         .filter(map_spaces::map_id.eq(input_map_layout_id))
@@ -27,89 +186,102 @@ pub fn run_shortest_paths(
             table: "map_spaces",
             function: function!(),
             error: err,
-        })?
-        .to_vec();
+        })?;
 
-    let mut graph_2d = Array2D::filled_with(NO_BLOCK, MAP_SIZE, MAP_SIZE);
+    Ok(NextHopMatrix::new(&roads_list))
+}
 
-    for road in &roads_list {
-        let (road_x, road_y) = (road.0, road.1);
-        graph_2d
-            .set(road_x as usize, road_y as usize, ROAD_ID)
-            .unwrap();
-    }
+//computes and persists the next hops of a saved layout version
+pub fn store_shortest_paths(conn: &mut PgConnection, map_id: i32) -> Result<NextHopMatrix> {
+    let shortest_paths = run_shortest_paths(conn, map_id)?;
+    let next_hops = shortest_paths.to_bytes();
+    let new_layout_next_hops = NewLayoutNextHops {
+        map_id: &map_id,
+        next_hops: &next_hops,
+    };
 
-    let mut adjacency_list: HashMap<(i32, i32), Vec<(i32, i32)>> = HashMap::new();
-
-    for road in &roads_list {
-        let (road_x, road_y) = (road.0, road.1);
-        let mut neighbors = Vec::new();
-
-        for &(dx, dy) in &[(1, 0), (0, 1), (-1, 0), (0, -1)] {
-            let (nx, ny) = (road_x + dx, road_y + dy);
-            if nx >= 0
-                && ny >= 0
-                && (nx as usize) < MAP_SIZE
-                && (ny as usize) < MAP_SIZE
-                && graph_2d[(nx as usize, ny as usize)] == ROAD_ID
-            {
-                neighbors.push((nx, ny));
-            }
-        }
+    diesel::insert_into(layout_next_hops::table)
+        .values(&new_layout_next_hops)
+        .on_conflict(layout_next_hops::map_id)
+        .do_update()
+        .set(layout_next_hops::next_hops.eq(&next_hops))
+        .execute(conn)
+        .map_err(|err| DieselError {
+            table: "layout_next_hops",
+            function: function!(),
+            error: err,
+        })?;
 
-        adjacency_list.insert((road_x, road_y), neighbors);
-    }
+    Ok(shortest_paths)
+}
 
-    let mut shortest_paths: HashMap<SourceDestXY, Coords> = HashMap::new();
-
-    for (start_x, start_y) in &roads_list {
-        let start_node = (*start_x, *start_y);
-        let mut visited: HashSet<(i32, i32)> = HashSet::new();
-        let mut queue: VecDeque<((i32, i32), (i32, i32))> = VecDeque::new();
-
-        visited.insert(start_node);
-        queue.push_back((start_node, start_node));
-
-        while let Some((current_node, parent_node)) = queue.pop_front() {
-            for neighbor in &adjacency_list[&current_node] {
-                if visited.insert(*neighbor) {
-                    let next_hop = if start_node == parent_node {
-                        *neighbor
-                    } else {
-                        parent_node
-                    };
-
-                    queue.push_back((*neighbor, next_hop));
-
-                    shortest_paths.insert(
-                        SourceDestXY {
-                            source_x: *start_x,
-                            source_y: *start_y,
-                            dest_x: neighbor.0,
-                            dest_y: neighbor.1,
-                        },
-                        Coords {
-                            x: next_hop.0,
-                            y: next_hop.1,
-                        },
-                    );
-                }
-            }
+//a new version with the same roads, like a rollback, reuses the next hops of the old one
+pub fn copy_shortest_paths(
+    conn: &mut PgConnection,
+    from_map_id: i32,
+    to_map_id: i32,
+) -> Result<bool> {
+    let next_hops = layout_next_hops::table
+        .find(from_map_id)
+        .select(layout_next_hops::next_hops)
+        .first::<Vec<u8>>(conn)
+        .optional()
+        .map_err(|err| DieselError {
+            table: "layout_next_hops",
+            function: function!(),
+            error: err,
+        })?;
+
+    match next_hops {
+        Some(next_hops) => {
+            diesel::insert_into(layout_next_hops::table)
+                .values(&NewLayoutNextHops {
+                    map_id: &to_map_id,
+                    next_hops: &next_hops,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "layout_next_hops",
+                    function: function!(),
+                    error: err,
+                })?;
+            Ok(true)
         }
+        None => Ok(false),
     }
+}
 
-    // // Writing entries to shortest_path table
-    // let chunks: Vec<&[NewShortestPath]> = shortest_paths.chunks(1000).collect();
-    // for chunk in chunks {
-    //     diesel::insert_into(shortest_path::table)
-    //         .values(chunk)
-    //         .execute(conn)
-    //         .map_err(|err| DieselError {
-    //             table: "shortest_path",
-    //             function: function!(),
-    //             error: err,
-    //         })?;
-    // }
+//stores the next hops of a version unless they already are
+pub fn ensure_shortest_paths(conn: &mut PgConnection, map_id: i32) -> Result<()> {
+    let is_stored = diesel::select(diesel::dsl::exists(layout_next_hops::table.find(map_id)))
+        .get_result::<bool>(conn)
+        .map_err(|err| DieselError {
+            table: "layout_next_hops",
+            function: function!(),
+            error: err,
+        })?;
+    if !is_stored {
+        store_shortest_paths(conn, map_id)?;
+    }
+    Ok(())
+}
 
-    Ok(shortest_paths)
+//loads the stored next hops, computing and storing them for versions saved without any
+pub fn get_shortest_paths(conn: &mut PgConnection, map_id: i32) -> Result<NextHopMatrix> {
+    let next_hops = layout_next_hops::table
+        .find(map_id)
+        .select(layout_next_hops::next_hops)
+        .first::<Vec<u8>>(conn)
+        .optional()
+        .map_err(|err| DieselError {
+            table: "layout_next_hops",
+            function: function!(),
+            error: err,
+        })?;
+
+    match next_hops.as_deref().and_then(NextHopMatrix::from_bytes) {
+        Some(shortest_paths) => Ok(shortest_paths),
+        None => store_shortest_paths(conn, map_id),
+    }
 }
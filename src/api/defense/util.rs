@@ -1,22 +1,26 @@
 /// CRUD functions
-use super::MapSpacesEntry;
+use super::{MapSpacesEntry, TransferArtifactResponse};
 use crate::api::auth::LoginResponse;
-use crate::api::error::AuthError;
+use crate::api::error::{AuthError, LayoutError, SpendError};
 use crate::api::game::util::UserDetail;
 use crate::api::user::util::fetch_user;
 use crate::api::util::GameHistoryEntry;
-use crate::api::util::{HistoryboardEntry, HistoryboardResponse};
+use crate::api::util::{lock_player_funds, HistoryboardEntry, HistoryboardResponse};
 use crate::api::{self};
-use crate::constants::{BANK_BUILDING_NAME, INITIAL_ARTIFACTS, INITIAL_RATING, ROAD_ID};
+use crate::constants::{
+    BANK_BUILDING_NAME, DEFAULT_LAYOUT_NAME, INITIAL_ARTIFACTS, INITIAL_RATING, MAX_LAYOUT_SLOTS,
+    MAX_LAYOUT_VERSIONS, ROAD_ID,
+};
 use crate::models::*;
 use crate::util::function;
+use crate::validator::fog::{BlockFootprint, FogOfWar};
 use crate::{api::util::GameHistoryResponse, error::DieselError};
 use anyhow::{Ok, Result};
 use diesel::dsl::exists;
 use diesel::{prelude::*, select};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Serialize, Clone)]
 pub struct MapSpacesResponseWithArifacts {
@@ -41,6 +45,8 @@ pub struct MineTypeResponseWithoutBlockId {
     pub level: i32,
     pub cost: i32,
     pub name: String,
+    pub effect: MineEffect,
+    pub effect_duration: i32,
 }
 
 #[derive(Serialize, Clone)]
@@ -52,6 +58,8 @@ pub struct MineTypeResponse {
     pub level: i32,
     pub cost: i32,
     pub name: String,
+    pub effect: MineEffect,
+    pub effect_duration: i32,
 }
 
 #[derive(Serialize, Clone)]
@@ -64,6 +72,8 @@ pub struct DefenderTypeResponse {
     pub name: String,
     pub level: i32,
     pub cost: i32,
+    //set for defenders that fire projectiles instead of running into the attacker
+    pub attack_type_id: Option<i32>,
 }
 
 #[derive(Serialize, Clone)]
@@ -77,6 +87,7 @@ pub struct BuildingTypeResponse {
     pub capacity: i32,
     pub block_id: i32,
     pub hp: i32,
+    pub is_wall: bool,
 }
 
 #[derive(Serialize)]
@@ -135,6 +146,7 @@ pub fn check_valid_map_id(
 
     let map_layout_id = map_layout::table
         .filter(map_layout::player.eq(player))
+        .filter(map_layout::is_active.eq(true))
         .inner_join(map_spaces::table.on(map_layout::id.eq(map_spaces::map_id)))
         .filter(map_spaces::id.eq(map_space_id))
         .select(map_layout::id)
@@ -161,46 +173,91 @@ pub fn defender_exists(defender: i32, conn: &mut PgConnection) -> Result<bool> {
 
 pub fn transfer_artifacts_building(
     conn: &mut PgConnection,
+    player_id: &i32,
     building_map_space_id: &i32,
-    bank_map_space_id: &i32,
-    new_building_artifact_count: &i32,
-    new_bank_artifact_count: &i32,
-) -> Result<()> {
+    artifacts_differ: &i32,
+) -> Result<TransferArtifactResponse> {
     use crate::schema::artifact;
 
-    diesel::update(artifact::table.filter(artifact::map_space_id.eq(bank_map_space_id)))
-        .set(artifact::count.eq(new_bank_artifact_count))
-        .execute(conn)
-        .map_err(|err| DieselError {
-            table: "map_spaces",
-            function: function!(),
-            error: err,
-        })
-        .unwrap();
+    conn.transaction(|conn| {
+        let mut funds = lock_player_funds(conn, *player_id)?;
 
-    if *new_building_artifact_count == 0 {
-        diesel::delete(
-            artifact::dsl::artifact.filter(artifact::dsl::map_space_id.eq(building_map_space_id)),
-        )
-        .execute(conn)
-        .map_err(|err| DieselError {
-            table: "shortest_path",
-            function: function!(),
-            error: err,
-        })?;
-        Ok(())
-    } else {
-        diesel::update(artifact::table.filter(artifact::map_space_id.eq(building_map_space_id)))
-            .set(artifact::count.eq(new_building_artifact_count))
+        //make sure the building has an artifact row to lock
+        diesel::insert_into(artifact::table)
+            .values(&NewArtifact {
+                map_space_id: *building_map_space_id,
+                count: 0,
+            })
+            .on_conflict_do_nothing()
             .execute(conn)
             .map_err(|err| DieselError {
-                table: "map_spaces",
+                table: "artifact",
                 function: function!(),
                 error: err,
-            })
-            .unwrap();
-        Ok(())
-    }
+            })?;
+
+        let building_artifact_count = artifact::table
+            .find(building_map_space_id)
+            .select(artifact::count)
+            .for_update()
+            .first::<i32>(conn)
+            .map_err(|err| DieselError {
+                table: "artifact",
+                function: function!(),
+                error: err,
+            })?;
+
+        if *artifacts_differ > funds.bank_artifacts {
+            return Err(SpendError::NotEnoughArtifactsInBank.into());
+        }
+        if artifacts_differ + building_artifact_count < 0 {
+            return Err(SpendError::NotEnoughArtifactsInBuilding.into());
+        }
+
+        let building_capacity = get_building_capacity(conn, building_map_space_id)?;
+        if building_capacity < artifacts_differ + building_artifact_count {
+            return Err(SpendError::BuildingCapacityExceeded.into());
+        }
+
+        let new_building_artifact_count = building_artifact_count + artifacts_differ;
+        let new_bank_artifact_count = funds.bank_artifacts - artifacts_differ;
+
+        diesel::update(artifact::table.find(funds.bank_map_space_id))
+            .set(artifact::count.eq(new_bank_artifact_count))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "artifact",
+                function: function!(),
+                error: err,
+            })?;
+        funds.bank_artifacts = new_bank_artifact_count;
+
+        if new_building_artifact_count == 0 {
+            diesel::delete(artifact::table.find(building_map_space_id))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "artifact",
+                    function: function!(),
+                    error: err,
+                })?;
+        } else {
+            diesel::update(artifact::table.find(building_map_space_id))
+                .set(artifact::count.eq(new_building_artifact_count))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "artifact",
+                    function: function!(),
+                    error: err,
+                })?;
+        }
+
+        Ok(TransferArtifactResponse {
+            building_map_space_id: *building_map_space_id,
+            artifacts_in_building: new_building_artifact_count,
+            bank_map_space_id: funds.bank_map_space_id,
+            artifacts_in_bank: funds.bank_artifacts,
+        })
+    })
 }
 
 pub fn create_artifact_record(
@@ -340,7 +397,50 @@ pub fn fetch_map_layout(conn: &mut PgConnection, player: &i32) -> Result<MapLayo
 
     let layout = map_layout::table
         .filter(map_layout::player.eq(player))
+        .filter(map_layout::is_active.eq(true))
+        .first::<MapLayout>(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+    Ok(layout)
+}
+
+pub fn fetch_layout_slots(conn: &mut PgConnection, player: &i32) -> Result<Vec<MapLayout>> {
+    use crate::schema::map_layout;
+
+    let layouts = map_layout::table
+        .filter(map_layout::player.eq(player))
+        .filter(map_layout::is_latest.eq(true))
+        .order_by(map_layout::id)
+        .load::<MapLayout>(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+    Ok(layouts)
+}
+
+//slots live in the level of the active layout
+pub fn fetch_layout_slot(
+    conn: &mut PgConnection,
+    player: &i32,
+    level_id: &i32,
+    name: &str,
+) -> Result<Option<MapLayout>> {
+    use crate::schema::map_layout;
+
+    let layout = map_layout::table
+        .filter(map_layout::player.eq(player))
+        .filter(map_layout::level_id.eq(level_id))
+        .filter(map_layout::name.eq(name))
+        .filter(map_layout::is_latest.eq(true))
         .first::<MapLayout>(conn)
+        .optional()
         .map_err(|err| DieselError {
             table: "map_layout",
             function: function!(),
@@ -350,6 +450,122 @@ pub fn fetch_map_layout(conn: &mut PgConnection, player: &i32) -> Result<MapLayo
     Ok(layout)
 }
 
+pub fn create_layout_slot(
+    conn: &mut PgConnection,
+    player: &i32,
+    level_id: &i32,
+    name: &str,
+    maps: &[MapSpacesEntry],
+) -> Result<MapLayout> {
+    use crate::schema::map_layout;
+
+    let slots_used: i64 = map_layout::table
+        .filter(map_layout::player.eq(player))
+        .filter(map_layout::level_id.eq(level_id))
+        .filter(map_layout::is_latest.eq(true))
+        .count()
+        .get_result(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+    if slots_used >= MAX_LAYOUT_SLOTS {
+        return Err(LayoutError::AllSlotsInUse.into());
+    }
+
+    let new_map_layout = NewMapLayout {
+        player,
+        level_id,
+        is_valid: &false,
+        name,
+        is_active: &false,
+        version: &1,
+        is_latest: &true,
+    };
+
+    conn.transaction(|conn| insert_layout_version(conn, &new_map_layout, maps))
+}
+
+//saves into a new or existing draft slot of the active level
+pub fn save_layout_draft(
+    conn: &mut PgConnection,
+    player: &i32,
+    name: &str,
+    maps: &[MapSpacesEntry],
+) -> Result<MapLayout> {
+    let active_map = fetch_map_layout(conn, player)?;
+    match fetch_layout_slot(conn, player, &active_map.level_id, name)? {
+        Some(slot) if slot.is_active => Err(LayoutError::ActiveLayoutNotDraft.into()),
+        Some(slot) => put_base_details(maps, &slot, false, conn),
+        None => create_layout_slot(conn, player, &active_map.level_id, name, maps),
+    }
+}
+
+//map spaces of a layout in the same shape as they are saved
+pub fn fetch_map_spaces_entries(
+    conn: &mut PgConnection,
+    map_id: &i32,
+) -> Result<Vec<MapSpacesEntry>> {
+    use crate::schema::{artifact, map_spaces};
+
+    let entries = map_spaces::table
+        .left_join(artifact::table)
+        .filter(map_spaces::map_id.eq(map_id))
+        .select((map_spaces::all_columns, artifact::count.nullable()))
+        .load::<(MapSpaces, Option<i32>)>(conn)
+        .map_err(|err| DieselError {
+            table: "map_spaces",
+            function: function!(),
+            error: err,
+        })?
+        .into_iter()
+        .map(|(map_space, artifacts)| MapSpacesEntry {
+            x_coordinate: map_space.x_coordinate,
+            y_coordinate: map_space.y_coordinate,
+            block_type_id: map_space.block_type_id,
+            artifacts: artifacts.unwrap_or(0),
+        })
+        .collect();
+
+    Ok(entries)
+}
+
+//makes the given layout the one being defended, it must already be validated
+pub fn activate_layout_slot(conn: &mut PgConnection, player: &i32, map_id: &i32) -> Result<()> {
+    use crate::schema::map_layout;
+
+    conn.transaction(|conn| {
+        diesel::update(
+            map_layout::table
+                .filter(map_layout::player.eq(player))
+                .filter(map_layout::is_active.eq(true)),
+        )
+        .set(map_layout::is_active.eq(false))
+        .execute(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+        diesel::update(map_layout::table.find(map_id))
+            .set((
+                map_layout::is_active.eq(true),
+                map_layout::is_valid.eq(true),
+            ))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "map_layout",
+                function: function!(),
+                error: err,
+            })?;
+
+        Ok(())
+    })
+}
+
 pub fn fetch_map_layout_from_game(
     conn: &mut PgConnection,
     game_id: i32,
@@ -439,36 +655,53 @@ pub fn get_details_from_map_layout(
     })
 }
 
-pub fn get_map_details_for_attack(
+//map spaces as the attacker sees them, with mines shown as roads
+pub fn fetch_attack_map_spaces(
     conn: &mut PgConnection,
-    map: MapLayout,
-    attacker_id: i32,
-) -> Result<DefenseResponse> {
-    use crate::schema::{
-        available_blocks, block_type, emp_type, level_constraints, levels_fixture, map_spaces,
-    };
+    map_id: i32,
+) -> Result<Vec<BlockFootprint>> {
+    use crate::schema::{block_type, building_type, map_spaces};
 
-    let map_spaces = map_spaces::table
-        .inner_join(block_type::table)
-        .filter(map_spaces::map_id.eq(map.id))
-        .load::<(MapSpaces, BlockType)>(conn)
+    Ok(map_spaces::table
+        .inner_join(block_type::table.inner_join(building_type::table))
+        .filter(map_spaces::map_id.eq(map_id))
+        .load::<(MapSpaces, (BlockType, BuildingType))>(conn)
         .map_err(|err| DieselError {
             table: "map_spaces",
             function: function!(),
             error: err,
         })?
         .into_iter()
-        .map(|(mut map_space, block_type)| {
-            if block_type.building_type == ROAD_ID {
-                if block_type.category == BlockCategory::Mine {
-                    map_space.block_type_id = ROAD_ID;
-                }
-                map_space
-            } else {
-                map_space
+        .map(|(mut map_space, (block_type, building_type))| {
+            if block_type.building_type == ROAD_ID && block_type.category == BlockCategory::Mine {
+                map_space.block_type_id = ROAD_ID;
+            }
+            BlockFootprint {
+                map_space,
+                width: building_type.width,
+                height: building_type.height,
             }
         })
-        .collect();
+        .collect())
+}
+
+pub fn get_map_details_for_attack(
+    conn: &mut PgConnection,
+    map: MapLayout,
+    attacker_id: i32,
+    fog_of_war: bool,
+) -> Result<DefenseResponse> {
+    use crate::schema::{available_blocks, emp_type, level_constraints, levels_fixture};
+
+    let footprints = fetch_attack_map_spaces(conn, map.id)?;
+    let map_spaces = if fog_of_war {
+        FogOfWar::new(footprints).1
+    } else {
+        footprints
+            .into_iter()
+            .map(|footprint| footprint.map_space)
+            .collect()
+    };
     let blocks = fetch_building_blocks(conn, &map.player)?;
     let levels_fixture = levels_fixture::table
         .find(map.level_id)
@@ -586,31 +819,295 @@ pub fn fetch_buildings(conn: &mut PgConnection) -> Result<Vec<BuildingType>> {
         })?)
 }
 
+//every save inserts the next version of the slot, item changes rewrite only the latest one
 pub fn put_base_details(
     maps: &[MapSpacesEntry],
     map: &MapLayout,
+    is_valid: bool,
     conn: &mut PgConnection,
-) -> Result<()> {
-    use crate::schema::artifact;
-    use crate::schema::map_spaces::dsl::*;
+) -> Result<MapLayout> {
+    use crate::schema::map_layout;
 
-    diesel::delete(artifact::table)
-        .filter(artifact::map_space_id.eq_any(map_spaces.filter(map_id.eq(map.id)).select(id)))
+    conn.transaction(|conn| {
+        diesel::update(map_layout::table.find(map.id))
+            .set((
+                map_layout::is_latest.eq(false),
+                map_layout::is_active.eq(false),
+            ))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "map_layout",
+                function: function!(),
+                error: err,
+            })?;
+
+        let new_map_layout = NewMapLayout {
+            player: &map.player,
+            level_id: &map.level_id,
+            is_valid: &is_valid,
+            name: &map.name,
+            is_active: &map.is_active,
+            version: &(map.version + 1),
+            is_latest: &true,
+        };
+
+        let new_map = insert_layout_version(conn, &new_map_layout, maps)?;
+        prune_layout_versions(conn, &new_map)?;
+        Ok(new_map)
+    })
+}
+
+//latest versions that have the block placed, item changes rewrite these in place
+pub fn get_latest_layouts_with_block(
+    conn: &mut PgConnection,
+    player: &i32,
+    block_type_id: &i32,
+) -> Result<Vec<i32>> {
+    use crate::schema::{map_layout, map_spaces};
+
+    let ids_of_maps = map_layout::table
+        .inner_join(map_spaces::table)
+        .filter(map_layout::player.eq(player))
+        .filter(map_layout::is_latest.eq(true))
+        .filter(map_spaces::block_type_id.eq(block_type_id))
+        .select(map_layout::id)
+        .distinct()
+        .load::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+    Ok(ids_of_maps)
+}
+
+//drops the oldest versions of a slot beyond the cap, versions played in a game are kept
+fn prune_layout_versions(conn: &mut PgConnection, map: &MapLayout) -> Result<()> {
+    use crate::schema::{artifact, game, layout_next_hops, map_layout, map_spaces};
+
+    let stale_ids: Vec<i32> = map_layout::table
+        .filter(map_layout::player.eq(map.player))
+        .filter(map_layout::level_id.eq(map.level_id))
+        .filter(map_layout::name.eq(&map.name))
+        .filter(map_layout::is_latest.eq(false))
+        .filter(map_layout::is_active.eq(false))
+        .filter(map_layout::version.le(map.version - MAX_LAYOUT_VERSIONS))
+        .select(map_layout::id)
+        .load::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+    if stale_ids.is_empty() {
+        return Ok(());
+    }
+
+    let played_ids: HashSet<i32> = game::table
+        .filter(game::map_layout_id.eq_any(&stale_ids))
+        .select(game::map_layout_id)
+        .load::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "game",
+            function: function!(),
+            error: err,
+        })?
+        .into_iter()
+        .collect();
+    let stale_ids: Vec<i32> = stale_ids
+        .into_iter()
+        .filter(|id| !played_ids.contains(id))
+        .collect();
+
+    let stale_map_spaces = map_spaces::table
+        .filter(map_spaces::map_id.eq_any(&stale_ids))
+        .select(map_spaces::id);
+    diesel::delete(artifact::table.filter(artifact::map_space_id.eq_any(stale_map_spaces)))
         .execute(conn)
         .map_err(|err| DieselError {
             table: "artifact",
             function: function!(),
             error: err,
         })?;
-
-    diesel::delete(map_spaces)
-        .filter(map_id.eq(map.id))
+    diesel::delete(map_spaces::table.filter(map_spaces::map_id.eq_any(&stale_ids)))
         .execute(conn)
         .map_err(|err| DieselError {
             table: "map_spaces",
             function: function!(),
             error: err,
         })?;
+    diesel::delete(layout_next_hops::table.filter(layout_next_hops::map_id.eq_any(&stale_ids)))
+        .execute(conn)
+        .map_err(|err| DieselError {
+            table: "layout_next_hops",
+            function: function!(),
+            error: err,
+        })?;
+    diesel::delete(map_layout::table.filter(map_layout::id.eq_any(&stale_ids)))
+        .execute(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+    Ok(())
+}
+
+pub fn fetch_layout_versions(
+    conn: &mut PgConnection,
+    player: &i32,
+    level_id: &i32,
+    name: &str,
+) -> Result<Vec<MapLayout>> {
+    use crate::schema::map_layout;
+
+    let versions = map_layout::table
+        .filter(map_layout::player.eq(player))
+        .filter(map_layout::level_id.eq(level_id))
+        .filter(map_layout::name.eq(name))
+        .order_by(map_layout::version.desc())
+        .load::<MapLayout>(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+    Ok(versions)
+}
+
+pub fn fetch_layout_version(
+    conn: &mut PgConnection,
+    player: &i32,
+    level_id: &i32,
+    name: &str,
+    version: &i32,
+) -> Result<Option<MapLayout>> {
+    use crate::schema::map_layout;
+
+    let layout = map_layout::table
+        .filter(map_layout::player.eq(player))
+        .filter(map_layout::level_id.eq(level_id))
+        .filter(map_layout::name.eq(name))
+        .filter(map_layout::version.eq(version))
+        .first::<MapLayout>(conn)
+        .optional()
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
+
+    Ok(layout)
+}
+
+#[derive(Serialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct LayoutBlock {
+    pub x_coordinate: i32,
+    pub y_coordinate: i32,
+    pub block_type_id: i32,
+}
+
+#[derive(Serialize)]
+pub struct LayoutDiffResponse {
+    pub from_version: i32,
+    pub to_version: i32,
+    pub added: Vec<LayoutBlock>,
+    pub removed: Vec<LayoutBlock>,
+}
+
+//blocks placed in `to` but not in `from` are added, the reverse are removed
+pub fn diff_layout_versions(
+    conn: &mut PgConnection,
+    from: &MapLayout,
+    to: &MapLayout,
+) -> Result<LayoutDiffResponse> {
+    let to_layout_block = |entry: &MapSpacesEntry| LayoutBlock {
+        x_coordinate: entry.x_coordinate,
+        y_coordinate: entry.y_coordinate,
+        block_type_id: entry.block_type_id,
+    };
+    let from_blocks: HashSet<LayoutBlock> = fetch_map_spaces_entries(conn, &from.id)?
+        .iter()
+        .map(to_layout_block)
+        .collect();
+    let to_blocks: HashSet<LayoutBlock> = fetch_map_spaces_entries(conn, &to.id)?
+        .iter()
+        .map(to_layout_block)
+        .collect();
+
+    let mut added: Vec<LayoutBlock> = to_blocks.difference(&from_blocks).copied().collect();
+    let mut removed: Vec<LayoutBlock> = from_blocks.difference(&to_blocks).copied().collect();
+    added.sort_by_key(|block| (block.x_coordinate, block.y_coordinate));
+    removed.sort_by_key(|block| (block.x_coordinate, block.y_coordinate));
+
+    Ok(LayoutDiffResponse {
+        from_version: from.version,
+        to_version: to.version,
+        added,
+        removed,
+    })
+}
+
+//blocks of an old version with the artifacts of the latest version carried over
+pub fn get_rollback_entries(
+    conn: &mut PgConnection,
+    player: &i32,
+    latest: &MapLayout,
+    target: &MapLayout,
+) -> Result<Vec<MapSpacesEntry>> {
+    let latest_entries = fetch_map_spaces_entries(conn, &latest.id)?;
+    let mut artifacts_at: HashMap<(i32, i32, i32), i32> = latest_entries
+        .iter()
+        .filter(|entry| entry.artifacts > 0)
+        .map(|entry| {
+            (
+                (entry.x_coordinate, entry.y_coordinate, entry.block_type_id),
+                entry.artifacts,
+            )
+        })
+        .collect();
+
+    let mut entries = fetch_map_spaces_entries(conn, &target.id)?;
+    for entry in entries.iter_mut() {
+        entry.artifacts = artifacts_at
+            .remove(&(entry.x_coordinate, entry.y_coordinate, entry.block_type_id))
+            .unwrap_or(0);
+    }
+
+    //artifacts of buildings missing in the old version go to the bank
+    let leftover_artifacts: i32 = artifacts_at.values().sum();
+    if leftover_artifacts > 0 {
+        let bank_block_type_id = get_block_id_of_bank(conn, player)?;
+        match entries
+            .iter_mut()
+            .find(|entry| entry.block_type_id == bank_block_type_id)
+        {
+            Some(bank) => bank.artifacts += leftover_artifacts,
+            None => return Err(LayoutError::VersionWithoutBank.into()),
+        }
+    }
+
+    Ok(entries)
+}
+
+fn insert_layout_version(
+    conn: &mut PgConnection,
+    new_map_layout: &NewMapLayout,
+    maps: &[MapSpacesEntry],
+) -> Result<MapLayout> {
+    use crate::schema::{artifact, map_layout, map_spaces};
+
+    let map: MapLayout = diesel::insert_into(map_layout::table)
+        .values(new_map_layout)
+        .get_result(conn)
+        .map_err(|err| DieselError {
+            table: "map_layout",
+            function: function!(),
+            error: err,
+        })?;
 
     let m: Vec<NewMapSpaces> = maps
         .iter()
@@ -622,7 +1119,7 @@ pub fn put_base_details(
         })
         .collect();
 
-    let result: Vec<MapSpaces> = diesel::insert_into(map_spaces)
+    let result: Vec<MapSpaces> = diesel::insert_into(map_spaces::table)
         .values(m)
         .on_conflict_do_nothing()
         .get_results(conn)
@@ -664,7 +1161,7 @@ pub fn put_base_details(
             error: err,
         })?;
 
-    Ok(())
+    Ok(map)
 }
 
 pub fn get_level_constraints(
@@ -809,6 +1306,8 @@ pub fn fetch_mine_types(conn: &mut PgConnection, user_id: &i32) -> Result<Vec<Mi
                 cost: mine_type.cost,
                 level: mine_type.level,
                 name: mine_type.name,
+                effect: mine_type.effect,
+                effect_duration: mine_type.effect_duration,
             })
         })
         .collect();
@@ -842,6 +1341,7 @@ pub fn fetch_defender_types(
                 name: defender_type.name,
                 level: defender_type.level,
                 cost: defender_type.cost,
+                attack_type_id: defender_type.attack_type_id,
             })
         })
         .collect();
@@ -857,7 +1357,7 @@ pub fn fetch_building_blocks(
     let joined_table = available_blocks::table
         .inner_join(block_type::table.inner_join(building_type::table))
         .filter(available_blocks::user_id.eq(user_id))
-        .filter(block_type::category.eq(BlockCategory::Building));
+        .filter(block_type::category.eq_any([BlockCategory::Building, BlockCategory::Wall]));
 
     let buildings: Vec<BuildingTypeResponse> = joined_table
         .load::<(AvailableBlocks, (BlockType, BuildingType))>(conn)
@@ -877,6 +1377,7 @@ pub fn fetch_building_blocks(
             capacity: building_type.capacity,
             block_id: block_type.id,
             hp: building_type.hp,
+            is_wall: block_type.category == BlockCategory::Wall,
         })
         .collect();
     Ok(buildings)
@@ -977,6 +1478,10 @@ pub fn add_user_default_base(
             player: &user.id,
             level_id,
             is_valid: &true,
+            name: DEFAULT_LAYOUT_NAME,
+            is_active: &true,
+            version: &1,
+            is_latest: &true,
         };
 
         let map_layout: MapLayout = diesel::insert_into(map_layout::table)
@@ -990,7 +1495,8 @@ pub fn add_user_default_base(
 
         let joined_table = user::table
             .filter(user::id.eq(bot_user_id))
-            .inner_join(map_layout::table.inner_join(map_spaces::table.left_join(artifact::table)));
+            .inner_join(map_layout::table.inner_join(map_spaces::table.left_join(artifact::table)))
+            .filter(map_layout::is_active.eq(true));
 
         let mut artifact_map: HashMap<(i32, i32), i32> = HashMap::new();
 
@@ -1074,6 +1580,7 @@ pub fn add_user_default_base(
                 user_id: user.id,
                 category: available_block.category,
                 emp_type_id: available_block.emp_type_id,
+                artifacts_spent: 0,
             })
             .collect();
 
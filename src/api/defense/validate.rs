@@ -5,14 +5,73 @@ use super::{
 };
 use crate::{api::error::BaseInvalidError, constants::*, models::*};
 use petgraph::{self, algo::tarjan_scc, prelude::*, Graph};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
 
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DiagnosticSeverity {
+    //the layout cannot be saved at all, not even as a draft
+    Error,
+    //the layout can be kept as a draft but cannot be defended
+    Warning,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LayoutDiagnostic {
+    #[serde(skip)]
+    pub error: BaseInvalidError,
+    pub code: &'static str,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    pub coordinates: Vec<(i32, i32)>,
+    pub block_type_id: Option<i32>,
+    pub block_name: Option<String>,
+    pub suggested_fix: String,
+}
+
+fn diagnostic(
+    error: BaseInvalidError,
+    severity: DiagnosticSeverity,
+    coordinates: Vec<(i32, i32)>,
+    block_type_id: Option<i32>,
+    suggested_fix: String,
+) -> LayoutDiagnostic {
+    LayoutDiagnostic {
+        code: error.code(),
+        message: error.message(),
+        error,
+        severity,
+        coordinates,
+        block_type_id,
+        block_name: None,
+        suggested_fix,
+    }
+}
+
+fn first_error(diagnostics: Vec<LayoutDiagnostic>) -> Result<(), BaseInvalidError> {
+    match diagnostics.into_iter().next() {
+        Some(diagnostic) => Err(diagnostic.error),
+        None => Ok(()),
+    }
+}
+
 //checks overlaps of blocks and also within map size
 pub fn is_valid_update_layout(
     map_spaces: &[MapSpacesEntry],
     blocks: &HashMap<i32, BlockType>,
     buildings: &[BuildingType],
 ) -> Result<(), BaseInvalidError> {
+    first_error(diagnose_update_layout(map_spaces, blocks, buildings))
+}
+
+//runs every rule of is_valid_update_layout without stopping at the first problem
+pub fn diagnose_update_layout(
+    map_spaces: &[MapSpacesEntry],
+    blocks: &HashMap<i32, BlockType>,
+    buildings: &[BuildingType],
+) -> Vec<LayoutDiagnostic> {
+    let mut diagnostics = Vec::new();
     let mut occupied_positions: HashSet<(i32, i32)> = HashSet::new();
     let mut _road_positions: HashSet<(i32, i32)> = HashSet::new();
     let buildings: HashMap<i32, BuildingType> = buildings
@@ -21,24 +80,37 @@ pub fn is_valid_update_layout(
         .collect();
     for map_space in map_spaces {
         let block_type = map_space.block_type_id;
-
-        if !blocks.contains_key(&block_type) {
-            return Err(BaseInvalidError::InvalidBuildingType(block_type));
-        }
-        let block = blocks.get(&block_type).unwrap();
+        let (x, y) = (map_space.x_coordinate, map_space.y_coordinate);
+
+        let block = match blocks.get(&block_type) {
+            Some(block) => block,
+            None => {
+                diagnostics.push(diagnostic(
+                    BaseInvalidError::InvalidBuildingType(block_type),
+                    DiagnosticSeverity::Error,
+                    vec![(x, y)],
+                    Some(block_type),
+                    "Remove this block, you do not own it".to_string(),
+                ));
+                continue;
+            }
+        };
 
         let building_type = block.building_type;
-        if !buildings.contains_key(&building_type) {
-            return Err(BaseInvalidError::InvalidBlockType(building_type));
-        }
-
-        let building: &BuildingType = buildings.get(&building_type).unwrap();
-        let (x, y, width, height) = (
-            map_space.x_coordinate,
-            map_space.y_coordinate,
-            building.width,
-            building.height,
-        );
+        let building: &BuildingType = match buildings.get(&building_type) {
+            Some(building) => building,
+            None => {
+                diagnostics.push(diagnostic(
+                    BaseInvalidError::InvalidBlockType(building_type),
+                    DiagnosticSeverity::Error,
+                    vec![(x, y)],
+                    Some(block_type),
+                    "Remove this block".to_string(),
+                ));
+                continue;
+            }
+        };
+        let (width, height) = (building.width, building.height);
         /*if x == -1 && building_type != ROAD_ID {
             return Err(BaseInvalidError::InvalidRotation(
                 buildings[&building_type].name.clone(),
@@ -46,20 +118,39 @@ pub fn is_valid_update_layout(
             ));
         }*/
 
+        let mut is_outside_map = false;
+        let mut overlapping_positions = Vec::new();
         for i in 0..width {
             for j in 0..height {
                 if (0..MAP_SIZE as i32).contains(&(x + i))
                     && (0..MAP_SIZE as i32).contains(&(y + j))
                 {
-                    if occupied_positions.contains(&(x + i, y + j)) {
-                        return Err(BaseInvalidError::OverlappingBlocks);
+                    if !occupied_positions.insert((x + i, y + j)) {
+                        overlapping_positions.push((x + i, y + j));
                     }
-                    occupied_positions.insert((x + i, y + j));
                 } else {
-                    return Err(BaseInvalidError::BlockOutsideMap);
+                    is_outside_map = true;
                 }
             }
         }
+        if is_outside_map {
+            diagnostics.push(diagnostic(
+                BaseInvalidError::BlockOutsideMap,
+                DiagnosticSeverity::Error,
+                vec![(x, y)],
+                Some(block_type),
+                format!("Move all {width}x{height} tiles of the block inside the {MAP_SIZE}x{MAP_SIZE} map"),
+            ));
+        }
+        if !overlapping_positions.is_empty() {
+            diagnostics.push(diagnostic(
+                BaseInvalidError::OverlappingBlocks,
+                DiagnosticSeverity::Error,
+                overlapping_positions,
+                Some(block_type),
+                "Move the block to free tiles".to_string(),
+            ));
+        }
         // if building_type == ROAD_ID {
         //     road_positions.insert((map_space.x_coordinate, map_space.y_coordinate));
         // }
@@ -68,7 +159,7 @@ pub fn is_valid_update_layout(
     //     return Err(BaseInvalidError::RoundRoad);
     // }
 
-    Ok(())
+    diagnostics
 }
 
 // checks every 4x4 tiles has completely Roads
@@ -102,7 +193,28 @@ pub fn is_valid_save_layout(
     mines: &[MineTypeResponse],
     user_artifacts: &i32,
 ) -> Result<(), BaseInvalidError> {
-    is_valid_update_layout(map_spaces, blocks, buildings)?;
+    first_error(diagnose_save_layout(
+        map_spaces,
+        block_constraints,
+        blocks,
+        buildings,
+        defenders,
+        mines,
+        user_artifacts,
+    ))
+}
+
+//runs every rule of is_valid_save_layout without stopping at the first problem
+pub fn diagnose_save_layout(
+    map_spaces: &[MapSpacesEntry],
+    block_constraints: &mut HashMap<i32, i32>,
+    blocks: &HashMap<i32, BlockType>,
+    buildings: &[BuildingType],
+    defenders: &[DefenderTypeResponse],
+    mines: &[MineTypeResponse],
+    user_artifacts: &i32,
+) -> Vec<LayoutDiagnostic> {
+    let mut diagnostics = diagnose_update_layout(map_spaces, blocks, buildings);
 
     // let mut graph: Graph<(), (), Directed> = Graph::new();
     let mut road_graph: Graph<(), (), Directed> = Graph::new();
@@ -124,7 +236,21 @@ pub fn is_valid_save_layout(
     let mines: HashMap<i32, MineTypeResponse> =
         mines.iter().map(|mine| (mine.id, mine.clone())).collect();
 
-    let mut map_buildings: Vec<(i32, i32, i32)> = Vec::new();
+    let block_name = |block: &BlockType| match block.category {
+        BlockCategory::Building | BlockCategory::Wall => buildings
+            .get(&block.building_type)
+            .map(|building| building.name.clone()),
+        BlockCategory::Defender => block
+            .defender_type
+            .and_then(|defender_type| defenders.get(&defender_type))
+            .map(|defender| defender.name.clone()),
+        BlockCategory::Mine => block
+            .mine_type
+            .and_then(|mine_type| mines.get(&mine_type))
+            .map(|mine| mine.name.clone()),
+    };
+
+    let mut map_buildings: Vec<(i32, i32, i32, i32, i32)> = Vec::new();
     let mut total_artifacts = 0;
 
     for map_space in map_spaces {
@@ -135,12 +261,26 @@ pub fn is_valid_save_layout(
             artifacts,
         } = *map_space;
 
-        let block = blocks.get(&block_type_id).unwrap();
-
-        let building_type = block.building_type;
-
-        if artifacts > buildings[&building_type].capacity {
-            return Err(BaseInvalidError::InvalidArtifactCount);
+        //unknown blocks are already reported by diagnose_update_layout
+        let (block, building) = match blocks
+            .get(&block_type_id)
+            .and_then(|block| Some((block, buildings.get(&block.building_type)?)))
+        {
+            Some(block_and_building) => block_and_building,
+            None => continue,
+        };
+
+        if artifacts > building.capacity {
+            diagnostics.push(diagnostic(
+                BaseInvalidError::InvalidArtifactCount,
+                DiagnosticSeverity::Warning,
+                vec![(x_coordinate, y_coordinate)],
+                Some(block_type_id),
+                format!(
+                    "Store at most {} artifacts in this building",
+                    building.capacity
+                ),
+            ));
         }
 
         total_artifacts += artifacts;
@@ -150,13 +290,23 @@ pub fn is_valid_save_layout(
             if *block_constraint > 0 {
                 *block_constraint -= 1;
             } else {
-                return Err(BaseInvalidError::BlockCountExceeded(block_type_id));
+                diagnostics.push(diagnostic(
+                    BaseInvalidError::BlockCountExceeded(block_type_id),
+                    DiagnosticSeverity::Warning,
+                    vec![(x_coordinate, y_coordinate)],
+                    Some(block_type_id),
+                    "Remove this block, your level allows no more of it".to_string(),
+                ));
             }
         }
 
         // add roads and entrances to graph
         // let new_node = graph.add_node(());
-        if building_type == ROAD_ID {
+        //walls can never overlap roads, so they cannot split the road network,
+        //and they hold nothing so they need no road access
+        if block.category == BlockCategory::Wall {
+            continue;
+        } else if block.building_type == ROAD_ID {
             let road_node = road_graph.add_node(());
             // map_grid.insert((x_coordinate, y_coordinate), new_node);
             road_grid.insert((x_coordinate, y_coordinate), road_node);
@@ -167,22 +317,32 @@ pub fn is_valid_save_layout(
             // node_to_coords.insert(new_node, entrance);
             // map_grid.insert(entrance, new_node);
 
-            map_buildings.push((x_coordinate, y_coordinate, buildings[&building_type].width));
+            map_buildings.push((
+                x_coordinate,
+                y_coordinate,
+                building.width,
+                building.height,
+                block_type_id,
+            ));
         }
     }
 
     if total_artifacts != *user_artifacts {
-        return Err(BaseInvalidError::InvalidArtifactCount);
+        diagnostics.push(diagnostic(
+            BaseInvalidError::InvalidArtifactCount,
+            DiagnosticSeverity::Warning,
+            Vec::new(),
+            None,
+            format!("Place exactly {user_artifacts} artifacts, {total_artifacts} are placed"),
+        ));
     }
-    //print building id,
 
-    for (x_coordinate, y_coordinate, width) in map_buildings {
+    for (x_coordinate, y_coordinate, width, height, block_type_id) in map_buildings {
         let building_top_left_x = x_coordinate;
         let building_top_left_y = y_coordinate;
-        let building_side = width;
         // Check top side
         let mut c1 = 0;
-        for x in building_top_left_x..building_top_left_x + building_side {
+        for x in building_top_left_x..building_top_left_x + width {
             let y = building_top_left_y - 1;
             if road_grid.contains_key(&(x, y)) {
                 c1 += 1;
@@ -191,8 +351,8 @@ pub fn is_valid_save_layout(
 
         // Check bottom side
         let mut c2 = 0;
-        for x in building_top_left_x..building_top_left_x + building_side {
-            let y = building_top_left_y + building_side;
+        for x in building_top_left_x..building_top_left_x + width {
+            let y = building_top_left_y + height;
             if road_grid.contains_key(&(x, y)) {
                 c2 += 1;
             }
@@ -200,7 +360,7 @@ pub fn is_valid_save_layout(
 
         // Check left side
         let mut c3 = 0;
-        for y in building_top_left_y..building_top_left_y + building_side {
+        for y in building_top_left_y..building_top_left_y + height {
             let x = building_top_left_x - 1;
             if road_grid.contains_key(&(x, y)) {
                 c3 += 1;
@@ -209,43 +369,38 @@ pub fn is_valid_save_layout(
 
         // Check right side
         let mut c4 = 0;
-        for y in building_top_left_y..building_top_left_y + building_side {
-            let x = building_top_left_x + building_side;
+        for y in building_top_left_y..building_top_left_y + height {
+            let x = building_top_left_x + width;
             if road_grid.contains_key(&(x, y)) {
                 c4 += 1;
             }
         }
-        if c1 < building_side && c2 < building_side && c3 < building_side && c4 < building_side {
-            return Err(BaseInvalidError::NotAdjacentToRoad);
+        if c1 < width && c2 < width && c3 < height && c4 < height {
+            diagnostics.push(diagnostic(
+                BaseInvalidError::NotAdjacentToRoad,
+                DiagnosticSeverity::Warning,
+                vec![(x_coordinate, y_coordinate)],
+                Some(block_type_id),
+                "Run a road along one full side of the building".to_string(),
+            ));
         }
     }
 
-    //checks if every block of each type is used
-    for block_constraint in block_constraints {
-        if *block_constraint.1 != 0 {
-            if let Some(block) = blocks.get(block_constraint.0) {
-                let category = &block.category;
-                match category {
-                    BlockCategory::Building => {
-                        return Err(BaseInvalidError::BlocksUnused(
-                            buildings[&block.building_type].name.clone(),
-                        ));
-                    }
-                    BlockCategory::Defender => {
-                        if let Some(defender_type) = block.defender_type {
-                            return Err(BaseInvalidError::BlocksUnused(
-                                defenders[&defender_type].name.clone(),
-                            ));
-                        }
-                    }
-                    BlockCategory::Mine => {
-                        if let Some(mine_type) = block.mine_type {
-                            return Err(BaseInvalidError::BlocksUnused(
-                                mines[&mine_type].name.clone(),
-                            ));
-                        }
-                    }
-                }
+    //checks if every block of each type is used, walls are optional
+    for (block_type_id, unused) in block_constraints.iter() {
+        let is_wall = matches!(
+            blocks.get(block_type_id),
+            Some(block) if block.category == BlockCategory::Wall
+        );
+        if *unused != 0 && !is_wall {
+            if let Some(name) = blocks.get(block_type_id).and_then(block_name) {
+                diagnostics.push(diagnostic(
+                    BaseInvalidError::BlocksUnused(name.clone()),
+                    DiagnosticSeverity::Warning,
+                    Vec::new(),
+                    Some(*block_type_id),
+                    format!("Place {unused} more {name}"),
+                ));
             }
         }
     }
@@ -280,18 +435,45 @@ pub fn is_valid_save_layout(
 
     let road_connected_components = tarjan_scc(&road_graph);
 
-    if road_connected_components.len() != 1 {
+    if road_connected_components.is_empty() {
+        diagnostics.push(diagnostic(
+            BaseInvalidError::NotConnected("City has no roads".to_string()),
+            DiagnosticSeverity::Warning,
+            Vec::new(),
+            Some(ROAD_ID),
+            "Place roads that connect all buildings".to_string(),
+        ));
+    } else if road_connected_components.len() != 1 {
         let first_component_node = road_connected_components[0][0];
         let second_component_node = road_connected_components[1][0];
         let first_node_coords = road_node_to_coords[&first_component_node];
         let second_node_coords = road_node_to_coords[&second_component_node];
-        return Err(BaseInvalidError::NotConnected(format!(
-            "Road from ({}, {}) to ({}, {}) is not connected",
-            first_node_coords.0, first_node_coords.1, second_node_coords.0, second_node_coords.1
-        )));
+        diagnostics.push(diagnostic(
+            BaseInvalidError::NotConnected(format!(
+                "Road from ({}, {}) to ({}, {}) is not connected",
+                first_node_coords.0,
+                first_node_coords.1,
+                second_node_coords.0,
+                second_node_coords.1
+            )),
+            DiagnosticSeverity::Warning,
+            road_connected_components
+                .iter()
+                .map(|component| road_node_to_coords[&component[0]])
+                .collect(),
+            Some(ROAD_ID),
+            "Join the marked road networks into one".to_string(),
+        ));
+    }
+
+    for diagnostic in diagnostics.iter_mut() {
+        diagnostic.block_name = diagnostic
+            .block_type_id
+            .and_then(|block_type_id| blocks.get(&block_type_id))
+            .and_then(block_name);
     }
 
-    Ok(())
+    diagnostics
 
     // let connected_components = tarjan_scc(&graph);
 
@@ -308,3 +490,142 @@ pub fn is_valid_save_layout(
     //     )))
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TALL_BUILDING_ID: i32 = 1;
+
+    fn entry(x_coordinate: i32, y_coordinate: i32, block_type_id: i32) -> MapSpacesEntry {
+        MapSpacesEntry {
+            x_coordinate,
+            y_coordinate,
+            block_type_id,
+            artifacts: 0,
+        }
+    }
+
+    fn building_type(id: i32, width: i32, height: i32) -> BuildingType {
+        BuildingType {
+            id,
+            name: format!("Building_{id}"),
+            width,
+            height,
+            capacity: 0,
+            level: 1,
+            cost: 0,
+            hp: 100,
+            max_level: 1,
+            next_level_id: None,
+        }
+    }
+
+    //a road and a building two tiles wide along x and three tiles tall along y
+    fn catalog() -> (HashMap<i32, BlockType>, Vec<BuildingType>) {
+        let blocks = [ROAD_ID, TALL_BUILDING_ID]
+            .into_iter()
+            .map(|id| {
+                (
+                    id,
+                    BlockType {
+                        id,
+                        defender_type: None,
+                        mine_type: None,
+                        category: BlockCategory::Building,
+                        building_type: id,
+                    },
+                )
+            })
+            .collect();
+        let buildings = vec![
+            building_type(ROAD_ID, 1, 1),
+            building_type(TALL_BUILDING_ID, 2, 3),
+        ];
+        (blocks, buildings)
+    }
+
+    fn update_codes(map_spaces: &[MapSpacesEntry]) -> Vec<&'static str> {
+        let (blocks, buildings) = catalog();
+        diagnose_update_layout(map_spaces, &blocks, &buildings)
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect()
+    }
+
+    fn is_adjacent_to_road(map_spaces: &[MapSpacesEntry]) -> bool {
+        let (blocks, buildings) = catalog();
+        let mut block_constraints = HashMap::new();
+        !diagnose_save_layout(
+            map_spaces,
+            &mut block_constraints,
+            &blocks,
+            &buildings,
+            &[],
+            &[],
+            &0,
+        )
+        .iter()
+        .any(|diagnostic| diagnostic.code == "not_adjacent_to_road")
+    }
+
+    fn roads(tiles: impl IntoIterator<Item = (i32, i32)>) -> Vec<MapSpacesEntry> {
+        tiles
+            .into_iter()
+            .map(|(x, y)| entry(x, y, ROAD_ID))
+            .collect()
+    }
+
+    #[test]
+    fn overlap_uses_width_along_x_and_height_along_y() {
+        let building = || entry(4, 4, TALL_BUILDING_ID);
+
+        //(5, 6) is the bottom right tile of the footprint
+        assert_eq!(
+            update_codes(&[building(), entry(5, 6, ROAD_ID)]),
+            vec!["overlapping_blocks"]
+        );
+        //one past the width and one past the height are free
+        assert!(update_codes(&[building(), entry(6, 4, ROAD_ID)]).is_empty());
+        assert!(update_codes(&[building(), entry(4, 7, ROAD_ID)]).is_empty());
+    }
+
+    #[test]
+    fn footprint_must_fit_inside_the_map() {
+        let map_size = MAP_SIZE as i32;
+
+        assert!(update_codes(&[entry(map_size - 2, map_size - 3, TALL_BUILDING_ID)]).is_empty());
+        assert_eq!(
+            update_codes(&[entry(map_size - 2, map_size - 2, TALL_BUILDING_ID)]),
+            vec!["block_outside_map"]
+        );
+        assert_eq!(
+            update_codes(&[entry(map_size - 1, map_size - 3, TALL_BUILDING_ID)]),
+            vec!["block_outside_map"]
+        );
+    }
+
+    #[test]
+    fn road_along_a_full_short_side_is_adjacent() {
+        let mut map_spaces = roads([(4, 3), (5, 3)]);
+        map_spaces.push(entry(4, 4, TALL_BUILDING_ID));
+
+        assert!(is_adjacent_to_road(&map_spaces));
+    }
+
+    #[test]
+    fn road_along_a_full_long_side_is_adjacent() {
+        let mut map_spaces = roads([(3, 4), (3, 5), (3, 6)]);
+        map_spaces.push(entry(4, 4, TALL_BUILDING_ID));
+
+        assert!(is_adjacent_to_road(&map_spaces));
+    }
+
+    #[test]
+    fn road_along_part_of_the_long_side_is_not_adjacent() {
+        let mut map_spaces = roads([(6, 4), (6, 5)]);
+        map_spaces.push(entry(4, 4, TALL_BUILDING_ID));
+
+        assert!(!is_adjacent_to_road(&map_spaces));
+    }
+}
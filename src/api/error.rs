@@ -1,5 +1,8 @@
+use crate::models::ItemKind;
 use actix_web::{
-    error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound, ErrorUnauthorized},
+    error::{
+        ErrorBadRequest, ErrorConflict, ErrorInternalServerError, ErrorNotFound, ErrorUnauthorized,
+    },
     ResponseError,
 };
 use derive_more::Display;
@@ -50,9 +53,9 @@ pub enum BaseInvalidError {
     NotAdjacentToRoad,
 }
 
-impl ResponseError for BaseInvalidError {
-    fn error_response(&self) -> actix_web::HttpResponse {
-        let response_body = match self {
+impl BaseInvalidError {
+    pub fn message(&self) -> String {
+        match self {
             BaseInvalidError::InvalidBlockType(block_type) => {
                 format!("Invalid block type: {block_type}")
             }
@@ -77,8 +80,168 @@ impl ResponseError for BaseInvalidError {
             BaseInvalidError::NotAdjacentToRoad => {
                 "A building is not adjacent to a road".to_string()
             } // BaseInvalidError::RoundRoad => "A 4x4 Square Cannot have all as Road".to_string(),
-        };
-        ErrorBadRequest(response_body).into()
+        }
+    }
+
+    //stable identifier for clients, independent of the message wording
+    pub fn code(&self) -> &'static str {
+        match self {
+            BaseInvalidError::InvalidBlockType(_) => "invalid_block_type",
+            BaseInvalidError::InvalidBuildingType(_) => "invalid_building_type",
+            BaseInvalidError::OverlappingBlocks => "overlapping_blocks",
+            BaseInvalidError::BlockOutsideMap => "block_outside_map",
+            BaseInvalidError::BlockCountExceeded(_) => "block_count_exceeded",
+            BaseInvalidError::InvalidArtifactCount => "invalid_artifact_count",
+            BaseInvalidError::BlocksUnused(_) => "blocks_unused",
+            BaseInvalidError::NotConnected(_) => "not_connected",
+            BaseInvalidError::NotAdjacentToRoad => "not_adjacent_to_road",
+        }
+    }
+}
+
+impl ResponseError for BaseInvalidError {
+    fn error_response(&self) -> actix_web::HttpResponse {
+        ErrorBadRequest(self.message()).into()
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum LayoutError {
+    #[display(fmt = "Layout not found")]
+    SlotNotFound,
+    #[display(fmt = "All layout slots are in use")]
+    AllSlotsInUse,
+    #[display(fmt = "The active layout cannot be saved as a draft")]
+    ActiveLayoutNotDraft,
+    #[display(fmt = "Invalid layout code")]
+    InvalidCode,
+    #[display(fmt = "Layout version not found")]
+    VersionNotFound,
+    #[display(fmt = "This version has no bank to hold the artifacts")]
+    VersionWithoutBank,
+}
+
+impl ResponseError for LayoutError {
+    fn error_response(&self) -> actix_web::HttpResponse {
+        match self {
+            LayoutError::SlotNotFound | LayoutError::VersionNotFound => {
+                ErrorNotFound(self.to_string()).into()
+            }
+            _ => ErrorBadRequest(self.to_string()).into(),
+        }
+    }
+}
+
+//layout errors go back to the client, everything else is internal
+pub fn handle_layout_error(err: anyhow::Error) -> actix_web::Error {
+    match err.downcast::<LayoutError>() {
+        Ok(layout_error) => layout_error.into(),
+        Err(err) => handle_error(err.into()),
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum SpendError {
+    #[display(fmt = "Not enough artifacts")]
+    NotEnoughArtifacts,
+    #[display(fmt = "Not enough artifacts in the bank")]
+    NotEnoughArtifactsInBank,
+    #[display(fmt = "Not enough artifacts in the building")]
+    NotEnoughArtifactsInBuilding,
+    #[display(fmt = "Building capacity not sufficient")]
+    BuildingCapacityExceeded,
+}
+
+impl ResponseError for SpendError {
+    fn error_response(&self) -> actix_web::HttpResponse {
+        ErrorBadRequest(self.to_string()).into()
+    }
+}
+
+//spending errors go back to the client, everything else is internal
+pub fn handle_spend_error(err: anyhow::Error) -> actix_web::Error {
+    match err.downcast::<SpendError>() {
+        Ok(spend_error) => spend_error.into(),
+        Err(err) => handle_error(err.into()),
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum InventoryError {
+    #[display(
+        fmt = "Either item is not a {:?} or the user does not have the item",
+        _0
+    )]
+    ItemNotOwned(ItemKind),
+    #[display(fmt = "{:?} is at max level", _0)]
+    MaxLevel(ItemKind),
+    #[display(fmt = "{:?} is at the lowest level", _0)]
+    LowestLevel(ItemKind),
+    #[display(fmt = "All builders are busy")]
+    BuildersBusy,
+    #[display(fmt = "Item is already being upgraded")]
+    AlreadyUpgrading,
+    #[display(fmt = "Item is being upgraded")]
+    UpgradeInProgress,
+    #[display(fmt = "Upgrade job not found")]
+    UpgradeJobNotFound,
+    #[display(fmt = "Roads and the bank cannot be sold")]
+    NotSellable,
+    #[display(fmt = "Cannot sell your last attacker")]
+    LastAttacker,
+    #[display(fmt = "Move artifacts out of the building first")]
+    ArtifactsInBuilding,
+    #[display(fmt = "You already own the lower level of this item")]
+    LowerLevelOwned,
+    #[display(fmt = "Your base would not fit the constraints of its level")]
+    LevelConstraintsExceeded,
+    #[display(fmt = "The lower level has a different size. Remove it from the base first")]
+    SizeMismatch,
+}
+
+impl ResponseError for InventoryError {
+    fn error_response(&self) -> actix_web::HttpResponse {
+        ErrorBadRequest(self.to_string()).into()
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ShopError {
+    #[display(fmt = "Bundle not found")]
+    NotFound,
+    #[display(fmt = "Bundle is locked until a later level")]
+    Locked,
+    #[display(fmt = "You already own everything in this bundle")]
+    AlreadyOwned,
+}
+
+impl ResponseError for ShopError {
+    fn error_response(&self) -> actix_web::HttpResponse {
+        ErrorBadRequest(self.to_string()).into()
+    }
+}
+
+#[derive(Debug, Display, Error)]
+pub enum IdempotencyError {
+    #[display(fmt = "This Idempotency-Key was already used for a different request")]
+    KeyReused,
+}
+
+impl ResponseError for IdempotencyError {
+    fn error_response(&self) -> actix_web::HttpResponse {
+        ErrorConflict(self.to_string()).into()
+    }
+}
+
+//inventory, shop and spending errors go back to the client, everything else is internal
+pub fn handle_item_error(err: anyhow::Error) -> actix_web::Error {
+    let err = match err.downcast::<InventoryError>() {
+        Ok(inventory_error) => return inventory_error.into(),
+        Err(err) => err,
+    };
+    match err.downcast::<ShopError>() {
+        Ok(shop_error) => shop_error.into(),
+        Err(err) => handle_spend_error(err),
     }
 }
 
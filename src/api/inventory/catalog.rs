@@ -0,0 +1,447 @@
+/// Item catalog over the attacker, building, defender, emp and mine type tables
+use crate::error::DieselError;
+use crate::models::{BlockCategory, BlockType, ItemCategory, ItemKind};
+use crate::schema::{
+    attacker_type, available_blocks, block_type, building_type, defender_type, emp_type, mine_type,
+};
+use crate::util::function;
+use anyhow::Result;
+use diesel::{prelude::*, PgConnection};
+use serde::Serialize;
+use std::collections::HashMap;
+
+// (item_id, type_id, name, level, max_level, cost, next_level_id)
+type CatalogRow = (i32, i32, String, i32, i32, i32, Option<i32>);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct CatalogItem {
+    pub kind: ItemKind,
+    // block_type id for blocks, type id for attackers and emps
+    pub item_id: i32,
+    pub type_id: i32,
+    pub name: String,
+    pub level: i32,
+    pub max_level: i32,
+    pub cost: i32,
+    pub next_level_id: Option<i32>,
+}
+
+impl CatalogItem {
+    fn from_row(kind: ItemKind, row: CatalogRow) -> Self {
+        let (item_id, type_id, name, level, max_level, cost, next_level_id) = row;
+        CatalogItem {
+            kind,
+            item_id,
+            type_id,
+            name,
+            level,
+            max_level,
+            cost,
+            next_level_id,
+        }
+    }
+
+    pub fn is_max_level(&self) -> bool {
+        self.level >= self.max_level || self.next_level_id.is_none()
+    }
+}
+
+impl ItemKind {
+    pub fn item_category(&self) -> ItemCategory {
+        match self {
+            ItemKind::Attacker => ItemCategory::Attacker,
+            ItemKind::Emp => ItemCategory::Emp,
+            ItemKind::Building | ItemKind::Defender | ItemKind::Mine => ItemCategory::Block,
+        }
+    }
+
+    pub fn table_name(&self) -> &'static str {
+        match self {
+            ItemKind::Attacker => "attacker_type",
+            ItemKind::Building => "building_type",
+            ItemKind::Defender => "defender_type",
+            ItemKind::Emp => "emp_type",
+            ItemKind::Mine => "mine_type",
+        }
+    }
+
+    //walls are building types too, so they share the building upgrade chains
+    pub fn block_categories(&self) -> &'static [BlockCategory] {
+        match self {
+            ItemKind::Building => &[BlockCategory::Building, BlockCategory::Wall],
+            ItemKind::Defender => &[BlockCategory::Defender],
+            ItemKind::Mine => &[BlockCategory::Mine],
+            ItemKind::Attacker | ItemKind::Emp => &[],
+        }
+    }
+}
+
+//fetches an item only if the player has it in available_blocks
+pub fn get_owned_item(
+    conn: &mut PgConnection,
+    player_id: i32,
+    kind: ItemKind,
+    item_id: i32,
+) -> Result<Option<CatalogItem>> {
+    let owned_blocks = available_blocks::table
+        .filter(available_blocks::user_id.eq(player_id))
+        .filter(available_blocks::category.eq(kind.item_category()));
+
+    let row: Option<CatalogRow> = match kind {
+        ItemKind::Attacker => owned_blocks
+            .inner_join(attacker_type::table)
+            .filter(attacker_type::id.eq(item_id))
+            .select((
+                attacker_type::id,
+                attacker_type::id,
+                attacker_type::name,
+                attacker_type::level,
+                attacker_type::max_level,
+                attacker_type::cost,
+                attacker_type::next_level_id,
+            ))
+            .first::<CatalogRow>(conn)
+            .optional(),
+        ItemKind::Emp => owned_blocks
+            .inner_join(emp_type::table)
+            .filter(emp_type::id.eq(item_id))
+            .select((
+                emp_type::id,
+                emp_type::id,
+                emp_type::name,
+                emp_type::level,
+                emp_type::max_level,
+                emp_type::cost,
+                emp_type::next_level_id,
+            ))
+            .first::<CatalogRow>(conn)
+            .optional(),
+        ItemKind::Building => owned_blocks
+            .inner_join(block_type::table.inner_join(building_type::table))
+            .filter(available_blocks::block_type_id.eq(item_id))
+            .filter(block_type::category.eq_any(kind.block_categories()))
+            .select((
+                block_type::id,
+                building_type::id,
+                building_type::name,
+                building_type::level,
+                building_type::max_level,
+                building_type::cost,
+                building_type::next_level_id,
+            ))
+            .first::<CatalogRow>(conn)
+            .optional(),
+        ItemKind::Defender => owned_blocks
+            .inner_join(block_type::table.inner_join(defender_type::table))
+            .filter(available_blocks::block_type_id.eq(item_id))
+            .filter(block_type::category.eq(BlockCategory::Defender))
+            .select((
+                block_type::id,
+                defender_type::id,
+                defender_type::name,
+                defender_type::level,
+                defender_type::max_level,
+                defender_type::cost,
+                defender_type::next_level_id,
+            ))
+            .first::<CatalogRow>(conn)
+            .optional(),
+        ItemKind::Mine => owned_blocks
+            .inner_join(block_type::table.inner_join(mine_type::table))
+            .filter(available_blocks::block_type_id.eq(item_id))
+            .filter(block_type::category.eq(BlockCategory::Mine))
+            .select((
+                block_type::id,
+                mine_type::id,
+                mine_type::name,
+                mine_type::level,
+                mine_type::max_level,
+                mine_type::cost,
+                mine_type::next_level_id,
+            ))
+            .first::<CatalogRow>(conn)
+            .optional(),
+    }
+    .map_err(|err| DieselError {
+        table: "available_blocks",
+        function: function!(),
+        error: err,
+    })?;
+
+    Ok(row.map(|row| CatalogItem::from_row(kind, row)))
+}
+
+//resolves the id stored in available_blocks for the next level of an item
+pub fn get_next_level_item_id(conn: &mut PgConnection, item: &CatalogItem) -> Result<Option<i32>> {
+    match item.next_level_id {
+        Some(next_level_id) => get_item_id_of_type(conn, item.kind, next_level_id),
+        None => Ok(None),
+    }
+}
+
+//type id and cost of the level below, if any
+pub fn get_previous_level(
+    conn: &mut PgConnection,
+    kind: ItemKind,
+    type_id: i32,
+) -> Result<Option<(i32, i32)>> {
+    let previous_level = match kind {
+        ItemKind::Attacker => attacker_type::table
+            .filter(attacker_type::next_level_id.eq(type_id))
+            .select((attacker_type::id, attacker_type::cost))
+            .first::<(i32, i32)>(conn)
+            .optional(),
+        ItemKind::Emp => emp_type::table
+            .filter(emp_type::next_level_id.eq(type_id))
+            .select((emp_type::id, emp_type::cost))
+            .first::<(i32, i32)>(conn)
+            .optional(),
+        ItemKind::Building => building_type::table
+            .filter(building_type::next_level_id.eq(type_id))
+            .select((building_type::id, building_type::cost))
+            .first::<(i32, i32)>(conn)
+            .optional(),
+        ItemKind::Defender => defender_type::table
+            .filter(defender_type::next_level_id.eq(type_id))
+            .select((defender_type::id, defender_type::cost))
+            .first::<(i32, i32)>(conn)
+            .optional(),
+        ItemKind::Mine => mine_type::table
+            .filter(mine_type::next_level_id.eq(type_id))
+            .select((mine_type::id, mine_type::cost))
+            .first::<(i32, i32)>(conn)
+            .optional(),
+    }
+    .map_err(|err| DieselError {
+        table: kind.table_name(),
+        function: function!(),
+        error: err,
+    })?;
+
+    Ok(previous_level)
+}
+
+//resolves the id stored in available_blocks for a type id of the given kind
+pub fn get_item_id_of_type(
+    conn: &mut PgConnection,
+    kind: ItemKind,
+    type_id: i32,
+) -> Result<Option<i32>> {
+    if kind.block_categories().is_empty() {
+        return Ok(Some(type_id));
+    }
+    let block = block_type::table.filter(block_type::category.eq_any(kind.block_categories()));
+
+    let item_id = match kind {
+        ItemKind::Attacker | ItemKind::Emp => Ok(Some(type_id)),
+        ItemKind::Building => block
+            .filter(block_type::building_type.eq(type_id))
+            .select(block_type::id)
+            .first::<i32>(conn)
+            .optional(),
+        ItemKind::Defender => block
+            .filter(block_type::defender_type.eq(type_id))
+            .select(block_type::id)
+            .first::<i32>(conn)
+            .optional(),
+        ItemKind::Mine => block
+            .filter(block_type::mine_type.eq(type_id))
+            .select(block_type::id)
+            .first::<i32>(conn)
+            .optional(),
+    }
+    .map_err(|err| DieselError {
+        table: "block_type",
+        function: function!(),
+        error: err,
+    })?;
+
+    Ok(item_id)
+}
+
+//an upgrade chain is identified by its kind and the type id of its lowest level
+pub type ItemChain = (ItemKind, i32);
+
+//upgrade chain of every id that can be stored in available_blocks
+#[derive(Default)]
+pub struct ItemChains {
+    pub blocks: HashMap<i32, ItemChain>,
+    pub attackers: HashMap<i32, ItemChain>,
+    pub emps: HashMap<i32, ItemChain>,
+}
+
+impl ItemChains {
+    pub fn get(&self, category: ItemCategory, item_id: i32) -> Option<ItemChain> {
+        match category {
+            ItemCategory::Block => self.blocks.get(&item_id),
+            ItemCategory::Attacker => self.attackers.get(&item_id),
+            ItemCategory::Emp => self.emps.get(&item_id),
+        }
+        .copied()
+    }
+}
+
+//maps every type id to the lowest level reachable through next_level_id
+fn get_lowest_levels(levels: Vec<(i32, Option<i32>)>) -> HashMap<i32, i32> {
+    let previous_levels: HashMap<i32, i32> = levels
+        .iter()
+        .filter_map(|(id, next_level_id)| next_level_id.map(|next_level_id| (next_level_id, *id)))
+        .collect();
+
+    levels
+        .iter()
+        .map(|(id, _)| {
+            let mut lowest_level = *id;
+            //bounded walk in case a broken chain loops
+            for _ in 0..levels.len() {
+                match previous_levels.get(&lowest_level) {
+                    Some(previous_level) => lowest_level = *previous_level,
+                    None => break,
+                }
+            }
+            (*id, lowest_level)
+        })
+        .collect()
+}
+
+pub fn get_item_chains(conn: &mut PgConnection) -> Result<ItemChains> {
+    let attackers = attacker_type::table
+        .select((attacker_type::id, attacker_type::next_level_id))
+        .load::<(i32, Option<i32>)>(conn)
+        .map_err(|err| DieselError {
+            table: "attacker_type",
+            function: function!(),
+            error: err,
+        })?;
+    let emps = emp_type::table
+        .select((emp_type::id, emp_type::next_level_id))
+        .load::<(i32, Option<i32>)>(conn)
+        .map_err(|err| DieselError {
+            table: "emp_type",
+            function: function!(),
+            error: err,
+        })?;
+    let buildings = building_type::table
+        .select((building_type::id, building_type::next_level_id))
+        .load::<(i32, Option<i32>)>(conn)
+        .map_err(|err| DieselError {
+            table: "building_type",
+            function: function!(),
+            error: err,
+        })?;
+    let defenders = defender_type::table
+        .select((defender_type::id, defender_type::next_level_id))
+        .load::<(i32, Option<i32>)>(conn)
+        .map_err(|err| DieselError {
+            table: "defender_type",
+            function: function!(),
+            error: err,
+        })?;
+    let mines = mine_type::table
+        .select((mine_type::id, mine_type::next_level_id))
+        .load::<(i32, Option<i32>)>(conn)
+        .map_err(|err| DieselError {
+            table: "mine_type",
+            function: function!(),
+            error: err,
+        })?;
+    let blocks = block_type::table
+        .load::<BlockType>(conn)
+        .map_err(|err| DieselError {
+            table: "block_type",
+            function: function!(),
+            error: err,
+        })?;
+
+    let lowest_buildings = get_lowest_levels(buildings);
+    let lowest_defenders = get_lowest_levels(defenders);
+    let lowest_mines = get_lowest_levels(mines);
+
+    let mut item_chains = ItemChains {
+        attackers: get_lowest_levels(attackers)
+            .into_iter()
+            .map(|(id, lowest_level)| (id, (ItemKind::Attacker, lowest_level)))
+            .collect(),
+        emps: get_lowest_levels(emps)
+            .into_iter()
+            .map(|(id, lowest_level)| (id, (ItemKind::Emp, lowest_level)))
+            .collect(),
+        ..Default::default()
+    };
+    for block in blocks {
+        let chain = match block.category {
+            BlockCategory::Building | BlockCategory::Wall => lowest_buildings
+                .get(&block.building_type)
+                .map(|lowest_level| (ItemKind::Building, *lowest_level)),
+            BlockCategory::Defender => block
+                .defender_type
+                .and_then(|defender_type| lowest_defenders.get(&defender_type))
+                .map(|lowest_level| (ItemKind::Defender, *lowest_level)),
+            BlockCategory::Mine => block
+                .mine_type
+                .and_then(|mine_type| lowest_mines.get(&mine_type))
+                .map(|lowest_level| (ItemKind::Mine, *lowest_level)),
+        };
+        if let Some(chain) = chain {
+            item_chains.blocks.insert(block.id, chain);
+        }
+    }
+
+    Ok(item_chains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(level: i32, max_level: i32, next_level_id: Option<i32>) -> CatalogItem {
+        CatalogItem {
+            kind: ItemKind::Defender,
+            item_id: 1,
+            type_id: 1,
+            name: "Sentry".to_string(),
+            level,
+            max_level,
+            cost: 100,
+            next_level_id,
+        }
+    }
+
+    #[test]
+    fn every_level_of_a_chain_maps_to_its_first_level() {
+        let lowest_levels = get_lowest_levels(vec![(1, Some(2)), (2, Some(3)), (3, None)]);
+        assert_eq!(lowest_levels[&1], 1);
+        assert_eq!(lowest_levels[&2], 1);
+        assert_eq!(lowest_levels[&3], 1);
+    }
+
+    #[test]
+    fn separate_chains_do_not_mix() {
+        let lowest_levels =
+            get_lowest_levels(vec![(1, Some(2)), (2, None), (5, Some(6)), (6, None)]);
+        assert_eq!(lowest_levels[&2], 1);
+        assert_eq!(lowest_levels[&6], 5);
+    }
+
+    #[test]
+    fn a_looping_chain_still_resolves() {
+        let lowest_levels = get_lowest_levels(vec![(1, Some(2)), (2, Some(1))]);
+        assert_eq!(lowest_levels.len(), 2);
+    }
+
+    #[test]
+    fn item_is_at_max_level_at_the_end_of_its_chain() {
+        assert!(!item(1, 3, Some(2)).is_max_level());
+        assert!(item(3, 3, Some(4)).is_max_level());
+        assert!(item(2, 3, None).is_max_level());
+    }
+
+    #[test]
+    fn walls_upgrade_along_the_building_chains() {
+        assert!(ItemKind::Building
+            .block_categories()
+            .contains(&BlockCategory::Wall));
+        assert_eq!(ItemKind::Mine.item_category(), ItemCategory::Block);
+        assert!(ItemKind::Emp.block_categories().is_empty());
+    }
+}
@@ -1,22 +1,37 @@
-use self::util::{upgrade_attacker, upgrade_building, upgrade_defender, upgrade_emp, upgrade_mine};
 use super::{
-    attack::util::get_game_id_from_redis, auth::session::AuthUser, error, PgPool, RedisPool,
+    auth::session::AuthUser,
+    error,
+    util::{is_under_attack, run_idempotent},
+    PgPool, RedisPool,
 };
+use crate::models::{ItemKind, UpgradeJob};
 use actix_web::{
     error::ErrorBadRequest,
     web::{self, Json},
-    Responder, Result,
+    HttpRequest, Responder, Result,
 };
+use diesel::PgConnection;
 use serde::{Deserialize, Serialize};
+pub mod catalog;
 pub mod util;
 
 pub fn routes(cfg: &mut web::ServiceConfig) {
     cfg.service(web::resource("/get").route(web::get().to(get_inventory)))
-        .service(web::resource("/upgrade").route(web::post().to(upgrade)));
+        .service(web::resource("/upgrade").route(web::post().to(upgrade)))
+        .service(web::resource("/sell").route(web::post().to(sell)))
+        .service(web::resource("/downgrade").route(web::post().to(downgrade)))
+        .service(
+            web::resource("/jobs")
+                .route(web::get().to(get_upgrade_jobs))
+                .route(web::post().to(start_upgrade_job)),
+        )
+        .service(web::resource("/jobs/{job_id}/finish").route(web::post().to(finish_upgrade_job)))
+        .service(web::resource("/jobs/{job_id}/cancel").route(web::post().to(cancel_upgrade_job)));
 }
 
 async fn get_inventory(user: AuthUser, pool: web::Data<PgPool>) -> Result<impl Responder> {
     let user_id = user.0;
+
     let response = web::block(move || {
         let mut conn = pool.get()?;
         util::get_inventory(user_id, &mut conn)
@@ -29,45 +44,246 @@ async fn get_inventory(user: AuthUser, pool: web::Data<PgPool>) -> Result<impl R
 
 #[derive(Deserialize, Serialize)]
 struct UpgradeStruct {
-    pub item_type: String,
+    pub item_type: ItemKind,
     pub item_id: i32,
 }
 
+//legacy response, the map space id of the building being upgraded or 0
 async fn upgrade(
+    http_req: HttpRequest,
     pool: web::Data<PgPool>,
     redis_pool: web::Data<RedisPool>,
     user: AuthUser,
     req: Json<UpgradeStruct>,
 ) -> Result<impl Responder> {
     let user_id = user.0;
-    let mut conn = pool.get().map_err(|err| error::handle_error(err.into()))?;
-    let item_type = &req.item_type;
+    let item_type = req.item_type;
     let item_id = req.item_id;
 
-    let mut redis_conn = redis_pool
-        .get()
-        .map_err(|err| error::handle_error(err.into()))?;
+    if is_under_attack(user_id, &redis_pool)? {
+        return Err(ErrorBadRequest("You are under attack. Cannot upgrade now"));
+    }
+
+    run_idempotent(
+        &http_req,
+        pool,
+        user_id,
+        "upgrade",
+        move |conn| {
+            let (_, map_space_id) = start_upgrade(user_id, conn, item_type, item_id)?;
+            Ok(map_space_id)
+        },
+        error::handle_item_error,
+    )
+    .await
+}
+
+async fn start_upgrade_job(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    req: Json<UpgradeStruct>,
+) -> Result<impl Responder> {
+    let user_id = user.0;
+    let item_type = req.item_type;
+    let item_id = req.item_id;
 
-    if let Ok(Some(_)) = get_game_id_from_redis(user_id, &mut redis_conn, false) {
+    if is_under_attack(user_id, &redis_pool)? {
         return Err(ErrorBadRequest("You are under attack. Cannot upgrade now"));
     }
 
-    let mut map_space_id_if_valid = 0;
+    run_idempotent(
+        &http_req,
+        pool,
+        user_id,
+        "upgrade_job",
+        move |conn| {
+            let (upgrade_job, _) = start_upgrade(user_id, conn, item_type, item_id)?;
+            Ok(upgrade_job)
+        },
+        error::handle_item_error,
+    )
+    .await
+}
 
-    match item_type.as_str() {
-        "attacker" => upgrade_attacker(user_id, &mut conn, item_id)
-            .map_err(|err| ErrorBadRequest(err.to_string()))?,
-        "building" => {
-            map_space_id_if_valid = upgrade_building(user_id, &mut conn, item_id)
-                .map_err(|err| ErrorBadRequest(err.to_string()))?;
+fn start_upgrade(
+    user_id: i32,
+    conn: &mut PgConnection,
+    item_type: ItemKind,
+    item_id: i32,
+) -> anyhow::Result<(UpgradeJob, i32)> {
+    util::complete_finished_upgrade_jobs(user_id, conn)?;
+    let upgrade_job = util::upgrade_item(user_id, conn, item_type, item_id)?;
+    let map_space_id = match item_type {
+        ItemKind::Building => {
+            let map_id = util::get_user_map_id(user_id, conn)?;
+            util::get_building_map_space_id(conn, &map_id, &item_id)?.unwrap_or(0)
         }
-        "defender" => upgrade_defender(user_id, &mut conn, item_id)
-            .map_err(|err| ErrorBadRequest(err.to_string()))?,
-        "emp" => upgrade_emp(user_id, &mut conn, item_id)
-            .map_err(|err| ErrorBadRequest(err.to_string()))?,
-        "mine" => upgrade_mine(user_id, &mut conn, item_id)
-            .map_err(|err| ErrorBadRequest(err.to_string()))?,
-        _ => return Err(ErrorBadRequest("Invalid item type")),
+        _ => 0,
+    };
+    Ok((upgrade_job, map_space_id))
+}
+
+#[derive(Serialize)]
+struct ItemRefundResponse {
+    pub item_type: ItemKind,
+    pub item_id: i32,
+    pub artifacts: i32,
+}
+
+async fn sell(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    req: Json<UpgradeStruct>,
+) -> Result<impl Responder> {
+    let user_id = user.0;
+    let item_type = req.item_type;
+    let item_id = req.item_id;
+
+    if is_under_attack(user_id, &redis_pool)? {
+        return Err(ErrorBadRequest("You are under attack. Cannot sell now"));
+    }
+
+    run_idempotent(
+        &http_req,
+        pool,
+        user_id,
+        "sell",
+        move |conn| {
+            util::complete_finished_upgrade_jobs(user_id, conn)?;
+            let refund = util::sell_item(user_id, conn, item_type, item_id)?;
+            Ok(ItemRefundResponse {
+                item_type,
+                item_id,
+                artifacts: refund,
+            })
+        },
+        error::handle_item_error,
+    )
+    .await
+}
+
+async fn downgrade(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    req: Json<UpgradeStruct>,
+) -> Result<impl Responder> {
+    let user_id = user.0;
+    let item_type = req.item_type;
+    let item_id = req.item_id;
+
+    if is_under_attack(user_id, &redis_pool)? {
+        return Err(ErrorBadRequest(
+            "You are under attack. Cannot downgrade now",
+        ));
+    }
+
+    run_idempotent(
+        &http_req,
+        pool,
+        user_id,
+        "downgrade",
+        move |conn| {
+            util::complete_finished_upgrade_jobs(user_id, conn)?;
+            let refund = util::downgrade_item(user_id, conn, item_type, item_id)?;
+            Ok(ItemRefundResponse {
+                item_type,
+                item_id,
+                artifacts: refund,
+            })
+        },
+        error::handle_item_error,
+    )
+    .await
+}
+
+async fn get_upgrade_jobs(pool: web::Data<PgPool>, user: AuthUser) -> Result<impl Responder> {
+    let user_id = user.0;
+
+    let response = web::block(move || {
+        let mut conn = pool.get()?;
+        util::get_upgrade_jobs(user_id, &mut conn)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    Ok(Json(response))
+}
+
+#[derive(Serialize)]
+struct UpgradeJobArtifactsResponse {
+    pub job_id: i32,
+    pub artifacts: i32,
+}
+
+async fn finish_upgrade_job(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    job_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let user_id = user.0;
+    let job_id = job_id.into_inner();
+
+    if is_under_attack(user_id, &redis_pool)? {
+        return Err(ErrorBadRequest(
+            "You are under attack. Cannot finish upgrade now",
+        ));
+    }
+
+    run_idempotent(
+        &http_req,
+        pool,
+        user_id,
+        "finish_upgrade",
+        move |conn| {
+            let cost = util::finish_upgrade_job(user_id, job_id, conn)?;
+            Ok(UpgradeJobArtifactsResponse {
+                job_id,
+                artifacts: cost,
+            })
+        },
+        error::handle_item_error,
+    )
+    .await
+}
+
+async fn cancel_upgrade_job(
+    http_req: HttpRequest,
+    pool: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+    job_id: web::Path<i32>,
+) -> Result<impl Responder> {
+    let user_id = user.0;
+    let job_id = job_id.into_inner();
+
+    if is_under_attack(user_id, &redis_pool)? {
+        return Err(ErrorBadRequest(
+            "You are under attack. Cannot cancel upgrade now",
+        ));
     }
-    Ok(Json(map_space_id_if_valid))
+
+    run_idempotent(
+        &http_req,
+        pool,
+        user_id,
+        "cancel_upgrade",
+        move |conn| {
+            util::complete_finished_upgrade_jobs(user_id, conn)?;
+            let refund = util::cancel_upgrade_job(user_id, job_id, conn)?;
+            Ok(UpgradeJobArtifactsResponse {
+                job_id,
+                artifacts: refund,
+            })
+        },
+        error::handle_item_error,
+    )
+    .await
 }
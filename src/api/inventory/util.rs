@@ -1,18 +1,28 @@
-use crate::constants::BANK_BUILDING_NAME;
+use super::catalog;
+use crate::api::attack::util::get_game_id_from_redis;
+use crate::api::defense::util::get_latest_layouts_with_block;
+use crate::api::error::InventoryError;
+use crate::api::util::{lock_player_funds, refund_artifacts, spend_artifacts, PlayerFunds};
+use crate::api::RedisConn;
+use crate::constants::{
+    BANK_BUILDING_NAME, FINISH_UPGRADE_COST_PER_MINUTE, ITEM_REFUND_PERCENTAGE, MAX_BUILDER_SLOTS,
+    ROAD_ID, UPGRADE_CANCEL_REFUND_PERCENTAGE, UPGRADE_TIME_PER_LEVEL_IN_MINUTES,
+};
 use crate::error::DieselError;
 use crate::models::{
-    AttackerType, BlockCategory, BlockType, BuildingType, DefenderType, EmpType, ItemCategory,
-    MineType,
+    AttackerAbility, AttackerType, BlockCategory, BlockType, BuildingType, DefenderType, EmpEffect,
+    EmpType, ItemCategory, ItemKind, MineEffect, MineType, NewUpgradeJob, UpgradeJob,
 };
 use crate::schema::{
     artifact, attacker_type, available_blocks, block_type, building_type, defender_type, emp_type,
     mine_type,
 };
-use crate::schema::{map_layout, map_spaces, user};
+use crate::schema::{level_constraints, map_layout, map_spaces, upgrade_job, user};
 use crate::util::function;
 use anyhow::{Ok, Result};
 use diesel::{dsl::exists, prelude::*, select, PgConnection};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize)]
 pub struct BuildingTypeResponse {
@@ -25,6 +35,7 @@ pub struct BuildingTypeResponse {
     level: i32,
     cost: i32,
     hp: i32,
+    is_wall: bool,
     next_level_stats: Option<NextLevelBuildingTypeResponse>,
 }
 #[derive(Serialize, Deserialize)]
@@ -50,6 +61,11 @@ pub struct AttackerTypeResponse {
     level: i32,
     cost: i32,
     name: String,
+    ability: AttackerAbility,
+    ability_power: i32,
+    ability_duration: i32,
+    ability_cooldown: i32,
+    ability_charges: i32,
     next_level_stats: Option<NextLevelAttackerTypeResponse>,
 }
 #[derive(Serialize, Deserialize)]
@@ -62,6 +78,11 @@ pub struct NextLevelAttackerTypeResponse {
     level: i32,
     cost: i32,
     name: String,
+    ability: AttackerAbility,
+    ability_power: i32,
+    ability_duration: i32,
+    ability_cooldown: i32,
+    ability_charges: i32,
 }
 #[derive(Serialize, Deserialize)]
 
@@ -92,24 +113,26 @@ pub struct NextLevelDefenderTypeResponse {
 
 pub struct EmpTypeResponse {
     id: i32,
-    att_type: String,
+    att_type: EmpEffect,
     attack_radius: i32,
     attack_damage: i32,
     cost: i32,
     name: String,
     level: i32,
+    disable_duration: i32,
     next_level_stats: Option<NextLevelEmpTypeResponse>,
 }
 #[derive(Serialize, Deserialize)]
 
 pub struct NextLevelEmpTypeResponse {
     id: i32,
-    att_type: String,
+    att_type: EmpEffect,
     attack_radius: i32,
     attack_damage: i32,
     cost: i32,
     name: String,
     level: i32,
+    disable_duration: i32,
 }
 #[derive(Serialize, Deserialize)]
 
@@ -121,6 +144,8 @@ pub struct MineTypeResponse {
     level: i32,
     cost: i32,
     name: String,
+    effect: MineEffect,
+    effect_duration: i32,
     next_level_stats: Option<NextLevelMineTypeResponse>,
 }
 #[derive(Serialize, Deserialize)]
@@ -133,6 +158,8 @@ pub struct NextLevelMineTypeResponse {
     level: i32,
     cost: i32,
     name: String,
+    effect: MineEffect,
+    effect_duration: i32,
 }
 #[derive(Serialize, Deserialize)]
 
@@ -168,29 +195,25 @@ fn get_building_types(
         .inner_join(block_type::table.inner_join(building_type::table))
         .filter(available_blocks::user_id.eq(player_id))
         .filter(available_blocks::category.eq(ItemCategory::Block))
-        .filter(block_type::category.eq(BlockCategory::Building))
-        .select((building_type::all_columns, block_type::id));
+        .filter(block_type::category.eq_any(ItemKind::Building.block_categories()))
+        .select((
+            building_type::all_columns,
+            block_type::id,
+            block_type::category,
+        ));
 
     let buildings = joined_table
-        .load::<(BuildingType, i32)>(conn)
+        .load::<(BuildingType, i32, BlockCategory)>(conn)
         .map_err(|err| DieselError {
             table: "building_type",
             function: function!(),
             error: err,
         })?
         .into_iter()
-        .map(|(building_type, block_id)| {
-            let max_level: i64 = building_type::table
-                .filter(building_type::name.eq(&building_type.name))
-                .count()
-                .get_result::<i64>(conn)
-                .map_err(|err| DieselError {
-                    table: "building_type",
-                    function: function!(),
-                    error: err,
-                })
-                .unwrap_or(0);
-            if building_type.level >= max_level as i32 {
+        .map(|(building_type, block_id, category)| {
+            if building_type.level >= building_type.max_level
+                || building_type.next_level_id.is_none()
+            {
                 // The building is at max level
                 BuildingTypeResponse {
                     id: building_type.id,
@@ -202,15 +225,13 @@ fn get_building_types(
                     level: building_type.level,
                     cost: building_type.cost,
                     hp: building_type.hp,
+                    is_wall: category == BlockCategory::Wall,
                     next_level_stats: None,
                 }
             } else {
-                let next_level = building_type.level + 1;
-
                 let next_level_stats: (BuildingType, BlockType) = building_type::table
                     .inner_join(block_type::table)
-                    .filter(building_type::name.eq(&building_type.name))
-                    .filter(building_type::level.eq(next_level))
+                    .filter(building_type::id.nullable().eq(building_type.next_level_id))
                     .first::<(BuildingType, BlockType)>(conn)
                     .map_err(|err| DieselError {
                         table: "building_type",
@@ -227,6 +248,8 @@ fn get_building_types(
                             level: 0,
                             cost: 0,
                             hp: 0,
+                            max_level: 0,
+                            next_level_id: None,
                         },
                         BlockType {
                             id: 0,
@@ -247,6 +270,7 @@ fn get_building_types(
                     level: building_type.level,
                     cost: building_type.cost,
                     hp: building_type.hp,
+                    is_wall: category == BlockCategory::Wall,
                     next_level_stats: Some(NextLevelBuildingTypeResponse {
                         id: next_level_stats.0.id,
                         block_id: next_level_stats.1.id,
@@ -285,17 +309,9 @@ fn get_attacker_types(
         })?
         .into_iter()
         .map(|attacker_type| {
-            let max_level: i64 = attacker_type::table
-                .filter(attacker_type::name.eq(&attacker_type.name))
-                .count()
-                .get_result::<i64>(conn)
-                .map_err(|err| DieselError {
-                    table: "attacker_type",
-                    function: function!(),
-                    error: err,
-                })
-                .unwrap_or(0);
-            if attacker_type.level >= max_level as i32 {
+            if attacker_type.level >= attacker_type.max_level
+                || attacker_type.next_level_id.is_none()
+            {
                 // The attacker is at max level
                 AttackerTypeResponse {
                     id: attacker_type.id,
@@ -305,13 +321,16 @@ fn get_attacker_types(
                     level: attacker_type.level,
                     cost: attacker_type.cost,
                     name: attacker_type.name,
+                    ability: attacker_type.ability,
+                    ability_power: attacker_type.ability_power,
+                    ability_duration: attacker_type.ability_duration,
+                    ability_cooldown: attacker_type.ability_cooldown,
+                    ability_charges: attacker_type.ability_charges,
                     next_level_stats: None,
                 }
             } else {
-                let next_level = attacker_type.level + 1;
                 let next_level_stats = attacker_type::table
-                    .filter(attacker_type::name.eq(&attacker_type.name))
-                    .filter(attacker_type::level.eq(next_level))
+                    .filter(attacker_type::id.nullable().eq(attacker_type.next_level_id))
                     .first::<AttackerType>(conn)
                     .map_err(|err| DieselError {
                         table: "attacker_type",
@@ -326,6 +345,13 @@ fn get_attacker_types(
                         level: 0,
                         cost: 0,
                         name: "".to_string(),
+                        max_level: 0,
+                        next_level_id: None,
+                        ability: AttackerAbility::None,
+                        ability_power: 0,
+                        ability_duration: 0,
+                        ability_cooldown: 0,
+                        ability_charges: 0,
                     });
                 AttackerTypeResponse {
                     id: attacker_type.id,
@@ -335,6 +361,11 @@ fn get_attacker_types(
                     level: attacker_type.level,
                     cost: attacker_type.cost,
                     name: attacker_type.name,
+                    ability: attacker_type.ability,
+                    ability_power: attacker_type.ability_power,
+                    ability_duration: attacker_type.ability_duration,
+                    ability_cooldown: attacker_type.ability_cooldown,
+                    ability_charges: attacker_type.ability_charges,
                     next_level_stats: Some(NextLevelAttackerTypeResponse {
                         id: next_level_stats.id,
                         max_health: next_level_stats.max_health,
@@ -343,6 +374,11 @@ fn get_attacker_types(
                         level: next_level_stats.level,
                         cost: next_level_stats.cost,
                         name: next_level_stats.name,
+                        ability: next_level_stats.ability,
+                        ability_power: next_level_stats.ability_power,
+                        ability_duration: next_level_stats.ability_duration,
+                        ability_cooldown: next_level_stats.ability_cooldown,
+                        ability_charges: next_level_stats.ability_charges,
                     }),
                 }
             }
@@ -372,17 +408,9 @@ fn get_defender_types(
         })?
         .into_iter()
         .map(|(defender_type, block_id)| {
-            let max_level: i64 = defender_type::table
-                .filter(defender_type::name.eq(&defender_type.name))
-                .count()
-                .get_result::<i64>(conn)
-                .map_err(|err| DieselError {
-                    table: "defender_type",
-                    function: function!(),
-                    error: err,
-                })
-                .unwrap_or(0);
-            if defender_type.level >= max_level as i32 {
+            if defender_type.level >= defender_type.max_level
+                || defender_type.next_level_id.is_none()
+            {
                 //the defender is at max level
                 DefenderTypeResponse {
                     id: defender_type.id,
@@ -396,12 +424,9 @@ fn get_defender_types(
                     next_level_stats: None,
                 }
             } else {
-                let next_level = defender_type.level + 1;
-
                 let next_level_stats: (DefenderType, BlockType) = defender_type::table
                     .inner_join(block_type::table)
-                    .filter(defender_type::name.eq(&defender_type.name))
-                    .filter(defender_type::level.eq(next_level))
+                    .filter(defender_type::id.nullable().eq(defender_type.next_level_id))
                     .first::<(DefenderType, BlockType)>(conn)
                     .map_err(|err| DieselError {
                         table: "building_type",
@@ -417,6 +442,9 @@ fn get_defender_types(
                             level: 0,
                             cost: 0,
                             name: "".to_string(),
+                            max_level: 0,
+                            next_level_id: None,
+                            attack_type_id: None,
                         },
                         BlockType {
                             id: 0,
@@ -471,18 +499,7 @@ fn get_mine_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<MineTyp
         })?
         .into_iter()
         .map(|(mine_type, block_id)| {
-            let max_level: i64 = mine_type::table
-                .filter(mine_type::name.eq(&mine_type.name))
-                .count()
-                .get_result::<i64>(conn)
-                .map_err(|err| DieselError {
-                    table: "mine_type",
-                    function: function!(),
-                    error: err,
-                })
-                .unwrap_or(0);
-
-            if mine_type.level >= max_level as i32 {
+            if mine_type.level >= mine_type.max_level || mine_type.next_level_id.is_none() {
                 //mine is at max level
                 MineTypeResponse {
                     id: mine_type.id,
@@ -492,15 +509,14 @@ fn get_mine_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<MineTyp
                     level: mine_type.level,
                     cost: mine_type.cost,
                     name: mine_type.name,
+                    effect: mine_type.effect,
+                    effect_duration: mine_type.effect_duration,
                     next_level_stats: None,
                 }
             } else {
-                let next_level = mine_type.level + 1;
-
                 let next_level_stats: (MineType, BlockType) = mine_type::table
                     .inner_join(block_type::table)
-                    .filter(mine_type::name.eq(&mine_type.name))
-                    .filter(mine_type::level.eq(next_level))
+                    .filter(mine_type::id.nullable().eq(mine_type.next_level_id))
                     .first::<(MineType, BlockType)>(conn)
                     .map_err(|err| DieselError {
                         table: "building_type",
@@ -515,6 +531,10 @@ fn get_mine_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<MineTyp
                             level: 0,
                             cost: 0,
                             name: "".to_string(),
+                            max_level: 0,
+                            next_level_id: None,
+                            effect: MineEffect::None,
+                            effect_duration: 0,
                         },
                         BlockType {
                             id: 0,
@@ -533,6 +553,8 @@ fn get_mine_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<MineTyp
                     level: mine_type.level,
                     cost: mine_type.cost,
                     name: mine_type.name,
+                    effect: mine_type.effect,
+                    effect_duration: mine_type.effect_duration,
                     next_level_stats: Some(NextLevelMineTypeResponse {
                         id: next_level_stats.0.id,
                         block_id: next_level_stats.1.id,
@@ -541,6 +563,8 @@ fn get_mine_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<MineTyp
                         level: next_level_stats.0.level,
                         cost: next_level_stats.0.cost,
                         name: next_level_stats.0.name,
+                        effect: next_level_stats.0.effect,
+                        effect_duration: next_level_stats.0.effect_duration,
                     }),
                 }
             }
@@ -566,17 +590,7 @@ fn get_emp_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<EmpTypeR
         })?
         .into_iter()
         .map(|emp_type| {
-            let max_level: i64 = emp_type::table
-                .filter(emp_type::name.eq(&emp_type.name))
-                .count()
-                .get_result::<i64>(conn)
-                .map_err(|err| DieselError {
-                    table: "emp_type",
-                    function: function!(),
-                    error: err,
-                })
-                .unwrap_or(0);
-            if emp_type.level >= max_level as i32 {
+            if emp_type.level >= emp_type.max_level || emp_type.next_level_id.is_none() {
                 // The emp is at max level
                 EmpTypeResponse {
                     id: emp_type.id,
@@ -586,13 +600,12 @@ fn get_emp_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<EmpTypeR
                     cost: emp_type.cost,
                     name: emp_type.name,
                     level: emp_type.level,
+                    disable_duration: emp_type.disable_duration,
                     next_level_stats: None,
                 }
             } else {
-                let next_level = emp_type.level + 1;
                 let next_level_stats = emp_type::table
-                    .filter(emp_type::name.eq(&emp_type.name))
-                    .filter(emp_type::level.eq(next_level))
+                    .filter(emp_type::id.nullable().eq(emp_type.next_level_id))
                     .first::<EmpType>(conn)
                     .map_err(|err| DieselError {
                         table: "emp_type",
@@ -601,12 +614,15 @@ fn get_emp_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<EmpTypeR
                     })
                     .unwrap_or(EmpType {
                         id: 0,
-                        att_type: "".to_string(),
+                        att_type: EmpEffect::Damage,
                         attack_radius: 0,
                         attack_damage: 0,
                         cost: 0,
                         name: "".to_string(),
                         level: 0,
+                        max_level: 0,
+                        next_level_id: None,
+                        disable_duration: 0,
                     });
                 EmpTypeResponse {
                     id: emp_type.id,
@@ -616,6 +632,7 @@ fn get_emp_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<EmpTypeR
                     cost: emp_type.cost,
                     name: emp_type.name,
                     level: emp_type.level,
+                    disable_duration: emp_type.disable_duration,
                     next_level_stats: Some(NextLevelEmpTypeResponse {
                         id: next_level_stats.id,
                         att_type: next_level_stats.att_type,
@@ -624,6 +641,7 @@ fn get_emp_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<EmpTypeR
                         cost: next_level_stats.cost,
                         name: next_level_stats.name,
                         level: next_level_stats.level,
+                        disable_duration: next_level_stats.disable_duration,
                     }),
                 }
             }
@@ -633,523 +651,771 @@ fn get_emp_types(player_id: i32, conn: &mut PgConnection) -> Result<Vec<EmpTypeR
     Ok(emps)
 }
 
-pub(crate) fn upgrade_building(
+pub(crate) fn upgrade_item(
     player_id: i32,
     conn: &mut PgConnection,
-    block_id: i32,
-) -> Result<i32> {
-    let user_artifacts = get_user_artifacts(player_id, conn)?;
-
-    //check if the given block id is a building
-    //check if the given user has the block id
-    let exists = select(exists(
-        available_blocks::table
-            .inner_join(block_type::table)
-            .filter(available_blocks::user_id.eq(player_id))
-            .filter(available_blocks::block_type_id.eq(block_id))
-            .filter(available_blocks::category.eq(ItemCategory::Block))
-            .filter(block_type::category.eq(BlockCategory::Building)),
-    ))
-    .get_result::<bool>(conn)?;
-
-    if !exists {
-        return Err(anyhow::anyhow!(
-            "either Block is not a building or the user does not have the block"
-        ));
-    }
-
-    let joined_table = available_blocks::table
-        .inner_join(block_type::table.inner_join(building_type::table))
-        .filter(available_blocks::user_id.eq(player_id))
-        .filter(available_blocks::category.eq(ItemCategory::Block))
-        .filter(block_type::category.eq(BlockCategory::Building));
-
-    let (cost, level, name): (i32, i32, String) = joined_table
-        .clone()
-        .filter(available_blocks::block_type_id.eq(block_id))
-        .select((
-            building_type::cost,
-            building_type::level,
-            building_type::name,
-        ))
-        .first::<(i32, i32, String)>(conn)
-        .map_err(|err| DieselError {
-            table: "building_type",
-            function: function!(),
-            error: err,
-        })?;
+    kind: ItemKind,
+    item_id: i32,
+) -> Result<UpgradeJob> {
+    conn.transaction(|conn| {
+        let mut funds = lock_player_funds(conn, player_id)?;
+
+        //check if the given user has the item
+        let item = match catalog::get_owned_item(conn, player_id, kind, item_id)? {
+            Some(item) => item,
+            None => return Err(InventoryError::ItemNotOwned(kind).into()),
+        };
+
+        if item.is_max_level() {
+            return Err(InventoryError::MaxLevel(kind).into());
+        };
+
+        let next_level_item_id = match catalog::get_next_level_item_id(conn, &item)? {
+            Some(next_level_item_id) => next_level_item_id,
+            None => return Err(InventoryError::MaxLevel(kind).into()),
+        };
+
+        start_upgrade_job(
+            conn,
+            player_id,
+            &mut funds,
+            kind,
+            item_id,
+            next_level_item_id,
+            item.level,
+            item.cost,
+        )
+    })
+}
 
-    let max_level: i64 = building_type::table
-        .filter(building_type::name.eq(&name))
+//must be called inside a transaction holding the player's funds lock
+#[allow(clippy::too_many_arguments)]
+fn start_upgrade_job(
+    conn: &mut PgConnection,
+    player_id: i32,
+    funds: &mut PlayerFunds,
+    item_type: ItemKind,
+    from_id: i32,
+    to_id: i32,
+    level: i32,
+    cost: i32,
+) -> Result<UpgradeJob> {
+    let active_jobs: i64 = upgrade_job::table
+        .filter(upgrade_job::user_id.eq(player_id))
         .count()
         .get_result::<i64>(conn)
         .map_err(|err| DieselError {
-            table: "building_type",
+            table: "upgrade_job",
             function: function!(),
             error: err,
         })?;
 
-    if level >= max_level as i32 {
-        return Err(anyhow::anyhow!("Building is at max level"));
-    };
-    if cost > user_artifacts {
-        return Err(anyhow::anyhow!("Not enough artifacts"));
-    };
-
-    let joined_table = block_type::table
-        .inner_join(building_type::table)
-        .filter(block_type::category.eq(BlockCategory::Building));
-
-    let next_level_block_id: i32 = joined_table
-        .filter(building_type::name.eq(name))
-        .filter(building_type::level.eq(level + 1))
-        .select(block_type::id)
-        .first::<i32>(conn)
-        .map_err(|err| DieselError {
-            table: "available_blocks",
-            function: function!(),
-            error: err,
-        })?;
-
-    let id_of_map = get_user_map_id(player_id, conn)?;
-    let bank_block_type_id = get_block_id_of_bank(conn, &player_id)?;
-    let bank_map_space_id = get_bank_map_space_id(conn, &id_of_map, &bank_block_type_id)?;
-    let artifacts_in_bank = get_building_artifact_count(conn, &id_of_map, &bank_map_space_id)?;
-    if artifacts_in_bank < cost {
-        return Err(anyhow::anyhow!("Not enough artifacts in bank"));
+    if !has_free_builder(active_jobs) {
+        return Err(InventoryError::BuildersBusy.into());
     }
-    let _ = run_transaction(
-        conn,
-        block_id,
-        next_level_block_id,
-        player_id,
-        cost,
-        user_artifacts,
-        bank_map_space_id,
-    );
-    let building_map_space_id = get_building_map_space_id(conn, &id_of_map, &next_level_block_id)?;
-    Ok(building_map_space_id)
-}
 
-pub(crate) fn upgrade_defender(
-    player_id: i32,
-    conn: &mut PgConnection,
-    block_id: i32,
-) -> Result<()> {
-    let user_artifacts = get_user_artifacts(player_id, conn)?;
-
-    //check if the given block id is a defender
-    //check if the given user has the block id
-    let exists = select(exists(
-        available_blocks::table
-            .inner_join(block_type::table)
-            .filter(available_blocks::user_id.eq(player_id))
-            .filter(available_blocks::block_type_id.eq(block_id))
-            .filter(available_blocks::category.eq(ItemCategory::Block))
-            .filter(block_type::category.eq(BlockCategory::Defender)),
+    let already_upgrading = select(exists(
+        upgrade_job::table
+            .filter(upgrade_job::user_id.eq(player_id))
+            .filter(upgrade_job::item_type.eq(item_type))
+            .filter(upgrade_job::from_id.eq(from_id)),
     ))
     .get_result::<bool>(conn)?;
 
-    if !exists {
-        return Err(anyhow::anyhow!(
-            "either Block is not a defender or the user does not have the block"
-        ));
+    if already_upgrading {
+        return Err(InventoryError::AlreadyUpgrading.into());
     }
 
-    let joined_table = available_blocks::table
-        .inner_join(block_type::table.inner_join(defender_type::table))
-        .filter(available_blocks::user_id.eq(player_id))
-        .filter(available_blocks::category.eq(ItemCategory::Block))
-        .filter(block_type::category.eq(BlockCategory::Defender));
+    spend_artifacts(conn, player_id, funds, cost)?;
 
-    let (cost, level, name): (i32, i32, String) = joined_table
-        .clone()
-        .filter(available_blocks::block_type_id.eq(block_id))
-        .select((
-            defender_type::cost,
-            defender_type::level,
-            defender_type::name,
-        ))
-        .first::<(i32, i32, String)>(conn)
-        .map_err(|err| DieselError {
-            table: "defender_type",
-            function: function!(),
-            error: err,
-        })?;
+    let started_at = chrono::Local::now().naive_local();
+    let finishes_at =
+        started_at + chrono::Duration::minutes(UPGRADE_TIME_PER_LEVEL_IN_MINUTES * level as i64);
 
-    let max_level: i64 = defender_type::table
-        .filter(defender_type::name.eq(&name))
-        .count()
-        .get_result::<i64>(conn)
+    let new_upgrade_job = NewUpgradeJob {
+        user_id: &player_id,
+        item_type: &item_type,
+        from_id: &from_id,
+        to_id: &to_id,
+        cost: &cost,
+        started_at: &started_at,
+        finishes_at: &finishes_at,
+    };
+
+    let job: UpgradeJob = diesel::insert_into(upgrade_job::table)
+        .values(&new_upgrade_job)
+        .get_result(conn)
         .map_err(|err| DieselError {
-            table: "defender_type",
+            table: "upgrade_job",
             function: function!(),
             error: err,
         })?;
 
-    if level >= max_level as i32 {
-        return Err(anyhow::anyhow!("Defender is at max level"));
-    };
-    if cost > user_artifacts {
-        return Err(anyhow::anyhow!("Not enough artifacts"));
-    };
+    Ok(job)
+}
 
-    let joined_table = block_type::table
-        .inner_join(defender_type::table)
-        .filter(block_type::category.eq(BlockCategory::Defender));
+fn has_free_builder(active_jobs: i64) -> bool {
+    active_jobs < MAX_BUILDER_SLOTS
+}
 
-    let next_level_block_id: i32 = joined_table
-        .filter(defender_type::name.eq(name))
-        .filter(defender_type::level.eq(level + 1))
-        .select(block_type::id)
-        .first::<i32>(conn)
-        .map_err(|err| DieselError {
-            table: "available_blocks",
-            function: function!(),
-            error: err,
-        })?;
+//every started minute left costs the same
+fn get_finish_cost(seconds_left: i64) -> i32 {
+    let minutes_left = ((seconds_left.max(0) + 59) / 60) as i32;
+    minutes_left * FINISH_UPGRADE_COST_PER_MINUTE
+}
 
-    let id_of_map = get_user_map_id(player_id, conn)?;
-    let bank_block_type_id = get_block_id_of_bank(conn, &player_id)?;
-    let bank_map_space_id = get_bank_map_space_id(conn, &id_of_map, &bank_block_type_id)?;
-    let artifacts_in_bank = get_building_artifact_count(conn, &id_of_map, &bank_map_space_id)?;
-    if artifacts_in_bank < cost {
-        return Err(anyhow::anyhow!("Not enough artifacts in bank"));
-    }
-    run_transaction(
-        conn,
-        block_id,
-        next_level_block_id,
-        player_id,
-        cost,
-        user_artifacts,
-        bank_map_space_id,
-    )
+fn get_cancel_refund(cost: i32) -> i32 {
+    (cost as f32 * UPGRADE_CANCEL_REFUND_PERCENTAGE).floor() as i32
 }
 
-pub(crate) fn upgrade_mine(player_id: i32, conn: &mut PgConnection, block_id: i32) -> Result<()> {
-    let user_artifacts = get_user_artifacts(player_id, conn)?;
-
-    //check if the given block id is a mine
-    //check if the given user has the block id
-    let exists = select(exists(
-        available_blocks::table
-            .inner_join(block_type::table)
-            .filter(available_blocks::user_id.eq(player_id))
-            .filter(available_blocks::block_type_id.eq(block_id))
-            .filter(available_blocks::category.eq(ItemCategory::Block))
-            .filter(block_type::category.eq(BlockCategory::Mine)),
-    ))
-    .get_result::<bool>(conn)?;
+//swaps the item to its next level, must be called inside a transaction
+fn apply_upgrade(conn: &mut PgConnection, job: &UpgradeJob) -> Result<()> {
+    match job.item_type {
+        ItemKind::Attacker => {
+            diesel::update(
+                available_blocks::table
+                    .filter(available_blocks::attacker_type_id.eq(job.from_id))
+                    .filter(available_blocks::user_id.eq(job.user_id)),
+            )
+            .set((
+                available_blocks::attacker_type_id.eq(job.to_id),
+                available_blocks::artifacts_spent.eq(available_blocks::artifacts_spent + job.cost),
+            ))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "available_blocks",
+                function: function!(),
+                error: err,
+            })?;
+        }
+        ItemKind::Emp => {
+            diesel::update(
+                available_blocks::table
+                    .filter(available_blocks::emp_type_id.eq(job.from_id))
+                    .filter(available_blocks::user_id.eq(job.user_id)),
+            )
+            .set((
+                available_blocks::emp_type_id.eq(job.to_id),
+                available_blocks::artifacts_spent.eq(available_blocks::artifacts_spent + job.cost),
+            ))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "available_blocks",
+                function: function!(),
+                error: err,
+            })?;
+        }
+        ItemKind::Building | ItemKind::Defender | ItemKind::Mine => {
+            let ids_of_maps = get_latest_layouts_with_block(conn, &job.user_id, &job.from_id)?;
+
+            diesel::update(
+                available_blocks::table
+                    .filter(available_blocks::block_type_id.eq(job.from_id))
+                    .filter(available_blocks::user_id.eq(job.user_id)),
+            )
+            .set((
+                available_blocks::block_type_id.eq(job.to_id),
+                available_blocks::artifacts_spent.eq(available_blocks::artifacts_spent + job.cost),
+            ))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "available_blocks",
+                function: function!(),
+                error: err,
+            })?;
 
-    if !exists {
-        return Err(anyhow::anyhow!(
-            "either Block is not a mine or the user does not have the block"
-        ));
+            //rewrite the block in place, older versions keep the level they were saved with
+            diesel::update(
+                map_spaces::table
+                    .filter(map_spaces::block_type_id.eq(job.from_id))
+                    .filter(map_spaces::map_id.eq_any(&ids_of_maps)),
+            )
+            .set(map_spaces::block_type_id.eq(job.to_id))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "map_spaces",
+                function: function!(),
+                error: err,
+            })?;
+        }
     }
 
-    let joined_table = available_blocks::table
-        .inner_join(block_type::table.inner_join(mine_type::table))
-        .filter(available_blocks::user_id.eq(player_id))
-        .filter(available_blocks::category.eq(ItemCategory::Block))
-        .filter(block_type::category.eq(BlockCategory::Mine));
-
-    let (cost, level, name): (i32, i32, String) = joined_table
-        .clone()
-        .filter(available_blocks::block_type_id.eq(block_id))
-        .select((mine_type::cost, mine_type::level, mine_type::name))
-        .first::<(i32, i32, String)>(conn)
+    diesel::delete(upgrade_job::table.find(job.id))
+        .execute(conn)
         .map_err(|err| DieselError {
-            table: "mine_type",
+            table: "upgrade_job",
             function: function!(),
             error: err,
         })?;
 
-    let max_level: i64 = mine_type::table
-        .filter(mine_type::name.eq(&name))
-        .count()
-        .get_result::<i64>(conn)
-        .map_err(|err| DieselError {
-            table: "mine_type",
-            function: function!(),
-            error: err,
-        })?;
+    Ok(())
+}
 
-    if level >= max_level as i32 {
-        return Err(anyhow::anyhow!("Defender is at max level"));
-    };
-    if cost > user_artifacts {
-        return Err(anyhow::anyhow!("Not enough artifacts"));
-    };
+pub fn complete_finished_upgrade_jobs(player_id: i32, conn: &mut PgConnection) -> Result<()> {
+    let current_time = chrono::Local::now().naive_local();
 
-    let joined_table = block_type::table
-        .inner_join(mine_type::table)
-        .filter(block_type::category.eq(BlockCategory::Mine));
+    conn.transaction(|conn| {
+        //take the funds lock first so this cannot race with finish or cancel
+        lock_player_funds(conn, player_id)?;
 
-    let next_level_block_id: i32 = joined_table
-        .filter(mine_type::name.eq(name))
-        .filter(mine_type::level.eq(level + 1))
-        .select(block_type::id)
-        .first::<i32>(conn)
-        .map_err(|err| DieselError {
-            table: "available_blocks",
-            function: function!(),
-            error: err,
-        })?;
+        let finished_jobs = upgrade_job::table
+            .filter(upgrade_job::user_id.eq(player_id))
+            .filter(upgrade_job::finishes_at.le(current_time))
+            .load::<UpgradeJob>(conn)
+            .map_err(|err| DieselError {
+                table: "upgrade_job",
+                function: function!(),
+                error: err,
+            })?;
 
-    let id_of_map = get_user_map_id(player_id, conn)?;
-    let bank_block_type_id = get_block_id_of_bank(conn, &player_id)?;
-    let bank_map_space_id = get_bank_map_space_id(conn, &id_of_map, &bank_block_type_id)?;
-    let artifacts_in_bank = get_building_artifact_count(conn, &id_of_map, &bank_map_space_id)?;
-    if artifacts_in_bank < cost {
-        return Err(anyhow::anyhow!("Not enough artifacts in bank"));
-    }
-    run_transaction(
-        conn,
-        block_id,
-        next_level_block_id,
-        player_id,
-        cost,
-        user_artifacts,
-        bank_map_space_id,
-    )
+        for job in finished_jobs.iter() {
+            apply_upgrade(conn, job)?;
+        }
+        Ok(())
+    })
 }
 
-pub(crate) fn upgrade_attacker(
-    player_id: i32,
+//applies the finished upgrades of every player who is not being attacked right now
+pub fn complete_all_finished_upgrade_jobs(
     conn: &mut PgConnection,
-    attacker_id: i32,
+    redis_conn: &mut RedisConn,
 ) -> Result<()> {
-    let user_artifacts = get_user_artifacts(player_id, conn)?;
-
-    let joined_table = available_blocks::table
-        .inner_join(attacker_type::table)
-        .filter(available_blocks::user_id.eq(player_id))
-        .filter(available_blocks::category.eq(ItemCategory::Attacker));
-
-    let exists = select(exists(
-        joined_table
-            .clone()
-            .filter(attacker_type::id.eq(attacker_id)),
-    ))
-    .get_result::<bool>(conn)?;
-
-    if !exists {
-        return Err(anyhow::anyhow!("User does not have the attacker"));
-    }
-
-    let (cost, level, name): (i32, i32, String) = joined_table
-        .filter(attacker_type::id.eq(attacker_id))
-        .select((
-            attacker_type::cost,
-            attacker_type::level,
-            attacker_type::name,
-        ))
-        .first::<(i32, i32, String)>(conn)
+    let current_time = chrono::Local::now().naive_local();
+    let player_ids = upgrade_job::table
+        .filter(upgrade_job::finishes_at.le(current_time))
+        .select(upgrade_job::user_id)
+        .distinct()
+        .load::<i32>(conn)
         .map_err(|err| DieselError {
-            table: "attacker_type",
+            table: "upgrade_job",
             function: function!(),
             error: err,
         })?;
 
-    let max_level: i64 = attacker_type::table
-        .filter(attacker_type::name.eq(&name))
-        .count()
-        .get_result::<i64>(conn)
+    for player_id in player_ids {
+        let is_under_attack = get_game_id_from_redis(player_id, redis_conn, false)
+            .ok()
+            .flatten()
+            .is_some();
+        if is_under_attack {
+            continue;
+        }
+        if let Err(err) = complete_finished_upgrade_jobs(player_id, conn) {
+            log::error!(
+                "Could not complete upgrade jobs of player {}: {}",
+                player_id,
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn get_upgrade_jobs(player_id: i32, conn: &mut PgConnection) -> Result<Vec<UpgradeJob>> {
+    let jobs = upgrade_job::table
+        .filter(upgrade_job::user_id.eq(player_id))
+        .order_by(upgrade_job::finishes_at.asc())
+        .load::<UpgradeJob>(conn)
         .map_err(|err| DieselError {
-            table: "attacker_type",
+            table: "upgrade_job",
             function: function!(),
             error: err,
         })?;
+    Ok(jobs)
+}
 
-    if level >= max_level as i32 {
-        return Err(anyhow::anyhow!("Attacker is at max level"));
-    };
-    if cost > user_artifacts {
-        return Err(anyhow::anyhow!("Not enough artifacts"));
-    };
-    let id_of_map = get_user_map_id(player_id, conn)?;
-    let bank_block_type_id = get_block_id_of_bank(conn, &player_id)?;
-    let bank_map_space_id = get_bank_map_space_id(conn, &id_of_map, &bank_block_type_id)?;
-    let artifacts_in_bank = get_building_artifact_count(conn, &id_of_map, &bank_map_space_id)?;
-    if artifacts_in_bank < cost {
-        return Err(anyhow::anyhow!("Not enough artifacts in bank"));
-    }
-    let next_level_attacker_id: i32 = attacker_type::table
-        .filter(attacker_type::name.eq(name))
-        .filter(attacker_type::level.eq(level + 1))
-        .select(attacker_type::id)
-        .first::<i32>(conn)
+//locks the job row, must be called inside a transaction
+fn get_upgrade_job_for_update(
+    player_id: i32,
+    job_id: i32,
+    conn: &mut PgConnection,
+) -> Result<UpgradeJob> {
+    let job = upgrade_job::table
+        .filter(upgrade_job::id.eq(job_id))
+        .filter(upgrade_job::user_id.eq(player_id))
+        .for_update()
+        .first::<UpgradeJob>(conn)
+        .optional()
         .map_err(|err| DieselError {
-            table: "attacker_type",
+            table: "upgrade_job",
             function: function!(),
             error: err,
         })?;
+
+    job.ok_or_else(|| InventoryError::UpgradeJobNotFound.into())
+}
+
+pub(crate) fn finish_upgrade_job(
+    player_id: i32,
+    job_id: i32,
+    conn: &mut PgConnection,
+) -> Result<i32> {
     conn.transaction(|conn| {
-        diesel::update(
-            available_blocks::table
-                .filter(available_blocks::attacker_type_id.eq(attacker_id))
-                .filter(available_blocks::user_id.eq(player_id)),
-        )
-        .set(available_blocks::attacker_type_id.eq(next_level_attacker_id))
-        .execute(conn)?;
+        let mut funds = lock_player_funds(conn, player_id)?;
+        let job = get_upgrade_job_for_update(player_id, job_id, conn)?;
+
+        let seconds_left = (job.finishes_at - chrono::Local::now().naive_local()).num_seconds();
+        let cost = get_finish_cost(seconds_left);
+
+        spend_artifacts(conn, player_id, &mut funds, cost)?;
+        apply_upgrade(conn, &job)?;
+
+        Ok(cost)
+    })
+}
 
-        //update artifacts in bank
-        diesel::update(artifact::table.filter(artifact::map_space_id.eq(bank_map_space_id)))
-            .set(artifact::count.eq(artifact::count - cost))
+pub(crate) fn cancel_upgrade_job(
+    player_id: i32,
+    job_id: i32,
+    conn: &mut PgConnection,
+) -> Result<i32> {
+    conn.transaction(|conn| {
+        let mut funds = lock_player_funds(conn, player_id)?;
+        let job = get_upgrade_job_for_update(player_id, job_id, conn)?;
+        let refund = get_cancel_refund(job.cost);
+
+        refund_artifacts(conn, player_id, &mut funds, refund)?;
+
+        diesel::delete(upgrade_job::table.find(job.id))
             .execute(conn)
             .map_err(|err| DieselError {
-                table: "artifact",
+                table: "upgrade_job",
                 function: function!(),
                 error: err,
             })?;
 
-        diesel::update(user::table.filter(user::id.eq(player_id)))
-            .set(user::artifacts.eq(user_artifacts - cost))
-            .execute(conn)?;
-
-        Ok(())
+        Ok(refund)
     })
 }
 
-pub(crate) fn upgrade_emp(player_id: i32, conn: &mut PgConnection, emp_id: i32) -> Result<()> {
-    let user_artifacts = get_user_artifacts(player_id, conn)?;
+//the item cannot change while an upgrade job for it is running
+fn check_no_upgrade_in_progress(
+    conn: &mut PgConnection,
+    player_id: i32,
+    kind: ItemKind,
+    item_id: i32,
+) -> Result<()> {
+    let is_upgrading = select(exists(
+        upgrade_job::table
+            .filter(upgrade_job::user_id.eq(player_id))
+            .filter(upgrade_job::item_type.eq(kind))
+            .filter(upgrade_job::from_id.eq(item_id)),
+    ))
+    .get_result::<bool>(conn)?;
 
-    let joined_table = available_blocks::table
-        .inner_join(emp_type::table)
+    if is_upgrading {
+        return Err(InventoryError::UpgradeInProgress.into());
+    }
+    Ok(())
+}
+
+//map spaces of a block on the player's base along with the artifacts stored in them
+fn get_placed_blocks(
+    conn: &mut PgConnection,
+    map_id: i32,
+    block_type_id: i32,
+) -> Result<Vec<(i32, Option<i32>)>> {
+    let placed_blocks = map_spaces::table
+        .left_join(artifact::table)
+        .filter(map_spaces::map_id.eq(map_id))
+        .filter(map_spaces::block_type_id.eq(block_type_id))
+        .select((map_spaces::id, artifact::count.nullable()))
+        .load::<(i32, Option<i32>)>(conn)
+        .map_err(|err| DieselError {
+            table: "map_spaces",
+            function: function!(),
+            error: err,
+        })?;
+    Ok(placed_blocks)
+}
+
+//removes an item from available_blocks and returns what was spent on it
+fn delete_available_item(
+    conn: &mut PgConnection,
+    player_id: i32,
+    kind: ItemKind,
+    item_id: i32,
+) -> Result<i32> {
+    let owned_items = available_blocks::table
         .filter(available_blocks::user_id.eq(player_id))
-        .filter(available_blocks::category.eq(ItemCategory::Emp));
+        .filter(available_blocks::category.eq(kind.item_category()));
+
+    let artifacts_spent = match kind {
+        ItemKind::Attacker => {
+            diesel::delete(owned_items.filter(available_blocks::attacker_type_id.eq(item_id)))
+                .returning(available_blocks::artifacts_spent)
+                .get_results::<i32>(conn)
+        }
+        ItemKind::Emp => {
+            diesel::delete(owned_items.filter(available_blocks::emp_type_id.eq(item_id)))
+                .returning(available_blocks::artifacts_spent)
+                .get_results::<i32>(conn)
+        }
+        ItemKind::Building | ItemKind::Defender | ItemKind::Mine => {
+            diesel::delete(owned_items.filter(available_blocks::block_type_id.eq(item_id)))
+                .returning(available_blocks::artifacts_spent)
+                .get_results::<i32>(conn)
+        }
+    }
+    .map_err(|err| DieselError {
+        table: "available_blocks",
+        function: function!(),
+        error: err,
+    })?;
 
-    let exists = select(exists(joined_table.clone().filter(emp_type::id.eq(emp_id))))
-        .get_result::<bool>(conn)?;
+    Ok(artifacts_spent.iter().sum())
+}
 
-    if !exists {
-        return Err(anyhow::anyhow!("User does not have the emp"));
+//what was spent on an item the player still owns
+fn get_artifacts_spent(
+    conn: &mut PgConnection,
+    player_id: i32,
+    kind: ItemKind,
+    item_id: i32,
+) -> Result<i32> {
+    let owned_items = available_blocks::table
+        .filter(available_blocks::user_id.eq(player_id))
+        .filter(available_blocks::category.eq(kind.item_category()))
+        .select(available_blocks::artifacts_spent);
+
+    let artifacts_spent = match kind {
+        ItemKind::Attacker => owned_items
+            .filter(available_blocks::attacker_type_id.eq(item_id))
+            .load::<i32>(conn),
+        ItemKind::Emp => owned_items
+            .filter(available_blocks::emp_type_id.eq(item_id))
+            .load::<i32>(conn),
+        ItemKind::Building | ItemKind::Defender | ItemKind::Mine => owned_items
+            .filter(available_blocks::block_type_id.eq(item_id))
+            .load::<i32>(conn),
     }
+    .map_err(|err| DieselError {
+        table: "available_blocks",
+        function: function!(),
+        error: err,
+    })?;
+
+    Ok(artifacts_spent.iter().sum())
+}
 
-    let (cost, level, name): (i32, i32, String) = joined_table
-        .filter(emp_type::id.eq(emp_id))
-        .select((emp_type::cost, emp_type::level, emp_type::name))
-        .first::<(i32, i32, String)>(conn)
+//share of the artifacts spent on an item that selling or downgrading gives back
+fn get_refund(artifacts_spent: i32) -> i32 {
+    (artifacts_spent as f32 * ITEM_REFUND_PERCENTAGE).floor() as i32
+}
+
+//a downgrade returns the last upgrade, but never more than was spent on the item
+fn get_downgrade_spend(artifacts_spent: i32, upgrade_cost: i32) -> i32 {
+    upgrade_cost.min(artifacts_spent)
+}
+
+//the layout being defended and the level it was built for
+fn get_active_layout(conn: &mut PgConnection, player_id: i32) -> Result<(i32, i32)> {
+    let active_layout = map_layout::table
+        .filter(map_layout::player.eq(player_id))
+        .filter(map_layout::is_active.eq(true))
+        .select((map_layout::id, map_layout::level_id))
+        .first::<(i32, i32)>(conn)
         .map_err(|err| DieselError {
-            table: "emp_type",
+            table: "map_layout",
             function: function!(),
             error: err,
         })?;
+    Ok(active_layout)
+}
 
-    let max_level: i64 = emp_type::table
-        .filter(emp_type::name.eq(&name))
-        .count()
-        .get_result::<i64>(conn)
+//every block left on the given layouts has to fit the constraints of their level
+fn check_level_constraints(conn: &mut PgConnection, ids_of_maps: &[i32]) -> Result<()> {
+    let layouts = map_layout::table
+        .filter(map_layout::id.eq_any(ids_of_maps))
+        .select((map_layout::id, map_layout::level_id))
+        .load::<(i32, i32)>(conn)
         .map_err(|err| DieselError {
-            table: "emp_type",
+            table: "map_layout",
             function: function!(),
             error: err,
         })?;
 
-    if level >= max_level as i32 {
-        return Err(anyhow::anyhow!("Emp is at max level"));
-    };
-    if cost > user_artifacts {
-        return Err(anyhow::anyhow!("Not enough artifacts"));
-    };
+    for (id_of_map, map_level_id) in layouts {
+        check_layout_level_constraints(conn, id_of_map, map_level_id)?;
+    }
+    Ok(())
+}
 
-    let next_level_emp_id: i32 = emp_type::table
-        .filter(emp_type::name.eq(name))
-        .filter(emp_type::level.eq(level + 1))
-        .select(emp_type::id)
-        .first::<i32>(conn)
+fn check_layout_level_constraints(
+    conn: &mut PgConnection,
+    id_of_map: i32,
+    map_level_id: i32,
+) -> Result<()> {
+    let placed_blocks = map_spaces::table
+        .filter(map_spaces::map_id.eq(id_of_map))
+        .group_by(map_spaces::block_type_id)
+        .select((map_spaces::block_type_id, diesel::dsl::count_star()))
+        .load::<(i32, i64)>(conn)
         .map_err(|err| DieselError {
-            table: "emp_type",
+            table: "map_spaces",
             function: function!(),
             error: err,
         })?;
+    let blocks_allowed: HashMap<i32, i32> = level_constraints::table
+        .filter(level_constraints::level_id.eq(map_level_id))
+        .select((level_constraints::block_id, level_constraints::no_of_blocks))
+        .load::<(i32, i32)>(conn)
+        .map_err(|err| DieselError {
+            table: "level_constraints",
+            function: function!(),
+            error: err,
+        })?
+        .into_iter()
+        .collect();
 
-    let id_of_map = get_user_map_id(player_id, conn)?;
-    let bank_block_type_id = get_block_id_of_bank(conn, &player_id)?;
-    let bank_map_space_id = get_bank_map_space_id(conn, &id_of_map, &bank_block_type_id)?;
-    let artifacts_in_bank = get_building_artifact_count(conn, &id_of_map, &bank_map_space_id)?;
-    if artifacts_in_bank < cost {
-        return Err(anyhow::anyhow!("Not enough artifacts in bank"));
+    for (block_type_id, placed_count) in placed_blocks {
+        if block_type_id == ROAD_ID {
+            continue;
+        }
+        let allowed_count = blocks_allowed.get(&block_type_id).copied().unwrap_or(0);
+        if placed_count > allowed_count as i64 {
+            return Err(InventoryError::LevelConstraintsExceeded.into());
+        }
     }
-    conn.transaction(|conn| {
-        diesel::update(
-            available_blocks::table
-                .filter(available_blocks::emp_type_id.eq(emp_id))
-                .filter(available_blocks::user_id.eq(player_id)),
-        )
-        .set(available_blocks::emp_type_id.eq(next_level_emp_id))
-        .execute(conn)?;
-
-        //update artifacts in bank
-        diesel::update(artifact::table.filter(artifact::map_space_id.eq(bank_map_space_id)))
-            .set(artifact::count.eq(artifact::count - cost))
-            .execute(conn)
-            .map_err(|err| DieselError {
-                table: "artifact",
-                function: function!(),
-                error: err,
-            })?;
+    Ok(())
+}
 
-        diesel::update(user::table.filter(user::id.eq(player_id)))
-            .set(user::artifacts.eq(user_artifacts - cost))
-            .execute(conn)?;
+pub(crate) fn sell_item(
+    player_id: i32,
+    conn: &mut PgConnection,
+    kind: ItemKind,
+    item_id: i32,
+) -> Result<i32> {
+    conn.transaction(|conn| {
+        let mut funds = lock_player_funds(conn, player_id)?;
+
+        let item = match catalog::get_owned_item(conn, player_id, kind, item_id)? {
+            Some(item) => item,
+            None => return Err(InventoryError::ItemNotOwned(kind).into()),
+        };
+        check_no_upgrade_in_progress(conn, player_id, kind, item_id)?;
+
+        match kind {
+            ItemKind::Building => {
+                if item.type_id == ROAD_ID || item.name == BANK_BUILDING_NAME {
+                    return Err(InventoryError::NotSellable.into());
+                }
+            }
+            ItemKind::Attacker => {
+                let attackers_owned: i64 = available_blocks::table
+                    .filter(available_blocks::user_id.eq(player_id))
+                    .filter(available_blocks::category.eq(ItemCategory::Attacker))
+                    .count()
+                    .get_result(conn)
+                    .map_err(|err| DieselError {
+                        table: "available_blocks",
+                        function: function!(),
+                        error: err,
+                    })?;
+                if attackers_owned <= 1 {
+                    return Err(InventoryError::LastAttacker.into());
+                }
+            }
+            ItemKind::Defender | ItemKind::Mine | ItemKind::Emp => {}
+        }
+
+        if !kind.block_categories().is_empty() {
+            let (id_of_map, _) = get_active_layout(conn, player_id)?;
+            let placed_blocks = get_placed_blocks(conn, id_of_map, item_id)?;
+            if placed_blocks
+                .iter()
+                .any(|(_, artifacts)| artifacts.unwrap_or(0) > 0)
+            {
+                return Err(InventoryError::ArtifactsInBuilding.into());
+            }
+        }
+
+        let artifacts_spent = delete_available_item(conn, player_id, kind, item_id)?;
+        let refund = get_refund(artifacts_spent);
+        refund_artifacts(conn, player_id, &mut funds, refund)?;
+
+        if !kind.block_categories().is_empty() {
+            //remove the block from the latest version of every layout slot
+            let ids_of_maps = get_latest_layouts_with_block(conn, &player_id, &item_id)?;
+            let map_space_ids: Vec<i32> = map_spaces::table
+                .filter(map_spaces::map_id.eq_any(&ids_of_maps))
+                .filter(map_spaces::block_type_id.eq(item_id))
+                .select(map_spaces::id)
+                .load::<i32>(conn)
+                .map_err(|err| DieselError {
+                    table: "map_spaces",
+                    function: function!(),
+                    error: err,
+                })?;
+            diesel::delete(artifact::table.filter(artifact::map_space_id.eq_any(&map_space_ids)))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "artifact",
+                    function: function!(),
+                    error: err,
+                })?;
+            diesel::delete(map_spaces::table.filter(map_spaces::id.eq_any(&map_space_ids)))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "map_spaces",
+                    function: function!(),
+                    error: err,
+                })?;
+            check_level_constraints(conn, &ids_of_maps)?;
+        }
 
-        Ok(())
+        Ok(refund)
     })
 }
 
-#[warn(clippy::too_many_arguments)]
-fn run_transaction(
-    conn: &mut PgConnection,
-    block_id: i32,
-    next_level_block_id: i32,
+pub(crate) fn downgrade_item(
     player_id: i32,
-    cost: i32,
-    user_artifacts: i32,
-    bank_map_space_id: i32,
-) -> Result<(), anyhow::Error> {
+    conn: &mut PgConnection,
+    kind: ItemKind,
+    item_id: i32,
+) -> Result<i32> {
     conn.transaction(|conn| {
-        let id_of_map = get_user_map_id(player_id, conn)?;
-
-        diesel::update(
-            available_blocks::table
-                .filter(available_blocks::block_type_id.eq(block_id))
-                .filter(available_blocks::user_id.eq(player_id)),
-        )
-        .set(available_blocks::block_type_id.eq(next_level_block_id))
-        .execute(conn)?;
-
-        diesel::update(user::table.filter(user::id.eq(player_id)))
-            .set(user::artifacts.eq(user_artifacts - cost))
-            .execute(conn)?;
-
-        //update artifacts in bank
-        diesel::update(artifact::table.filter(artifact::map_space_id.eq(bank_map_space_id)))
-            .set(artifact::count.eq(artifact::count - cost))
-            .execute(conn)
-            .map_err(|err| DieselError {
-                table: "artifact",
-                function: function!(),
-                error: err,
-            })?;
+        let mut funds = lock_player_funds(conn, player_id)?;
+
+        let item = match catalog::get_owned_item(conn, player_id, kind, item_id)? {
+            Some(item) => item,
+            None => return Err(InventoryError::ItemNotOwned(kind).into()),
+        };
+        check_no_upgrade_in_progress(conn, player_id, kind, item_id)?;
+
+        let (previous_type_id, previous_cost) =
+            match catalog::get_previous_level(conn, kind, item.type_id)? {
+                Some(previous_level) => previous_level,
+                None => return Err(InventoryError::LowestLevel(kind).into()),
+            };
+        let previous_item_id = match catalog::get_item_id_of_type(conn, kind, previous_type_id)? {
+            Some(previous_item_id) => previous_item_id,
+            None => return Err(InventoryError::LowestLevel(kind).into()),
+        };
+        if catalog::get_owned_item(conn, player_id, kind, previous_item_id)?.is_some() {
+            return Err(InventoryError::LowerLevelOwned.into());
+        }
+
+        let artifacts_spent = get_artifacts_spent(conn, player_id, kind, item_id)?;
+        let spend_returned = get_downgrade_spend(artifacts_spent, previous_cost);
+        let refund = get_refund(spend_returned);
+        refund_artifacts(conn, player_id, &mut funds, refund)?;
+
+        match kind {
+            ItemKind::Attacker => {
+                diesel::update(
+                    available_blocks::table
+                        .filter(available_blocks::attacker_type_id.eq(item_id))
+                        .filter(available_blocks::user_id.eq(player_id)),
+                )
+                .set((
+                    available_blocks::attacker_type_id.eq(previous_item_id),
+                    available_blocks::artifacts_spent
+                        .eq(available_blocks::artifacts_spent - spend_returned),
+                ))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "available_blocks",
+                    function: function!(),
+                    error: err,
+                })?;
+            }
+            ItemKind::Emp => {
+                diesel::update(
+                    available_blocks::table
+                        .filter(available_blocks::emp_type_id.eq(item_id))
+                        .filter(available_blocks::user_id.eq(player_id)),
+                )
+                .set((
+                    available_blocks::emp_type_id.eq(previous_item_id),
+                    available_blocks::artifacts_spent
+                        .eq(available_blocks::artifacts_spent - spend_returned),
+                ))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "available_blocks",
+                    function: function!(),
+                    error: err,
+                })?;
+            }
+            ItemKind::Building | ItemKind::Defender | ItemKind::Mine => {
+                let (id_of_map, _) = get_active_layout(conn, player_id)?;
+                let placed_blocks = get_placed_blocks(conn, id_of_map, item_id)?;
+
+                if kind == ItemKind::Building {
+                    let current_building = building_type::table
+                        .find(item.type_id)
+                        .first::<BuildingType>(conn)
+                        .map_err(|err| DieselError {
+                            table: "building_type",
+                            function: function!(),
+                            error: err,
+                        })?;
+                    let previous_building = building_type::table
+                        .find(previous_type_id)
+                        .first::<BuildingType>(conn)
+                        .map_err(|err| DieselError {
+                            table: "building_type",
+                            function: function!(),
+                            error: err,
+                        })?;
+
+                    if !placed_blocks.is_empty()
+                        && (previous_building.width != current_building.width
+                            || previous_building.height != current_building.height)
+                    {
+                        return Err(InventoryError::SizeMismatch.into());
+                    }
+                    if placed_blocks
+                        .iter()
+                        .any(|(_, artifacts)| artifacts.unwrap_or(0) > previous_building.capacity)
+                    {
+                        return Err(InventoryError::ArtifactsInBuilding.into());
+                    }
+                }
 
-        //update map spaces
-        diesel::update(
-            map_spaces::table
-                .filter(map_spaces::block_type_id.eq(block_id))
-                .filter(map_spaces::map_id.eq(id_of_map)),
-        )
-        .set(map_spaces::block_type_id.eq(next_level_block_id))
-        .execute(conn)?;
+                diesel::update(
+                    available_blocks::table
+                        .filter(available_blocks::block_type_id.eq(item_id))
+                        .filter(available_blocks::user_id.eq(player_id)),
+                )
+                .set((
+                    available_blocks::block_type_id.eq(previous_item_id),
+                    available_blocks::artifacts_spent
+                        .eq(available_blocks::artifacts_spent - spend_returned),
+                ))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "available_blocks",
+                    function: function!(),
+                    error: err,
+                })?;
+
+                let ids_of_maps = get_latest_layouts_with_block(conn, &player_id, &item_id)?;
+                diesel::update(
+                    map_spaces::table
+                        .filter(map_spaces::block_type_id.eq(item_id))
+                        .filter(map_spaces::map_id.eq_any(&ids_of_maps)),
+                )
+                .set(map_spaces::block_type_id.eq(previous_item_id))
+                .execute(conn)
+                .map_err(|err| DieselError {
+                    table: "map_spaces",
+                    function: function!(),
+                    error: err,
+                })?;
+                check_level_constraints(conn, &ids_of_maps)?;
+            }
+        }
 
-        Ok(())
+        Ok(refund)
     })
 }
 
 pub fn get_user_map_id(player_id: i32, conn: &mut PgConnection) -> Result<i32> {
     let id_of_map = map_layout::table
         .filter(map_layout::player.eq(player_id))
+        .filter(map_layout::is_active.eq(true))
         .select(map_layout::id)
         .first::<i32>(conn)
         .map_err(|err| DieselError {
@@ -1228,12 +1494,13 @@ pub fn get_building_map_space_id(
     conn: &mut PgConnection,
     filtered_layout_id: &i32,
     block_id: &i32,
-) -> Result<i32> {
+) -> Result<Option<i32>> {
     let fetched_building_map_space_id = map_spaces::table
         .filter(map_spaces::map_id.eq(filtered_layout_id))
         .filter(map_spaces::block_type_id.eq(block_id))
         .select(map_spaces::id)
         .first::<i32>(conn)
+        .optional()
         .map_err(|err| DieselError {
             table: "map_spaces",
             function: function!(),
@@ -1241,3 +1508,59 @@ pub fn get_building_map_space_id(
         })?;
     Ok(fetched_building_map_space_id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_builder_slot_can_be_used() {
+        assert!(has_free_builder(0));
+        assert!(has_free_builder(MAX_BUILDER_SLOTS - 1));
+        assert!(!has_free_builder(MAX_BUILDER_SLOTS));
+    }
+
+    #[test]
+    fn finishing_charges_every_started_minute() {
+        assert_eq!(get_finish_cost(0), 0);
+        assert_eq!(get_finish_cost(1), FINISH_UPGRADE_COST_PER_MINUTE);
+        assert_eq!(get_finish_cost(60), FINISH_UPGRADE_COST_PER_MINUTE);
+        assert_eq!(get_finish_cost(61), 2 * FINISH_UPGRADE_COST_PER_MINUTE);
+    }
+
+    #[test]
+    fn finishing_an_overdue_upgrade_is_free() {
+        assert_eq!(get_finish_cost(-120), 0);
+    }
+
+    #[test]
+    fn cancelling_refunds_a_share_of_the_upgrade_cost() {
+        assert_eq!(
+            get_cancel_refund(100),
+            (100.0 * UPGRADE_CANCEL_REFUND_PERCENTAGE) as i32
+        );
+        assert_eq!(get_cancel_refund(0), 0);
+        assert!(get_cancel_refund(7) <= 7);
+    }
+
+    #[test]
+    fn selling_refunds_a_share_of_what_was_spent() {
+        assert_eq!(
+            get_refund(300),
+            (300.0 * ITEM_REFUND_PERCENTAGE).floor() as i32
+        );
+        assert_eq!(get_refund(0), 0);
+    }
+
+    #[test]
+    fn downgrading_returns_the_last_upgrade() {
+        assert_eq!(get_downgrade_spend(500, 200), 200);
+    }
+
+    #[test]
+    fn downgrading_never_returns_more_than_was_spent() {
+        //a higher level bought in a bundle only cost its share of the bundle
+        assert_eq!(get_downgrade_spend(50, 200), 50);
+        assert_eq!(get_downgrade_spend(0, 200), 0);
+    }
+}
@@ -4,6 +4,7 @@ pub mod defense;
 pub mod error;
 pub mod game;
 pub mod inventory;
+pub mod shop;
 pub mod user;
 pub mod util;
 
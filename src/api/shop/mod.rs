@@ -0,0 +1,51 @@
+use super::{
+    auth::session::AuthUser,
+    error,
+    util::{is_under_attack, run_idempotent},
+    PgPool, RedisPool,
+};
+use actix_web::{error::ErrorBadRequest, web, HttpRequest, Responder, Result};
+
+pub mod util;
+
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("").route(web::get().to(get_shop)))
+        .service(web::resource("/{bundle_id}/buy").route(web::post().to(buy_bundle)));
+}
+
+async fn get_shop(pool: web::Data<PgPool>, user: AuthUser) -> Result<impl Responder> {
+    let user_id = user.0;
+    let response = web::block(move || {
+        let mut conn = pool.get()?;
+        util::get_shop(user_id, &mut conn)
+    })
+    .await?
+    .map_err(|err| error::handle_error(err.into()))?;
+
+    Ok(web::Json(response))
+}
+
+async fn buy_bundle(
+    req: HttpRequest,
+    bundle_id: web::Path<i32>,
+    pool: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    user: AuthUser,
+) -> Result<impl Responder> {
+    let user_id = user.0;
+    let bundle_id = bundle_id.into_inner();
+
+    if is_under_attack(user_id, &redis_pool)? {
+        return Err(ErrorBadRequest("You are under attack. Cannot buy now"));
+    }
+
+    run_idempotent(
+        &req,
+        pool,
+        user_id,
+        "shop",
+        move |conn| util::buy_bundle(user_id, bundle_id, conn),
+        error::handle_item_error,
+    )
+    .await
+}
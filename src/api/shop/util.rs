@@ -0,0 +1,400 @@
+use crate::api::error::ShopError;
+use crate::api::inventory::catalog::{self, ItemChain, ItemChains};
+use crate::api::util::{get_current_levels_fixture, lock_player_funds, spend_artifacts};
+use crate::error::DieselError;
+use crate::models::{
+    AvailableBlocks, ItemCategory, LevelsFixture, NewAvailableBlocks, ShopBundle, ShopBundleItem,
+};
+use crate::schema::{
+    available_blocks, level_constraints, levels_fixture, shop_bundle, shop_bundle_item,
+};
+use crate::util::function;
+use anyhow::{Ok, Result};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Serialize)]
+pub struct ShopBundleResponse {
+    pub id: i32,
+    pub name: String,
+    pub cost: i32,
+    pub unlocks_at: NaiveDateTime,
+    pub is_owned: bool,
+    pub items: Vec<ShopBundleItem>,
+}
+
+#[derive(Serialize)]
+pub struct ShopResponse {
+    pub available: Vec<ShopBundleResponse>,
+    pub locked: Vec<ShopBundleResponse>,
+}
+
+#[derive(Serialize)]
+pub struct ShopPurchaseResponse {
+    pub bundle_id: i32,
+    pub items_added: Vec<ShopBundleItem>,
+    pub artifacts: i32,
+    pub artifacts_in_bank: i32,
+}
+
+//owning any level of an upgrade chain counts as owning every level of it
+struct OwnedItems {
+    item_ids: HashSet<(ItemCategory, i32)>,
+    chains: HashSet<ItemChain>,
+    item_chains: ItemChains,
+}
+
+fn get_item_id(
+    category: ItemCategory,
+    block_type_id: Option<i32>,
+    attacker_type_id: Option<i32>,
+    emp_type_id: Option<i32>,
+) -> Option<i32> {
+    match category {
+        ItemCategory::Block => block_type_id,
+        ItemCategory::Attacker => attacker_type_id,
+        ItemCategory::Emp => emp_type_id,
+    }
+}
+
+impl OwnedItems {
+    fn contains(&self, item: &ShopBundleItem) -> bool {
+        let item_id = match get_item_id(
+            item.category,
+            item.block_type_id,
+            item.attacker_type_id,
+            item.emp_type_id,
+        ) {
+            Some(item_id) => item_id,
+            None => return false,
+        };
+
+        self.item_ids.contains(&(item.category, item_id))
+            || self
+                .item_chains
+                .get(item.category, item_id)
+                .is_some_and(|chain| self.chains.contains(&chain))
+    }
+}
+
+fn get_owned_items(conn: &mut PgConnection, player_id: i32) -> Result<OwnedItems> {
+    let available_blocks = available_blocks::table
+        .filter(available_blocks::user_id.eq(player_id))
+        .load::<AvailableBlocks>(conn)
+        .map_err(|err| DieselError {
+            table: "available_blocks",
+            function: function!(),
+            error: err,
+        })?;
+
+    let mut owned_items = OwnedItems {
+        item_ids: HashSet::new(),
+        chains: HashSet::new(),
+        item_chains: catalog::get_item_chains(conn)?,
+    };
+    for available_block in available_blocks {
+        let category = available_block.category;
+        let item_id = match get_item_id(
+            category,
+            available_block.block_type_id,
+            available_block.attacker_type_id,
+            available_block.emp_type_id,
+        ) {
+            Some(item_id) => item_id,
+            None => continue,
+        };
+
+        owned_items.item_ids.insert((category, item_id));
+        owned_items
+            .chains
+            .extend(owned_items.item_chains.get(category, item_id));
+    }
+
+    Ok(owned_items)
+}
+
+//blocks without a level constraint for the current level cannot be placed yet
+fn get_placeable_blocks(conn: &mut PgConnection, level_id: i32) -> Result<HashSet<i32>> {
+    Ok(level_constraints::table
+        .filter(level_constraints::level_id.eq(level_id))
+        .filter(level_constraints::no_of_blocks.gt(0))
+        .select(level_constraints::block_id)
+        .load::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "level_constraints",
+            function: function!(),
+            error: err,
+        })?
+        .into_iter()
+        .collect())
+}
+
+fn is_bundle_unlocked(
+    bundle_level: &LevelsFixture,
+    current_level: &LevelsFixture,
+    items: &[ShopBundleItem],
+    placeable_blocks: &HashSet<i32>,
+) -> bool {
+    bundle_level.start_date <= current_level.start_date
+        && items.iter().all(|item| match item.category {
+            ItemCategory::Block => item
+                .block_type_id
+                .is_some_and(|id| placeable_blocks.contains(&id)),
+            ItemCategory::Attacker | ItemCategory::Emp => true,
+        })
+}
+
+//the bundle cost is split over the items added, the first one takes the remainder
+fn get_item_share(cost: i32, items_count: i32, index: usize) -> i32 {
+    cost / items_count + if index == 0 { cost % items_count } else { 0 }
+}
+
+fn get_bundle_items(
+    conn: &mut PgConnection,
+    bundle_ids: &[i32],
+) -> Result<HashMap<i32, Vec<ShopBundleItem>>> {
+    let items = shop_bundle_item::table
+        .filter(shop_bundle_item::bundle_id.eq_any(bundle_ids))
+        .order_by(shop_bundle_item::id)
+        .load::<ShopBundleItem>(conn)
+        .map_err(|err| DieselError {
+            table: "shop_bundle_item",
+            function: function!(),
+            error: err,
+        })?;
+
+    let mut bundle_items: HashMap<i32, Vec<ShopBundleItem>> = HashMap::new();
+    for item in items {
+        bundle_items.entry(item.bundle_id).or_default().push(item);
+    }
+    Ok(bundle_items)
+}
+
+pub fn get_shop(player_id: i32, conn: &mut PgConnection) -> Result<ShopResponse> {
+    let current_level = get_current_levels_fixture(conn)?;
+    let placeable_blocks = get_placeable_blocks(conn, current_level.id)?;
+    let owned_items = get_owned_items(conn, player_id)?;
+
+    let bundles = shop_bundle::table
+        .inner_join(levels_fixture::table)
+        .order_by((levels_fixture::start_date, shop_bundle::cost))
+        .load::<(ShopBundle, LevelsFixture)>(conn)
+        .map_err(|err| DieselError {
+            table: "shop_bundle",
+            function: function!(),
+            error: err,
+        })?;
+
+    let bundle_ids: Vec<i32> = bundles.iter().map(|(bundle, _)| bundle.id).collect();
+    let mut bundle_items = get_bundle_items(conn, &bundle_ids)?;
+
+    let mut response = ShopResponse {
+        available: Vec::new(),
+        locked: Vec::new(),
+    };
+    for (bundle, bundle_level) in bundles {
+        let items = bundle_items.remove(&bundle.id).unwrap_or_default();
+        let is_unlocked =
+            is_bundle_unlocked(&bundle_level, &current_level, &items, &placeable_blocks);
+        let bundle_response = ShopBundleResponse {
+            id: bundle.id,
+            name: bundle.name,
+            cost: bundle.cost,
+            unlocks_at: bundle_level.start_date,
+            is_owned: items.iter().all(|item| owned_items.contains(item)),
+            items,
+        };
+
+        if is_unlocked {
+            response.available.push(bundle_response);
+        } else {
+            response.locked.push(bundle_response);
+        }
+    }
+
+    Ok(response)
+}
+
+pub(crate) fn buy_bundle(
+    player_id: i32,
+    bundle_id: i32,
+    conn: &mut PgConnection,
+) -> Result<ShopPurchaseResponse> {
+    conn.transaction(|conn| {
+        let mut funds = lock_player_funds(conn, player_id)?;
+
+        let (bundle, bundle_level) = shop_bundle::table
+            .inner_join(levels_fixture::table)
+            .filter(shop_bundle::id.eq(bundle_id))
+            .first::<(ShopBundle, LevelsFixture)>(conn)
+            .optional()
+            .map_err(|err| DieselError {
+                table: "shop_bundle",
+                function: function!(),
+                error: err,
+            })?
+            .ok_or(ShopError::NotFound)?;
+
+        let current_level = get_current_levels_fixture(conn)?;
+        let placeable_blocks = get_placeable_blocks(conn, current_level.id)?;
+        let items = get_bundle_items(conn, &[bundle.id])?
+            .remove(&bundle.id)
+            .unwrap_or_default();
+
+        if !is_bundle_unlocked(&bundle_level, &current_level, &items, &placeable_blocks) {
+            return Err(ShopError::Locked.into());
+        }
+
+        let owned_items = get_owned_items(conn, player_id)?;
+        let items_added: Vec<ShopBundleItem> = items
+            .into_iter()
+            .filter(|item| !owned_items.contains(item))
+            .collect();
+        if items_added.is_empty() {
+            return Err(ShopError::AlreadyOwned.into());
+        }
+
+        spend_artifacts(conn, player_id, &mut funds, bundle.cost)?;
+
+        let items_count = items_added.len() as i32;
+        let new_available_blocks: Vec<NewAvailableBlocks> = items_added
+            .iter()
+            .enumerate()
+            .map(|(index, item)| NewAvailableBlocks {
+                block_type_id: item.block_type_id,
+                user_id: player_id,
+                attacker_type_id: item.attacker_type_id,
+                emp_type_id: item.emp_type_id,
+                category: item.category,
+                artifacts_spent: get_item_share(bundle.cost, items_count, index),
+            })
+            .collect();
+
+        diesel::insert_into(available_blocks::table)
+            .values(&new_available_blocks)
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "available_blocks",
+                function: function!(),
+                error: err,
+            })?;
+
+        Ok(ShopPurchaseResponse {
+            bundle_id: bundle.id,
+            items_added,
+            artifacts: funds.user_artifacts,
+            artifacts_in_bank: funds.bank_artifacts,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ItemKind;
+    use chrono::NaiveDate;
+
+    fn level(id: i32, month: u32) -> LevelsFixture {
+        let start_date = NaiveDate::from_ymd_opt(2024, month, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        LevelsFixture {
+            id,
+            start_date,
+            end_date: start_date + chrono::Duration::days(30),
+            no_of_bombs: 10,
+            rating_factor: 1.0,
+            no_of_attackers: 3,
+        }
+    }
+
+    fn block_item(block_type_id: i32) -> ShopBundleItem {
+        ShopBundleItem {
+            id: block_type_id,
+            bundle_id: 1,
+            category: ItemCategory::Block,
+            block_type_id: Some(block_type_id),
+            attacker_type_id: None,
+            emp_type_id: None,
+        }
+    }
+
+    fn attacker_item(attacker_type_id: i32) -> ShopBundleItem {
+        ShopBundleItem {
+            id: 100 + attacker_type_id,
+            bundle_id: 1,
+            category: ItemCategory::Attacker,
+            block_type_id: None,
+            attacker_type_id: Some(attacker_type_id),
+            emp_type_id: None,
+        }
+    }
+
+    #[test]
+    fn bundle_of_a_later_level_is_locked() {
+        let placeable_blocks = HashSet::from([1]);
+        let items = [block_item(1)];
+        assert!(!is_bundle_unlocked(
+            &level(2, 2),
+            &level(1, 1),
+            &items,
+            &placeable_blocks
+        ));
+        assert!(is_bundle_unlocked(
+            &level(1, 1),
+            &level(2, 2),
+            &items,
+            &placeable_blocks
+        ));
+    }
+
+    #[test]
+    fn bundle_with_an_unplaceable_block_is_locked() {
+        let placeable_blocks = HashSet::from([1]);
+        assert!(!is_bundle_unlocked(
+            &level(1, 1),
+            &level(1, 1),
+            &[block_item(1), block_item(2)],
+            &placeable_blocks
+        ));
+    }
+
+    #[test]
+    fn attackers_need_no_level_constraint() {
+        assert!(is_bundle_unlocked(
+            &level(1, 1),
+            &level(1, 1),
+            &[attacker_item(1)],
+            &HashSet::new()
+        ));
+    }
+
+    #[test]
+    fn bundle_cost_is_split_without_losing_artifacts() {
+        let shares: Vec<i32> = (0..3).map(|index| get_item_share(100, 3, index)).collect();
+        assert_eq!(shares, vec![34, 33, 33]);
+        assert_eq!(shares.iter().sum::<i32>(), 100);
+    }
+
+    #[test]
+    fn owning_any_level_of_a_chain_owns_the_bundle_item() {
+        let mut item_chains = ItemChains::default();
+        item_chains.blocks.insert(1, (ItemKind::Defender, 1));
+        item_chains.blocks.insert(2, (ItemKind::Defender, 1));
+        item_chains.blocks.insert(3, (ItemKind::Mine, 3));
+        let owned_items = OwnedItems {
+            item_ids: HashSet::from([(ItemCategory::Block, 2)]),
+            chains: HashSet::from([(ItemKind::Defender, 1)]),
+            item_chains,
+        };
+
+        //every item of a bundle with only these is owned, so buying it is refused
+        assert!(owned_items.contains(&block_item(1)));
+        assert!(owned_items.contains(&block_item(2)));
+        assert!(!owned_items.contains(&block_item(3)));
+        assert!(!owned_items.contains(&attacker_item(1)));
+    }
+}
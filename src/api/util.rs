@@ -1,13 +1,24 @@
+use crate::constants::IDEMPOTENCY_KEY_AGE_IN_MINUTES;
 use crate::error::DieselError;
-use crate::models::{Game, LevelsFixture};
+use crate::models::{Game, LevelsFixture, NewIdempotencyKey};
 use crate::util::function;
+use actix_web::{
+    error::ErrorBadRequest,
+    http::header::ContentType,
+    web::{self, Data},
+    HttpRequest, HttpResponse,
+};
 use anyhow::Result;
-use chrono::Local;
+use chrono::{Duration, Local};
 use diesel::prelude::*;
 use diesel::PgConnection;
 use serde::{Deserialize, Serialize};
 
+use super::attack::util::get_game_id_from_redis;
+use super::error::{handle_error, IdempotencyError, SpendError};
 use super::game::util::UserDetail;
+use super::inventory::util::{get_bank_map_space_id, get_block_id_of_bank, get_user_map_id};
+use super::{PgPool, RedisPool};
 
 #[derive(Deserialize, Serialize)]
 pub struct HistoryboardQuery {
@@ -67,3 +78,249 @@ pub fn get_current_levels_fixture(conn: &mut PgConnection) -> Result<LevelsFixtu
         })?;
     Ok(level)
 }
+
+pub struct PlayerFunds {
+    pub user_artifacts: i32,
+    pub bank_map_space_id: i32,
+    pub bank_artifacts: i32,
+}
+
+//locks the user row and then the bank artifact row, must be called inside a transaction
+pub fn lock_player_funds(conn: &mut PgConnection, player_id: i32) -> Result<PlayerFunds> {
+    use crate::schema::{artifact, user};
+
+    let user_artifacts = user::table
+        .find(player_id)
+        .select(user::artifacts)
+        .for_update()
+        .first::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "user",
+            function: function!(),
+            error: err,
+        })?;
+
+    let id_of_map = get_user_map_id(player_id, conn)?;
+    let bank_block_type_id = get_block_id_of_bank(conn, &player_id)?;
+    let bank_map_space_id = get_bank_map_space_id(conn, &id_of_map, &bank_block_type_id)?;
+
+    let bank_artifacts = artifact::table
+        .find(bank_map_space_id)
+        .select(artifact::count)
+        .for_update()
+        .first::<i32>(conn)
+        .map_err(|err| DieselError {
+            table: "artifact",
+            function: function!(),
+            error: err,
+        })?;
+
+    Ok(PlayerFunds {
+        user_artifacts,
+        bank_map_space_id,
+        bank_artifacts,
+    })
+}
+
+//takes artifacts out of the bank, funds must come from lock_player_funds in the same transaction
+pub fn spend_artifacts(
+    conn: &mut PgConnection,
+    player_id: i32,
+    funds: &mut PlayerFunds,
+    cost: i32,
+) -> Result<()> {
+    if cost > funds.user_artifacts {
+        return Err(SpendError::NotEnoughArtifacts.into());
+    }
+    if cost > funds.bank_artifacts {
+        return Err(SpendError::NotEnoughArtifactsInBank.into());
+    }
+
+    set_player_funds(
+        conn,
+        player_id,
+        funds,
+        funds.user_artifacts - cost,
+        funds.bank_artifacts - cost,
+    )
+}
+
+//puts artifacts back into the bank, funds must come from lock_player_funds in the same transaction
+pub fn refund_artifacts(
+    conn: &mut PgConnection,
+    player_id: i32,
+    funds: &mut PlayerFunds,
+    refund: i32,
+) -> Result<()> {
+    set_player_funds(
+        conn,
+        player_id,
+        funds,
+        funds.user_artifacts + refund,
+        funds.bank_artifacts + refund,
+    )
+}
+
+fn set_player_funds(
+    conn: &mut PgConnection,
+    player_id: i32,
+    funds: &mut PlayerFunds,
+    user_artifacts: i32,
+    bank_artifacts: i32,
+) -> Result<()> {
+    use crate::schema::{artifact, user};
+
+    diesel::update(artifact::table.find(funds.bank_map_space_id))
+        .set(artifact::count.eq(bank_artifacts))
+        .execute(conn)
+        .map_err(|err| DieselError {
+            table: "artifact",
+            function: function!(),
+            error: err,
+        })?;
+
+    diesel::update(user::table.find(player_id))
+        .set(user::artifacts.eq(user_artifacts))
+        .execute(conn)
+        .map_err(|err| DieselError {
+            table: "user",
+            function: function!(),
+            error: err,
+        })?;
+
+    funds.user_artifacts = user_artifacts;
+    funds.bank_artifacts = bank_artifacts;
+    Ok(())
+}
+
+pub fn get_idempotency_key(req: &HttpRequest) -> actix_web::Result<Option<String>> {
+    let key = match req.headers().get("Idempotency-Key") {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+
+    let key = key
+        .to_str()
+        .map_err(|_| ErrorBadRequest("Invalid Idempotency-Key"))?;
+    if key.is_empty() || key.len() > 255 {
+        return Err(ErrorBadRequest("Invalid Idempotency-Key"));
+    }
+
+    Ok(Some(key.to_string()))
+}
+
+//the key is stored in the same transaction as the spend, a retry either waits for the first
+//request to commit and gets its response or runs again after the first one rolled back
+fn run_once<T, F>(
+    user_id: i32,
+    scope: &str,
+    key: &str,
+    conn: &mut PgConnection,
+    handler: F,
+) -> Result<String>
+where
+    T: Serialize,
+    F: FnOnce(&mut PgConnection) -> Result<T>,
+{
+    use crate::schema::idempotency_key;
+
+    conn.transaction(|conn| {
+        let now = Local::now().naive_local();
+        let expired_before = now - Duration::minutes(IDEMPOTENCY_KEY_AGE_IN_MINUTES);
+        diesel::delete(
+            idempotency_key::table
+                .filter(idempotency_key::user_id.eq(user_id))
+                .filter(idempotency_key::created_at.lt(expired_before)),
+        )
+        .execute(conn)
+        .map_err(|err| DieselError {
+            table: "idempotency_key",
+            function: function!(),
+            error: err,
+        })?;
+
+        let reserved = diesel::insert_into(idempotency_key::table)
+            .values(NewIdempotencyKey {
+                user_id: &user_id,
+                key,
+                scope,
+                created_at: &now,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "idempotency_key",
+                function: function!(),
+                error: err,
+            })?;
+
+        if reserved == 0 {
+            let (stored_scope, response): (String, Option<String>) = idempotency_key::table
+                .find((user_id, key))
+                .select((idempotency_key::scope, idempotency_key::response))
+                .first(conn)
+                .map_err(|err| DieselError {
+                    table: "idempotency_key",
+                    function: function!(),
+                    error: err,
+                })?;
+            if stored_scope != scope {
+                return Err(IdempotencyError::KeyReused.into());
+            }
+            return response.ok_or_else(|| anyhow::anyhow!("Idempotency key has no response"));
+        }
+
+        let response = serde_json::to_string(&handler(conn)?)?;
+        diesel::update(idempotency_key::table.find((user_id, key)))
+            .set(idempotency_key::response.eq(&response))
+            .execute(conn)
+            .map_err(|err| DieselError {
+                table: "idempotency_key",
+                function: function!(),
+                error: err,
+            })?;
+        Ok(response)
+    })
+}
+
+//checks the ongoing game without holding on to the redis connection
+pub fn is_under_attack(user_id: i32, redis_pool: &RedisPool) -> actix_web::Result<bool> {
+    let mut redis_conn = redis_pool.get().map_err(|err| handle_error(err.into()))?;
+    Ok(matches!(
+        get_game_id_from_redis(user_id, &mut redis_conn, false),
+        Ok(Some(_))
+    ))
+}
+
+//runs a spending handler at most once per Idempotency-Key, retries get the stored response
+pub async fn run_idempotent<T, F>(
+    req: &HttpRequest,
+    pool: Data<PgPool>,
+    user_id: i32,
+    scope: &'static str,
+    handler: F,
+    handle_handler_error: fn(anyhow::Error) -> actix_web::Error,
+) -> actix_web::Result<HttpResponse>
+where
+    T: Serialize,
+    F: FnOnce(&mut PgConnection) -> Result<T> + Send + 'static,
+{
+    let key = get_idempotency_key(req)?;
+
+    let response = web::block(move || {
+        let mut conn = pool.get()?;
+        match key {
+            Some(key) => run_once(user_id, scope, &key, &mut conn, handler),
+            None => Ok(serde_json::to_string(&handler(&mut conn)?)?),
+        }
+    })
+    .await?
+    .map_err(|err| match err.downcast::<IdempotencyError>() {
+        Ok(idempotency_error) => idempotency_error.into(),
+        Err(err) => handle_handler_error(err),
+    })?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(response))
+}
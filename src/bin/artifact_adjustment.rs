@@ -19,6 +19,7 @@ fn main() -> Result<()> {
             .inner_join(artifact::table)
             .inner_join(map_layout::table)
             .filter(map_layout::player.eq(user_id))
+            .filter(map_layout::is_active.eq(true))
             .select(sum(artifact::count))
             .first(&mut conn)
             .map_err(|err| anyhow!("Error getting sum of artifacts for user: {}", err))?;
@@ -17,6 +17,7 @@ fn main() {
             map_layout::table.on(map_layout::player
                 .eq(user::id)
                 .and(map_layout::level_id.eq(level_id))
+                .and(map_layout::is_active.eq(true))
                 .and(map_layout::is_valid.eq(true))),
         )
         .select(user::id)
@@ -0,0 +1,122 @@
+use aot_backend::api::defense::shortest_path::NextHopMatrix;
+use aot_backend::constants::MAP_SIZE;
+use aot_backend::validator::util::{Coords, SourceDestXY};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+
+//the HashMap based next hop table that NextHopMatrix replaced
+fn hash_map_next_hops(roads: &[(i32, i32)]) -> HashMap<SourceDestXY, Coords> {
+    let road_set: HashSet<(i32, i32)> = roads.iter().copied().collect();
+    let mut shortest_paths: HashMap<SourceDestXY, Coords> = HashMap::new();
+
+    for &start_node in roads {
+        let mut visited: HashSet<(i32, i32)> = HashSet::new();
+        let mut queue: VecDeque<((i32, i32), (i32, i32))> = VecDeque::new();
+
+        visited.insert(start_node);
+        queue.push_back((start_node, start_node));
+
+        while let Some((current_node, parent_node)) = queue.pop_front() {
+            for (dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                let neighbor = (current_node.0 + dx, current_node.1 + dy);
+                if road_set.contains(&neighbor) && visited.insert(neighbor) {
+                    let next_hop = if start_node == parent_node {
+                        neighbor
+                    } else {
+                        parent_node
+                    };
+                    queue.push_back((neighbor, next_hop));
+                    shortest_paths.insert(
+                        SourceDestXY {
+                            source_x: start_node.0,
+                            source_y: start_node.1,
+                            dest_x: neighbor.0,
+                            dest_y: neighbor.1,
+                        },
+                        Coords {
+                            x: next_hop.0,
+                            y: next_hop.1,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    shortest_paths
+}
+
+//roads on every other row joined by a column at alternating ends, like a maze
+fn serpentine_roads() -> Vec<(i32, i32)> {
+    let size = MAP_SIZE as i32;
+    let mut roads = Vec::new();
+    for x in (0..size).step_by(2) {
+        for y in 0..size {
+            roads.push((x, y));
+        }
+        if x + 1 < size {
+            let y = if (x / 2) % 2 == 0 { size - 1 } else { 0 };
+            roads.push((x + 1, y));
+        }
+    }
+    roads
+}
+
+//roads on every fourth row and column
+fn grid_roads() -> Vec<(i32, i32)> {
+    let size = MAP_SIZE as i32;
+    (0..size)
+        .flat_map(|x| (0..size).map(move |y| (x, y)))
+        .filter(|(x, y)| x % 4 == 0 || y % 4 == 0)
+        .collect()
+}
+
+fn time<T>(build: impl Fn() -> T) -> (T, Duration) {
+    let mut result = build();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        result = build();
+    }
+    (result, start.elapsed() / RUNS)
+}
+
+fn main() {
+    for (name, roads) in [("serpentine", serpentine_roads()), ("grid", grid_roads())] {
+        let (hash_map, hash_map_time) = time(|| hash_map_next_hops(&roads));
+        let (matrix, matrix_time) = time(|| NextHopMatrix::new(&roads));
+
+        //hashbrown stores one control byte next to every bucket
+        let hash_map_memory =
+            hash_map.capacity() * (size_of::<SourceDestXY>() + size_of::<Coords>() + 1);
+
+        let mismatches = hash_map
+            .iter()
+            .filter(|(source_dest, next_hop)| {
+                let source = Coords {
+                    x: source_dest.source_x,
+                    y: source_dest.source_y,
+                };
+                let dest = Coords {
+                    x: source_dest.dest_x,
+                    y: source_dest.dest_y,
+                };
+                matrix.next_hop(source, dest) != Some(**next_hop)
+            })
+            .count();
+
+        println!("{name}: {} roads, {} paths", roads.len(), hash_map.len());
+        println!(
+            "  hash map: {:>10} bytes {:>10.2?}",
+            hash_map_memory, hash_map_time
+        );
+        println!(
+            "  matrix:   {:>10} bytes {:>10.2?}",
+            matrix.memory_usage(),
+            matrix_time
+        );
+        println!("  mismatched next hops: {mismatches}");
+    }
+}
@@ -14,4 +14,23 @@ pub const MATCH_MAKING_ATTEMPTS: i32 = 10;
 pub const PERCENTANGE_ARTIFACTS_OBTAINABLE: f32 = 0.3;
 pub const BOMB_DAMAGE_MULTIPLIER: f32 = 5.0;
 
-pub const LIVES: i32 = 3;
+pub const LIVES: usize = 3;
+
+pub const MAX_BUILDER_SLOTS: i64 = 2;
+pub const UPGRADE_TIME_PER_LEVEL_IN_MINUTES: i64 = 10;
+pub const FINISH_UPGRADE_COST_PER_MINUTE: i32 = 5;
+pub const UPGRADE_CANCEL_REFUND_PERCENTAGE: f32 = 0.8;
+pub const ITEM_REFUND_PERCENTAGE: f32 = 0.5;
+pub const UPGRADE_JOB_SWEEP_INTERVAL_IN_SECONDS: u64 = 30;
+pub const IDEMPOTENCY_KEY_AGE_IN_MINUTES: i64 = 24 * 60;
+pub const DEFAULT_LAYOUT_NAME: &str = "Main";
+pub const MAX_LAYOUT_SLOTS: i64 = 3;
+pub const MAX_LAYOUT_VERSIONS: i32 = 20;
+pub const ROAD_TILE_COST: u32 = 1;
+pub const OPEN_TILE_COST: u32 = 3;
+pub const SLOW_EFFECT_SPEED_PERCENTAGE: i32 = 50;
+pub const FOG_OF_WAR_BORDER: i32 = 3;
+pub const FOG_OF_WAR_REVEAL_RADIUS: i32 = 3;
+pub const FOG_OF_WAR_SCOUT_RADIUS: i32 = 5;
+pub const SPAWN_ZONE_DEPTH: i32 = 3;
+pub const MAX_SQUAD_SIZE: usize = 3;
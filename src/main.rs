@@ -1,4 +1,4 @@
-use crate::api::{attack, auth, defense, game, inventory, user};
+use crate::api::{attack, auth, defense, game, inventory, shop, user};
 use actix_cors::Cors;
 use actix_session::{
     config::PersistentSession, storage::RedisActorSessionStore, SessionMiddleware,
@@ -46,6 +46,32 @@ async fn main() -> std::io::Result<()> {
 
     let conn = &mut pg_pool.get().expect("Could not get connection from pool");
     conn.run_pending_migrations(MIGRATIONS).unwrap();
+
+    //upgrades finish on their own, read requests only report them
+    let sweep_pg_pool = pg_pool.clone();
+    let sweep_redis_pool = redis_pool.clone();
+    actix_rt::spawn(async move {
+        let mut interval = actix_rt::time::interval(std::time::Duration::from_secs(
+            constants::UPGRADE_JOB_SWEEP_INTERVAL_IN_SECONDS,
+        ));
+        loop {
+            interval.tick().await;
+            let pg_pool = sweep_pg_pool.clone();
+            let redis_pool = sweep_redis_pool.clone();
+            let swept = web::block(move || {
+                let mut conn = pg_pool.get()?;
+                let mut redis_conn = redis_pool.get()?;
+                inventory::util::complete_all_finished_upgrade_jobs(&mut conn, &mut redis_conn)
+            })
+            .await;
+            match swept {
+                Ok(Err(err)) => log::error!("Could not complete upgrade jobs: {}", err),
+                Err(err) => log::error!("Could not complete upgrade jobs: {}", err),
+                Ok(Ok(())) => {}
+            }
+        }
+    });
+
     let max_age: i64 = std::env::var("MAX_AGE_IN_MINUTES")
         .expect("max age must be set!")
         .parse()
@@ -84,6 +110,7 @@ async fn main() -> std::io::Result<()> {
             .service(web::scope("/base").configure(defense::routes))
             .service(web::scope("/game").configure(game::routes))
             .service(web::scope("/inventory").configure(inventory::routes))
+            .service(web::scope("/shop").configure(shop::routes))
     })
     .bind("0.0.0.0:8000")?
     .run()
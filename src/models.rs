@@ -8,9 +8,12 @@ pub enum BlockCategory {
     Building,
     Defender,
     Mine,
+    Wall,
 }
 
-#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Clone, PartialEq, Copy, Deserialize)]
+#[derive(
+    diesel_derive_enum::DbEnum, Debug, Serialize, Clone, PartialEq, Eq, Copy, Deserialize, Hash,
+)]
 #[DieselTypePath = "crate::schema::sql_types::ItemCategory"]
 pub enum ItemCategory {
     Attacker,
@@ -18,23 +21,84 @@ pub enum ItemCategory {
     Block,
 }
 
+#[derive(
+    diesel_derive_enum::DbEnum, Debug, Serialize, Clone, PartialEq, Eq, Copy, Deserialize, Hash,
+)]
+#[DieselTypePath = "crate::schema::sql_types::ItemKind"]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Attacker,
+    Building,
+    Defender,
+    Emp,
+    Mine,
+}
+
+#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Clone, PartialEq, Eq, Copy, Deserialize)]
+#[DieselTypePath = "crate::schema::sql_types::MineEffect"]
+#[serde(rename_all = "lowercase")]
+pub enum MineEffect {
+    None,
+    Slow,
+    Root,
+    Disarm,
+}
+
+//whether a bomb damages buildings, disables defenders and mines, or both
+#[derive(diesel_derive_enum::DbEnum, Debug, Serialize, Clone, PartialEq, Eq, Copy, Deserialize)]
+#[DieselTypePath = "crate::schema::sql_types::EmpEffect"]
+#[serde(rename_all = "lowercase")]
+pub enum EmpEffect {
+    Damage,
+    Disable,
+    Both,
+}
+
+impl EmpEffect {
+    pub fn deals_damage(&self) -> bool {
+        matches!(self, EmpEffect::Damage | EmpEffect::Both)
+    }
+
+    pub fn disables(&self) -> bool {
+        matches!(self, EmpEffect::Disable | EmpEffect::Both)
+    }
+}
+
+//the one ability an attacker type can activate during an attack
+#[derive(
+    diesel_derive_enum::DbEnum, Debug, Serialize, Clone, PartialEq, Eq, Copy, Deserialize, Default,
+)]
+#[DieselTypePath = "crate::schema::sql_types::AttackerAbility"]
+#[serde(rename_all = "lowercase")]
+pub enum AttackerAbility {
+    #[default]
+    None,
+    Heal,
+    Shield,
+    Sprint,
+}
+
 #[derive(Queryable, Serialize, Clone, Debug)]
 pub struct EmpType {
     pub id: i32,
-    pub att_type: String,
+    pub att_type: EmpEffect,
     pub attack_radius: i32,
     pub attack_damage: i32,
     pub cost: i32,
     pub name: String,
     pub level: i32,
+    pub max_level: i32,
+    pub next_level_id: Option<i32>,
+    pub disable_duration: i32,
 }
 
-#[derive(Queryable, Serialize)]
+#[derive(Queryable, Serialize, Clone, Debug)]
 pub struct AttackType {
     pub id: i32,
     pub att_type: String,
     pub attack_radius: i32,
     pub attack_damage: i32,
+    pub projectile_speed: i32,
 }
 
 #[derive(Insertable)]
@@ -74,6 +138,8 @@ pub struct BuildingType {
     pub level: i32,
     pub cost: i32,
     pub hp: i32,
+    pub max_level: i32,
+    pub next_level_id: Option<i32>,
 }
 
 #[derive(Insertable)]
@@ -108,6 +174,7 @@ pub struct AvailableBlocks {
     pub emp_type_id: Option<i32>,
     pub category: ItemCategory,
     pub id: i32,
+    pub artifacts_spent: i32,
 }
 
 #[derive(Deserialize, Insertable)]
@@ -118,6 +185,7 @@ pub struct NewAvailableBlocks {
     pub attacker_type_id: Option<i32>,
     pub emp_type_id: Option<i32>,
     pub category: ItemCategory,
+    pub artifacts_spent: i32,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug)]
@@ -191,6 +259,10 @@ pub struct MapLayout {
     pub player: i32,
     pub level_id: i32,
     pub is_valid: bool,
+    pub name: String,
+    pub is_active: bool,
+    pub version: i32,
+    pub is_latest: bool,
 }
 
 #[derive(Insertable)]
@@ -199,6 +271,10 @@ pub struct NewMapLayout<'a> {
     pub player: &'a i32,
     pub level_id: &'a i32,
     pub is_valid: &'a bool,
+    pub name: &'a str,
+    pub is_active: &'a bool,
+    pub version: &'a i32,
+    pub is_latest: &'a bool,
 }
 
 #[derive(Queryable, Debug, Serialize, Deserialize, Clone)]
@@ -219,27 +295,20 @@ pub struct NewMapSpaces {
     pub block_type_id: i32,
 }
 
-#[derive(Queryable, Debug)]
-pub struct ShortestPath {
-    pub base_id: i32,
-    pub source_x: i32,
-    pub source_y: i32,
-    pub dest_x: i32,
-    pub dest_y: i32,
-    pub next_hop_x: i32,
-    pub next_hop_y: i32,
+#[derive(Insertable)]
+#[diesel(table_name = layout_next_hops)]
+pub struct NewLayoutNextHops<'a> {
+    pub map_id: &'a i32,
+    pub next_hops: &'a [u8],
 }
 
-#[derive(Insertable, PartialEq)]
-#[diesel(table_name = shortest_path)]
-pub struct NewShortestPath {
-    pub base_id: i32,
-    pub source_x: i32,
-    pub source_y: i32,
-    pub dest_x: i32,
-    pub dest_y: i32,
-    pub next_hop_x: i32,
-    pub next_hop_y: i32,
+#[derive(Insertable)]
+#[diesel(table_name = idempotency_key)]
+pub struct NewIdempotencyKey<'a> {
+    pub user_id: &'a i32,
+    pub key: &'a str,
+    pub scope: &'a str,
+    pub created_at: &'a NaiveDateTime,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Queryable)]
@@ -298,6 +367,10 @@ pub struct MineType {
     pub level: i32,
     pub cost: i32,
     pub name: String,
+    pub max_level: i32,
+    pub next_level_id: Option<i32>,
+    pub effect: MineEffect,
+    pub effect_duration: i32,
 }
 
 #[derive(Queryable, Clone, Debug, Serialize)]
@@ -309,6 +382,9 @@ pub struct DefenderType {
     pub level: i32,
     pub cost: i32,
     pub name: String,
+    pub max_level: i32,
+    pub next_level_id: Option<i32>,
+    pub attack_type_id: Option<i32>,
 }
 
 #[derive(Queryable, Clone, Debug, Serialize)]
@@ -338,4 +414,53 @@ pub struct AttackerType {
     pub level: i32,
     pub cost: i32,
     pub name: String,
+    pub max_level: i32,
+    pub next_level_id: Option<i32>,
+    pub ability: AttackerAbility,
+    pub ability_power: i32,
+    pub ability_duration: i32,
+    pub ability_cooldown: i32,
+    pub ability_charges: i32,
+}
+
+#[derive(Queryable, Clone, Debug, Serialize)]
+pub struct UpgradeJob {
+    pub id: i32,
+    pub user_id: i32,
+    pub item_type: ItemKind,
+    pub from_id: i32,
+    pub to_id: i32,
+    pub cost: i32,
+    pub started_at: NaiveDateTime,
+    pub finishes_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = upgrade_job)]
+pub struct NewUpgradeJob<'a> {
+    pub user_id: &'a i32,
+    pub item_type: &'a ItemKind,
+    pub from_id: &'a i32,
+    pub to_id: &'a i32,
+    pub cost: &'a i32,
+    pub started_at: &'a NaiveDateTime,
+    pub finishes_at: &'a NaiveDateTime,
+}
+
+#[derive(Queryable, Clone, Debug, Serialize)]
+pub struct ShopBundle {
+    pub id: i32,
+    pub name: String,
+    pub cost: i32,
+    pub level_id: i32,
+}
+
+#[derive(Queryable, Clone, Debug, Serialize)]
+pub struct ShopBundleItem {
+    pub id: i32,
+    pub bundle_id: i32,
+    pub category: ItemCategory,
+    pub block_type_id: Option<i32>,
+    pub attacker_type_id: Option<i32>,
+    pub emp_type_id: Option<i32>,
 }
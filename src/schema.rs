@@ -1,13 +1,29 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "attacker_ability"))]
+    pub struct AttackerAbility;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "block_category"))]
     pub struct BlockCategory;
 
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "emp_effect"))]
+    pub struct EmpEffect;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "item_category"))]
     pub struct ItemCategory;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "item_kind"))]
+    pub struct ItemKind;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "mine_effect"))]
+    pub struct MineEffect;
 }
 
 diesel::table! {
@@ -23,10 +39,14 @@ diesel::table! {
         att_type -> Varchar,
         attack_radius -> Int4,
         attack_damage -> Int4,
+        projectile_speed -> Int4,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::AttackerAbility;
+
     attacker_type (id) {
         id -> Int4,
         max_health -> Int4,
@@ -35,6 +55,13 @@ diesel::table! {
         level -> Int4,
         cost -> Int4,
         name -> Varchar,
+        max_level -> Int4,
+        next_level_id -> Nullable<Int4>,
+        ability -> AttackerAbility,
+        ability_power -> Int4,
+        ability_duration -> Int4,
+        ability_cooldown -> Int4,
+        ability_charges -> Int4,
     }
 }
 
@@ -49,6 +76,7 @@ diesel::table! {
         emp_type_id -> Nullable<Int4>,
         category -> ItemCategory,
         id -> Int4,
+        artifacts_spent -> Int4,
     }
 }
 
@@ -75,6 +103,8 @@ diesel::table! {
         level -> Int4,
         cost -> Int4,
         hp -> Int4,
+        max_level -> Int4,
+        next_level_id -> Nullable<Int4>,
     }
 }
 
@@ -87,18 +117,27 @@ diesel::table! {
         level -> Int4,
         cost -> Int4,
         name -> Varchar,
+        max_level -> Int4,
+        next_level_id -> Nullable<Int4>,
+        attack_type_id -> Nullable<Int4>,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::EmpEffect;
+
     emp_type (id) {
         id -> Int4,
-        att_type -> Varchar,
+        att_type -> EmpEffect,
         attack_radius -> Int4,
         attack_damage -> Int4,
         cost -> Int4,
         name -> Varchar,
         level -> Int4,
+        max_level -> Int4,
+        next_level_id -> Nullable<Int4>,
+        disable_duration -> Int4,
     }
 }
 
@@ -126,6 +165,23 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    idempotency_key (user_id, key) {
+        user_id -> Int4,
+        key -> Varchar,
+        scope -> Varchar,
+        response -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    layout_next_hops (map_id) {
+        map_id -> Int4,
+        next_hops -> Bytea,
+    }
+}
+
 diesel::table! {
     levels_fixture (id) {
         id -> Int4,
@@ -143,6 +199,10 @@ diesel::table! {
         player -> Int4,
         level_id -> Int4,
         is_valid -> Bool,
+        name -> Varchar,
+        is_active -> Bool,
+        version -> Int4,
+        is_latest -> Bool,
     }
 }
 
@@ -157,6 +217,9 @@ diesel::table! {
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::MineEffect;
+
     mine_type (id) {
         id -> Int4,
         radius -> Int4,
@@ -164,18 +227,33 @@ diesel::table! {
         level -> Int4,
         cost -> Int4,
         name -> Varchar,
+        max_level -> Int4,
+        next_level_id -> Nullable<Int4>,
+        effect -> MineEffect,
+        effect_duration -> Int4,
     }
 }
 
 diesel::table! {
-    shortest_path (base_id, source_x, source_y, dest_x, dest_y) {
-        base_id -> Int4,
-        source_x -> Int4,
-        source_y -> Int4,
-        dest_x -> Int4,
-        dest_y -> Int4,
-        next_hop_x -> Int4,
-        next_hop_y -> Int4,
+    shop_bundle (id) {
+        id -> Int4,
+        name -> Varchar,
+        cost -> Int4,
+        level_id -> Int4,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ItemCategory;
+
+    shop_bundle_item (id) {
+        id -> Int4,
+        bundle_id -> Int4,
+        category -> ItemCategory,
+        block_type_id -> Nullable<Int4>,
+        attacker_type_id -> Nullable<Int4>,
+        emp_type_id -> Nullable<Int4>,
     }
 }
 
@@ -186,6 +264,22 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ItemKind;
+
+    upgrade_job (id) {
+        id -> Int4,
+        user_id -> Int4,
+        item_type -> ItemKind,
+        from_id -> Int4,
+        to_id -> Int4,
+        cost -> Int4,
+        started_at -> Timestamp,
+        finishes_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     user (id) {
         id -> Int4,
@@ -209,15 +303,23 @@ diesel::joinable!(available_blocks -> user (user_id));
 diesel::joinable!(block_type -> building_type (building_type));
 diesel::joinable!(block_type -> defender_type (defender_type));
 diesel::joinable!(block_type -> mine_type (mine_type));
+diesel::joinable!(defender_type -> attack_type (attack_type_id));
 diesel::joinable!(game -> map_layout (map_layout_id));
+diesel::joinable!(idempotency_key -> user (user_id));
+diesel::joinable!(layout_next_hops -> map_layout (map_id));
 diesel::joinable!(level_constraints -> block_type (block_id));
 diesel::joinable!(level_constraints -> levels_fixture (level_id));
 diesel::joinable!(map_layout -> levels_fixture (level_id));
 diesel::joinable!(map_layout -> user (player));
 diesel::joinable!(map_spaces -> block_type (block_type_id));
 diesel::joinable!(map_spaces -> map_layout (map_id));
-diesel::joinable!(shortest_path -> map_layout (base_id));
+diesel::joinable!(shop_bundle -> levels_fixture (level_id));
+diesel::joinable!(shop_bundle_item -> attacker_type (attacker_type_id));
+diesel::joinable!(shop_bundle_item -> block_type (block_type_id));
+diesel::joinable!(shop_bundle_item -> emp_type (emp_type_id));
+diesel::joinable!(shop_bundle_item -> shop_bundle (bundle_id));
 diesel::joinable!(simulation_log -> game (game_id));
+diesel::joinable!(upgrade_job -> user (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     artifact,
@@ -229,12 +331,16 @@ diesel::allow_tables_to_appear_in_same_query!(
     defender_type,
     emp_type,
     game,
+    idempotency_key,
+    layout_next_hops,
     level_constraints,
     levels_fixture,
     map_layout,
     map_spaces,
     mine_type,
-    shortest_path,
+    shop_bundle,
+    shop_bundle_item,
     simulation_log,
+    upgrade_job,
     user,
 );
@@ -0,0 +1,80 @@
+use super::util::{tile_index, Coords};
+use crate::constants::{FOG_OF_WAR_BORDER, MAP_SIZE};
+use crate::models::MapSpaces;
+use serde::{Deserialize, Serialize};
+
+//a map space along with the tiles it covers
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BlockFootprint {
+    pub map_space: MapSpaces,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl BlockFootprint {
+    fn tiles(&self) -> impl Iterator<Item = Coords> + '_ {
+        let x = self.map_space.x_coordinate;
+        let y = self.map_space.y_coordinate;
+        (x..x + self.width).flat_map(move |x| (y..y + self.height).map(move |y| Coords { x, y }))
+    }
+}
+
+//tiles the attacker has seen and the blocks still hidden from them
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FogOfWar {
+    revealed: Vec<bool>,
+    hidden: Vec<BlockFootprint>,
+}
+
+impl FogOfWar {
+    //only the outer ring of the map starts out revealed, returns the blocks visible in it
+    pub fn new(blocks: Vec<BlockFootprint>) -> (Self, Vec<MapSpaces>) {
+        let size = MAP_SIZE as i32;
+        let revealed = (0..size)
+            .flat_map(|x| (0..size).map(move |y| (x, y)))
+            .map(|(x, y)| {
+                x < FOG_OF_WAR_BORDER
+                    || y < FOG_OF_WAR_BORDER
+                    || x >= size - FOG_OF_WAR_BORDER
+                    || y >= size - FOG_OF_WAR_BORDER
+            })
+            .collect();
+
+        let mut fog = FogOfWar {
+            revealed,
+            hidden: blocks,
+        };
+        let visible = fog.take_revealed_blocks();
+        (fog, visible)
+    }
+
+    pub fn is_revealed(&self, coords: Coords) -> bool {
+        tile_index(coords.x, coords.y).is_some_and(|tile| self.revealed[tile])
+    }
+
+    //reveals the square of the given radius around every center, returns the blocks it uncovered
+    pub fn reveal(&mut self, centers: &[Coords], radius: i32) -> Vec<MapSpaces> {
+        for center in centers {
+            for x in center.x - radius..=center.x + radius {
+                for y in center.y - radius..=center.y + radius {
+                    if let Some(tile) = tile_index(x, y) {
+                        self.revealed[tile] = true;
+                    }
+                }
+            }
+        }
+
+        self.take_revealed_blocks()
+    }
+
+    //a block is seen as soon as any of its tiles is
+    fn take_revealed_blocks(&mut self) -> Vec<MapSpaces> {
+        let blocks = std::mem::take(&mut self.hidden);
+        let (revealed, hidden): (Vec<BlockFootprint>, Vec<BlockFootprint>) = blocks
+            .into_iter()
+            .partition(|block| block.tiles().any(|tile| self.is_revealed(tile)));
+        self.hidden = hidden;
+
+        revealed.into_iter().map(|block| block.map_space).collect()
+    }
+}
@@ -2,28 +2,177 @@ use std::collections::{HashMap, HashSet};
 
 use crate::{
     api::attack::{
-        socket::{ActionType, BuildingResponse, ResultType, SocketRequest, SocketResponse},
+        socket::{
+            ActionType, BuildingResponse, ResultType, SocketRequest, SocketResponse, UnitPath,
+        },
         util::{Direction, EventResponse, GameLog},
     },
-    models::AttackerType,
-    validator::util::{Coords, SourceDestXY},
+    constants::MAP_SIZE,
+    models::{AttackerType, MapSpaces},
+    validator::util::Coords,
 };
 use anyhow::{Ok, Result};
 
 use self::{
+    pathfinding::DefenderPathfinder,
     state::State,
-    util::{send_terminate_game_message, Attacker, BombType, DefenderReturnType, MineDetails},
+    util::{
+        send_terminate_game_message, AbilityState, Attacker, BombType, DefenderReturnType,
+        MineDetails,
+    },
 };
 
 pub mod error;
+pub mod fog;
+pub mod pathfinding;
+pub mod placement;
 pub mod state;
 pub mod util;
 
+//checked up front so a malformed request ends the game instead of crashing the game task
+fn validate_request(
+    socket_request: &SocketRequest,
+    game_state: &State,
+    attacker_type: &HashMap<i32, AttackerType>,
+) -> Option<String> {
+    let on_map = |coords: &Coords| {
+        (0..MAP_SIZE as i32).contains(&coords.x) && (0..MAP_SIZE as i32).contains(&coords.y)
+    };
+    if !socket_request
+        .start_position
+        .iter()
+        .chain(socket_request.attacker_path.iter())
+        .chain(
+            socket_request
+                .unit_paths
+                .iter()
+                .flat_map(|unit_path| unit_path.path.iter()),
+        )
+        .all(on_map)
+    {
+        return Some("Position out of map".to_string());
+    }
+
+    if let Some(attacker_id) = socket_request.attacker_id {
+        if !attacker_type.contains_key(&attacker_id) {
+            return Some("Invalid attacker type".to_string());
+        }
+    }
+
+    let needs_attacker = matches!(
+        socket_request.action_type,
+        ActionType::MoveAttacker
+            | ActionType::IsMine
+            | ActionType::PlaceBombs
+            | ActionType::SelfDestruct
+            | ActionType::UseAbility
+    );
+    if needs_attacker && game_state.attackers.is_empty() {
+        return Some("Attacker not placed".to_string());
+    }
+
+    let unknown_unit = socket_request
+        .unit_id
+        .iter()
+        .chain(
+            socket_request
+                .unit_paths
+                .iter()
+                .map(|unit_path| &unit_path.unit_id),
+        )
+        .any(|&unit_id| game_state.unit_index(Some(unit_id)).is_none());
+    if socket_request.action_type != ActionType::PlaceAttacker && unknown_unit {
+        return Some("Unknown unit".to_string());
+    }
+
+    let needs_position = matches!(
+        socket_request.action_type,
+        ActionType::PlaceAttacker
+            | ActionType::MoveAttacker
+            | ActionType::IsMine
+            | ActionType::PlaceBombs
+    );
+    if needs_position && socket_request.start_position.is_none() {
+        return Some("Missing start position".to_string());
+    }
+
+    if socket_request.action_type == ActionType::MoveAttacker
+        && ((socket_request.attacker_path.is_empty() && socket_request.unit_paths.is_empty())
+            || socket_request
+                .unit_paths
+                .iter()
+                .any(|unit_path| unit_path.path.is_empty()))
+    {
+        return Some("Empty attacker path".to_string());
+    }
+
+    if socket_request.action_type == ActionType::PlaceBombs
+        && !on_map(&socket_request.bomb_position)
+    {
+        return Some("Bomb placed out of map".to_string());
+    }
+
+    None
+}
+
+//the paths of a squad move, or attacker_path for the acting unit alone
+fn requested_unit_paths(socket_request: &SocketRequest, game_state: &State) -> Vec<UnitPath> {
+    if !socket_request.unit_paths.is_empty() {
+        return socket_request.unit_paths.clone();
+    }
+    game_state
+        .unit_index(socket_request.unit_id)
+        .map(|unit_index| UnitPath {
+            unit_id: game_state.attackers[unit_index].unit_id,
+            path: socket_request.attacker_path.clone(),
+        })
+        .into_iter()
+        .collect()
+}
+
+//directions are taken from the unit's own last logged tile, as units move in between each other
+fn log_unit_path(
+    game_log: &mut GameLog,
+    unit_id: i32,
+    path: &[Coords],
+    bomb_position: Option<Coords>,
+) {
+    for &coord in path {
+        let mut direction = Direction::Up;
+
+        let prev_pos = game_log
+            .e
+            .iter()
+            .rev()
+            .find(|event| event.unit_id == Some(unit_id))
+            .map_or(coord, |event| event.coords);
+        if prev_pos.x < coord.x {
+            direction = Direction::Down;
+        } else if prev_pos.x > coord.x {
+            direction = Direction::Up;
+        } else if prev_pos.y < coord.y {
+            direction = Direction::Left;
+        } else if prev_pos.y > coord.y {
+            direction = Direction::Right;
+        }
+
+        game_log.e.push(EventResponse {
+            attacker_id: None,
+            unit_id: Some(unit_id),
+            bomb_id: None,
+            coords: coord,
+            direction,
+            is_bomb: Some(coord) == bomb_position,
+            is_ability: false,
+        });
+    }
+}
+
 pub fn game_handler(
     attacker_type: &HashMap<i32, AttackerType>,
     socket_request: SocketRequest,
     _game_state: &mut State,
-    _shortest_path: &HashMap<SourceDestXY, Coords>,
+    _shortest_path: &mut DefenderPathfinder,
     _roads: &HashSet<(i32, i32)>,
     _bomb_types: &Vec<BombType>,
     mut _game_log: &mut GameLog,
@@ -32,40 +181,64 @@ pub fn game_handler(
     let exploded_mines_result: Vec<MineDetails>;
     let buildings_damaged_result: Vec<BuildingResponse>;
 
+    if let Some(message) = validate_request(&socket_request, _game_state, attacker_type) {
+        return Some(Ok(send_terminate_game_message(
+            socket_request.frame_number,
+            message,
+        )));
+    }
+
     match socket_request.action_type {
         ActionType::PlaceAttacker => {
             _game_state.update_frame_number(socket_request.frame_number);
 
             let mut event_response = EventResponse {
                 attacker_id: None,
+                unit_id: None,
                 bomb_id: None,
                 coords: Coords { x: 0, y: 0 },
                 direction: Direction::Up,
                 is_bomb: false,
+                is_ability: false,
             };
 
             if let Some(attacker_id) = socket_request.attacker_id {
                 let attacker: AttackerType = attacker_type.get(&attacker_id).unwrap().clone();
-                _game_state.place_attacker(Attacker {
+                //every unit brings its own bombs of the type picked for it
+                let bomb_type = socket_request
+                    .bomb_id
+                    .and_then(|bomb_id| {
+                        _bomb_types.iter().find(|bomb_type| bomb_type.id == bomb_id)
+                    })
+                    .cloned();
+                let unit_id = _game_state.place_attacker(Attacker {
                     id: attacker.id,
+                    unit_id: 0,
                     path_in_current_frame: Vec::new(),
                     attacker_pos: socket_request.start_position.unwrap(),
                     attacker_health: attacker.max_health,
                     attacker_speed: attacker.speed,
                     bombs: Vec::new(),
                     trigger_defender: false,
-                    bomb_count: attacker.amt_of_emps,
+                    bomb_count: if bomb_type.is_some() {
+                        attacker.amt_of_emps
+                    } else {
+                        0
+                    },
+                    status_effects: Vec::new(),
+                    max_health: attacker.max_health,
+                    ability: AbilityState::new(&attacker),
+                    bomb_type,
                 });
-
-                for bomb_type in _bomb_types {
-                    if let Some(bomb_id) = socket_request.bomb_id {
-                        if bomb_type.id == bomb_id {
-                            _game_state.set_bombs(bomb_type.clone(), attacker.amt_of_emps);
-                        }
-                    }
+                if _game_state.in_validation.is_invalidated {
+                    return Some(Ok(send_terminate_game_message(
+                        socket_request.frame_number,
+                        _game_state.in_validation.message.clone(),
+                    )));
                 }
 
                 event_response.attacker_id = Some(attacker_id);
+                event_response.unit_id = unit_id;
                 event_response.coords = socket_request.start_position.unwrap();
             }
 
@@ -75,6 +248,10 @@ pub fn game_handler(
             _game_log.e.push(event_response);
             _game_log.r.au += 1;
 
+            let revealed_blocks = socket_request
+                .start_position
+                .and_then(|position| _game_state.reveal_path(&[position]));
+
             if _game_state.in_validation.is_invalidated {
                 return Some(Ok(send_terminate_game_message(
                     socket_request.frame_number,
@@ -92,6 +269,10 @@ pub fn game_handler(
                 // triggered_defenders: None,
                 defender_damaged: None,
                 damaged_buildings: None,
+                revealed_blocks,
+                squad: Some(_game_state.squad_response()),
+                stunned_defenders: None,
+                disarmed_mines: None,
                 total_damage_percentage: Some(_game_state.damage_percentage),
                 is_sync: false,
                 is_game_over: false,
@@ -101,100 +282,64 @@ pub fn game_handler(
             }));
         }
         ActionType::MoveAttacker => {
-            if let Some(attacker_id) = socket_request.attacker_id {
-                let attacker: AttackerType = attacker_type.get(&attacker_id).unwrap().clone();
-                let attacker_delta: Vec<Coords> = socket_request.attacker_path;
-
-                let attacker_result = _game_state.attacker_movement(
-                    socket_request.frame_number,
-                    _roads,
-                    Attacker {
-                        id: attacker.id,
-                        path_in_current_frame: attacker_delta.clone(),
-                        attacker_pos: socket_request.start_position.unwrap(),
-                        attacker_health: attacker.max_health,
-                        attacker_speed: attacker.speed,
-                        bombs: Vec::new(),
-                        trigger_defender: false,
-                        bomb_count: attacker.amt_of_emps,
-                    },
-                );
-
-                let attacker_result_clone = attacker_result.clone();
-
-                defender_damaged_result =
-                    _game_state.defender_movement(attacker_delta.clone(), _shortest_path);
+            let unit_paths = requested_unit_paths(&socket_request, _game_state);
 
-                for coord in attacker_delta {
-                    let mut direction = Direction::Up;
+            let moved_units =
+                _game_state.attacker_movement(socket_request.frame_number, _roads, &unit_paths);
 
-                    let prev_pos = _game_log.e.last().unwrap().coords;
-                    if prev_pos.x < coord.x {
-                        direction = Direction::Down;
-                    } else if prev_pos.x > coord.x {
-                        direction = Direction::Up;
-                    } else if prev_pos.y < coord.y {
-                        direction = Direction::Left;
-                    } else if prev_pos.y > coord.y {
-                        direction = Direction::Right;
-                    }
-
-                    let event_response = EventResponse {
-                        attacker_id: None,
-                        bomb_id: None,
-                        coords: coord,
-                        direction,
-                        is_bomb: false,
-                    };
-
-                    _game_log.e.push(event_response.clone());
+            let mut revealed_blocks: Option<Vec<MapSpaces>> = None;
+            for unit_path in unit_paths.iter() {
+                if let Some(blocks) = _game_state.reveal_path(&unit_path.path) {
+                    revealed_blocks.get_or_insert_with(Vec::new).extend(blocks);
                 }
+            }
 
-                let mut bool_temp = false;
-                if attacker_result_clone.unwrap().trigger_defender {
-                    bool_temp = true;
-                }
-                let result_type = if bool_temp {
-                    ResultType::DefendersDamaged
-                } else {
-                    ResultType::Nothing
-                };
+            defender_damaged_result = _game_state.defender_movement(&unit_paths, _shortest_path);
 
-                let mut is_attacker_alive = true;
+            for unit_path in unit_paths.iter() {
+                log_unit_path(_game_log, unit_path.unit_id, &unit_path.path, None);
+            }
 
-                if let Some(attacker) = &_game_state.attacker {
-                    if attacker.attacker_health == 0 {
-                        is_attacker_alive = false;
-                    }
-                }
+            let result_type = if moved_units.iter().any(|unit| unit.trigger_defender) {
+                ResultType::DefendersDamaged
+            } else {
+                ResultType::Nothing
+            };
 
-                if _game_state.in_validation.is_invalidated {
-                    return Some(Ok(send_terminate_game_message(
-                        socket_request.frame_number,
-                        _game_state.in_validation.message.clone(),
-                    )));
-                }
+            //alive while any unit of the squad is
+            let is_attacker_alive = _game_state.alive_units() > 0;
 
-                return Some(Ok(SocketResponse {
-                    frame_number: socket_request.frame_number,
-                    result_type,
-                    is_alive: Some(is_attacker_alive),
-                    attacker_health: Some(defender_damaged_result.clone().attacker_health),
-                    exploded_mines: None,
-                    // triggered_defenders: Some(defender_damaged_result.clone().defender_response),
-                    defender_damaged: Some(defender_damaged_result.clone().defender_response),
-                    damaged_buildings: None,
-                    total_damage_percentage: Some(_game_state.damage_percentage),
-                    is_sync: false,
-                    is_game_over: false,
-                    message: Some(String::from("Movement Response")),
-                }));
+            if _game_state.in_validation.is_invalidated {
+                return Some(Ok(send_terminate_game_message(
+                    socket_request.frame_number,
+                    _game_state.in_validation.message.clone(),
+                )));
             }
+
+            return Some(Ok(SocketResponse {
+                frame_number: socket_request.frame_number,
+                result_type,
+                is_alive: Some(is_attacker_alive),
+                attacker_health: Some(defender_damaged_result.clone().attacker_health),
+                exploded_mines: None,
+                // triggered_defenders: Some(defender_damaged_result.clone().defender_response),
+                defender_damaged: Some(defender_damaged_result.clone().defender_response),
+                damaged_buildings: None,
+                revealed_blocks,
+                squad: Some(_game_state.squad_response()),
+                stunned_defenders: None,
+                disarmed_mines: None,
+                total_damage_percentage: Some(_game_state.damage_percentage),
+                is_sync: false,
+                is_game_over: false,
+                message: Some(String::from("Movement Response")),
+            }));
         }
         ActionType::IsMine => {
             // is_mine
+            let unit_index = _game_state.unit_index(socket_request.unit_id).unwrap();
             let start_pos: Option<Coords> = socket_request.start_position;
-            exploded_mines_result = _game_state.mine_blast(start_pos);
+            exploded_mines_result = _game_state.mine_blast(unit_index, start_pos);
 
             let mut bool_temp = false;
             if !exploded_mines_result.is_empty() {
@@ -206,13 +351,7 @@ pub fn game_handler(
                 ResultType::Nothing
             };
 
-            let mut is_attacker_alive = true;
-
-            if let Some(attacker) = &_game_state.attacker {
-                if attacker.attacker_health == 0 {
-                    is_attacker_alive = false;
-                }
-            }
+            let is_attacker_alive = _game_state.attackers[unit_index].attacker_health > 0;
 
             if _game_state.in_validation.is_invalidated {
                 return Some(Ok(send_terminate_game_message(
@@ -226,11 +365,15 @@ pub fn game_handler(
                 result_type,
                 is_alive: Some(is_attacker_alive),
 
-                attacker_health: None,
+                attacker_health: Some(_game_state.attackers[unit_index].attacker_health),
                 exploded_mines: Some(exploded_mines_result),
                 // triggered_defenders: None,
                 defender_damaged: None,
                 damaged_buildings: None,
+                revealed_blocks: None,
+                squad: Some(_game_state.squad_response()),
+                stunned_defenders: None,
+                disarmed_mines: None,
                 total_damage_percentage: Some(_game_state.damage_percentage),
                 is_sync: false,
                 is_game_over: false,
@@ -238,44 +381,34 @@ pub fn game_handler(
             }));
         }
         ActionType::PlaceBombs => {
+            let unit_index = _game_state.unit_index(socket_request.unit_id).unwrap();
+            let unit_id = _game_state.attackers[unit_index].unit_id;
             let attacker_delta: Vec<Coords> = socket_request.attacker_path.clone();
             let current_pos = socket_request.start_position.unwrap();
             let bomb_coords = socket_request.bomb_position;
 
-            if _game_state.bombs.total_count == 0 {
+            if _game_state.bombs_left() == 0 {
                 return Some(Ok(send_terminate_game_message(
                     socket_request.frame_number,
                     "No bombs left".to_string(),
                 )));
             }
 
-            for coord in attacker_delta.clone() {
-                let mut direction = Direction::Up;
-
-                let prev_pos = _game_log.e.last().unwrap().coords;
-                if prev_pos.x < coord.x {
-                    direction = Direction::Down;
-                } else if prev_pos.x > coord.x {
-                    direction = Direction::Up;
-                } else if prev_pos.y < coord.y {
-                    direction = Direction::Left;
-                } else if prev_pos.y > coord.y {
-                    direction = Direction::Right;
-                }
+            log_unit_path(_game_log, unit_id, &attacker_delta, Some(bomb_coords));
 
-                let event_response = EventResponse {
-                    attacker_id: None,
-                    bomb_id: None,
-                    coords: coord,
-                    direction,
-                    is_bomb: coord == bomb_coords,
-                };
+            let blast = _game_state.place_bombs(unit_index, current_pos, bomb_coords);
+            buildings_damaged_result = blast.buildings;
 
-                _game_log.e.push(event_response.clone());
+            //defenders can walk through whatever the blast brought down
+            for building in _game_state.buildings.iter().filter(|building| {
+                building.current_hp == 0
+                    && buildings_damaged_result
+                        .iter()
+                        .any(|damaged| damaged.id == building.id)
+            }) {
+                _shortest_path.terrain.clear_building(building);
             }
 
-            buildings_damaged_result = _game_state.place_bombs(current_pos, bomb_coords);
-
             _game_log.r.b += 1;
             _game_log.r.d = _game_state.damage_percentage as i32;
             _game_log.r.a = _game_state.artifacts;
@@ -286,6 +419,8 @@ pub fn game_handler(
             }
             let result_type = if bool_temp {
                 ResultType::BuildingsDamaged
+            } else if !blast.stunned_defenders.is_empty() || !blast.disarmed_mines.is_empty() {
+                ResultType::TargetsDisabled
             } else {
                 ResultType::Nothing
             };
@@ -307,12 +442,55 @@ pub fn game_handler(
                 // triggered_defenders: None,
                 defender_damaged: None,
                 damaged_buildings: Some(buildings_damaged_result),
+                revealed_blocks: None,
+                squad: Some(_game_state.squad_response()),
+                stunned_defenders: Some(blast.stunned_defenders),
+                disarmed_mines: Some(blast.disarmed_mines),
                 total_damage_percentage: Some(_game_state.damage_percentage),
                 is_sync: false,
                 is_game_over: false,
                 message: Some(String::from("Place Bomb Response")),
             }));
         }
+        ActionType::Scout => {
+            if _game_state.bombs_left() == 0 {
+                return Some(Ok(send_terminate_game_message(
+                    socket_request.frame_number,
+                    "No bombs left".to_string(),
+                )));
+            }
+
+            let unit_index = _game_state.unit_index(socket_request.unit_id);
+            let revealed_blocks = _game_state.scout(unit_index, socket_request.bomb_position);
+            _game_log.r.b += 1;
+
+            if _game_state.in_validation.is_invalidated {
+                return Some(Ok(send_terminate_game_message(
+                    socket_request.frame_number,
+                    _game_state.in_validation.message.clone(),
+                )));
+            }
+
+            return Some(Ok(SocketResponse {
+                frame_number: socket_request.frame_number,
+                result_type: ResultType::TilesRevealed,
+                is_alive: Some(true),
+
+                attacker_health: None,
+                exploded_mines: None,
+                // triggered_defenders: None,
+                defender_damaged: None,
+                damaged_buildings: None,
+                revealed_blocks: Some(revealed_blocks),
+                squad: Some(_game_state.squad_response()),
+                stunned_defenders: None,
+                disarmed_mines: None,
+                total_damage_percentage: Some(_game_state.damage_percentage),
+                is_sync: false,
+                is_game_over: false,
+                message: Some(String::from("Scout Response")),
+            }));
+        }
         ActionType::Idle => {
             return Some(Ok(SocketResponse {
                 frame_number: socket_request.frame_number,
@@ -324,6 +502,10 @@ pub fn game_handler(
                 // triggered_defenders: None,
                 defender_damaged: None,
                 damaged_buildings: None,
+                revealed_blocks: None,
+                squad: None,
+                stunned_defenders: None,
+                disarmed_mines: None,
                 total_damage_percentage: Some(_game_state.damage_percentage),
                 is_sync: false,
                 is_game_over: false,
@@ -340,6 +522,10 @@ pub fn game_handler(
                 // triggered_defenders: None,
                 defender_damaged: None,
                 damaged_buildings: None,
+                revealed_blocks: None,
+                squad: None,
+                stunned_defenders: None,
+                disarmed_mines: None,
                 total_damage_percentage: Some(_game_state.damage_percentage),
                 is_sync: false,
                 is_game_over: true,
@@ -349,7 +535,16 @@ pub fn game_handler(
             return Some(Ok(socket_response));
         }
         ActionType::SelfDestruct => {
-            _game_state.self_destruct();
+            let unit_index = _game_state.unit_index(socket_request.unit_id).unwrap();
+            _game_state.self_destruct(unit_index);
+
+            if _game_state.in_validation.is_invalidated {
+                return Some(Ok(send_terminate_game_message(
+                    socket_request.frame_number,
+                    _game_state.in_validation.message.clone(),
+                )));
+            }
+
             let socket_response = SocketResponse {
                 frame_number: socket_request.frame_number,
                 result_type: ResultType::Nothing,
@@ -359,6 +554,10 @@ pub fn game_handler(
                 // triggered_defenders: None,
                 defender_damaged: None,
                 damaged_buildings: None,
+                revealed_blocks: None,
+                squad: Some(_game_state.squad_response()),
+                stunned_defenders: None,
+                disarmed_mines: None,
                 total_damage_percentage: Some(_game_state.damage_percentage),
                 is_sync: false,
                 is_game_over: false,
@@ -367,6 +566,501 @@ pub fn game_handler(
 
             return Some(Ok(socket_response));
         }
+        ActionType::UseAbility => {
+            let unit_index = _game_state.unit_index(socket_request.unit_id).unwrap();
+            _game_state.use_ability(unit_index);
+
+            if _game_state.in_validation.is_invalidated {
+                return Some(Ok(send_terminate_game_message(
+                    socket_request.frame_number,
+                    _game_state.in_validation.message.clone(),
+                )));
+            }
+
+            let unit = &_game_state.attackers[unit_index];
+            _game_log.e.push(EventResponse {
+                attacker_id: Some(unit.id),
+                unit_id: Some(unit.unit_id),
+                bomb_id: None,
+                coords: unit.attacker_pos,
+                direction: Direction::Up,
+                is_bomb: false,
+                is_ability: true,
+            });
+
+            return Some(Ok(SocketResponse {
+                frame_number: socket_request.frame_number,
+                result_type: ResultType::AbilityUsed,
+                is_alive: Some(true),
+                attacker_health: Some(unit.attacker_health),
+                exploded_mines: None,
+                // triggered_defenders: None,
+                defender_damaged: None,
+                damaged_buildings: None,
+                revealed_blocks: None,
+                squad: Some(_game_state.squad_response()),
+                stunned_defenders: None,
+                disarmed_mines: None,
+                total_damage_percentage: Some(_game_state.damage_percentage),
+                is_sync: false,
+                is_game_over: false,
+                message: Some(String::from("Ability Response")),
+            }));
+        }
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::attack::util::ResultResponse;
+    use crate::api::defense::shortest_path::NextHopMatrix;
+    use crate::api::defense::util::SimulationBaseResponse;
+    use crate::models::{AttackerAbility, EmpEffect, MineEffect, User};
+    use crate::validator::fog::FogOfWar;
+    use crate::validator::pathfinding::TerrainGrid;
+    use crate::validator::placement::Placement;
+    use crate::validator::util::{BuildingDetails, DefenderDetails, RangedAttack};
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    //roads on every fourth row and column
+    fn roads() -> Vec<(i32, i32)> {
+        let size = MAP_SIZE as i32;
+        (0..size)
+            .flat_map(|x| (0..size).map(move |y| (x, y)))
+            .filter(|(x, y)| x % 4 == 0 || y % 4 == 0)
+            .collect()
+    }
+
+    fn buildings() -> Vec<BuildingDetails> {
+        [(1, 1, 3, 3, false), (5, 5, 2, 3, false), (9, 1, 1, 3, true)]
+            .into_iter()
+            .enumerate()
+            .map(|(id, (x, y, width, height, is_wall))| BuildingDetails {
+                id: id as i32,
+                current_hp: 100,
+                total_hp: 100,
+                artifacts_obtained: 50,
+                tile: Coords { x, y },
+                width,
+                height,
+                is_wall,
+            })
+            .collect()
+    }
+
+    fn defenders() -> Vec<DefenderDetails> {
+        //the second one fires from range
+        [(4, 6, 2, false), (12, 12, 3, true), (20, 2, 1, false)]
+            .into_iter()
+            .enumerate()
+            .map(|(id, (x, y, speed, is_ranged))| DefenderDetails {
+                id: id as i32,
+                radius: 4,
+                speed,
+                damage: 30,
+                defender_pos: Coords { x, y },
+                is_alive: true,
+                damage_dealt: false,
+                target_id: None,
+                target_unit: None,
+                path_in_current_frame: Vec::new(),
+                stunned_frames: 0,
+                ranged: is_ranged.then_some(RangedAttack {
+                    range: 3,
+                    damage: 20,
+                    projectile_speed: 4,
+                }),
+                pending_hit: None,
+            })
+            .collect()
+    }
+
+    fn mines() -> Vec<MineDetails> {
+        [
+            (0, 2, MineEffect::None),
+            (8, 3, MineEffect::Slow),
+            (4, 9, MineEffect::Root),
+            (16, 4, MineEffect::Disarm),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(id, (x, y, effect))| MineDetails {
+            id: id as i32,
+            position: Coords { x, y },
+            radius: 1,
+            damage: 20,
+            effect,
+            effect_duration: 3,
+            disarmed_frames: 0,
+        })
+        .collect()
+    }
+
+    fn attacker_types() -> HashMap<i32, AttackerType> {
+        [
+            AttackerAbility::Sprint,
+            AttackerAbility::Heal,
+            AttackerAbility::Shield,
+            AttackerAbility::None,
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|(ability, id)| {
+            (
+                id,
+                AttackerType {
+                    id,
+                    max_health: 100,
+                    speed: id + 1,
+                    amt_of_emps: 3,
+                    level: id,
+                    cost: 0,
+                    name: format!("attacker{id}"),
+                    max_level: 2,
+                    next_level_id: None,
+                    ability,
+                    ability_power: 2,
+                    ability_duration: 3,
+                    ability_cooldown: 5,
+                    ability_charges: 2,
+                },
+            )
+        })
+        .collect()
+    }
+
+    fn bomb_types() -> Vec<BombType> {
+        [EmpEffect::Damage, EmpEffect::Disable, EmpEffect::Both]
+            .into_iter()
+            .zip(1..)
+            .map(|(effect, id)| BombType {
+                id,
+                radius: 2,
+                damage: 50,
+                total_count: 0,
+                effect,
+                disable_duration: 2,
+            })
+            .collect()
+    }
+
+    fn user(id: i32) -> User {
+        User {
+            id,
+            name: format!("player{id}"),
+            email: format!("player{id}@example.com"),
+            username: format!("player{id}"),
+            is_pragyan: false,
+            attacks_won: 0,
+            defenses_won: 0,
+            trophies: 1000,
+            avatar_id: 0,
+            artifacts: 0,
+        }
+    }
+
+    fn game_log() -> GameLog {
+        GameLog {
+            g: 1,
+            a: user(1),
+            d: user(2),
+            b: SimulationBaseResponse {
+                m: 1,
+                ms: Vec::new(),
+                b: Vec::new(),
+                d: Vec::new(),
+                mt: Vec::new(),
+                at: Vec::new(),
+                bt: Vec::new(),
+            },
+            e: Vec::new(),
+            r: ResultResponse {
+                d: 0,
+                a: 0,
+                b: 0,
+                au: 0,
+                na: 0,
+                nd: 0,
+                oa: 0,
+                od: 0,
+            },
+        }
+    }
+
+    //mostly on the map, sometimes just off it and now and then far away
+    fn coords() -> impl Strategy<Value = Coords> {
+        let size = MAP_SIZE as i32;
+        prop_oneof![
+            1 => any::<(i32, i32)>(),
+            1 => (-2..size + 2, -2..size + 2),
+            8 => (0..size, 0..size),
+        ]
+        .prop_map(|(x, y)| Coords { x, y })
+    }
+
+    //a walk of single steps, with the odd jump thrown in
+    fn path() -> impl Strategy<Value = Vec<Coords>> {
+        let step = (0..4usize, prop::option::weighted(0.1, coords()));
+        (coords(), prop::collection::vec(step, 0..6)).prop_map(|(start, steps)| {
+            let mut position = start;
+            let mut path = Vec::new();
+            for (direction, jump) in steps {
+                path.push(position);
+                let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][direction];
+                position = jump.unwrap_or(Coords {
+                    x: position.x.saturating_add(dx),
+                    y: position.y.saturating_add(dy),
+                });
+            }
+            path
+        })
+    }
+
+    //mostly units that could have been placed, sometimes none at all
+    fn unit_id() -> impl Strategy<Value = Option<i32>> {
+        prop_oneof![
+            1 => Just(None),
+            1 => any::<i32>().prop_map(Some),
+            8 => (0..4).prop_map(Some),
+        ]
+    }
+
+    fn action() -> impl Strategy<Value = ActionType> {
+        (0..9).prop_map(|action| match action {
+            0 => ActionType::IsMine,
+            1 => ActionType::PlaceAttacker,
+            2 => ActionType::MoveAttacker,
+            3 => ActionType::PlaceBombs,
+            4 => ActionType::Idle,
+            5 => ActionType::Terminate,
+            6 => ActionType::SelfDestruct,
+            7 => ActionType::UseAbility,
+            _ => ActionType::Scout,
+        })
+    }
+
+    //a request along with how it is tied to the game it is sent in
+    #[derive(Debug)]
+    struct Step {
+        request: SocketRequest,
+        //placements on a spawn zone tile get past the placement checks
+        spawn_tile: Option<Index>,
+        is_in_order: bool,
+    }
+
+    fn step() -> impl Strategy<Value = Step> {
+        let unit_paths = prop::collection::vec(
+            (unit_id(), path()).prop_map(|(unit_id, path)| UnitPath {
+                unit_id: unit_id.unwrap_or(0),
+                path,
+            }),
+            1..4,
+        );
+        (
+            action(),
+            path(),
+            prop::option::weighted(0.3, unit_paths),
+            prop::option::weighted(0.9, any::<bool>()),
+            (any::<bool>(), coords()),
+            prop::option::weighted(0.9, 0..5),
+            0..4,
+            unit_id(),
+            prop::option::of(any::<Index>()),
+            prop::option::weighted(0.9, Just(())),
+            any::<i32>(),
+        )
+            .prop_map(
+                |(
+                    action_type,
+                    attacker_path,
+                    unit_paths,
+                    start_on_path,
+                    (bomb_on_path, bomb_position),
+                    attacker_id,
+                    bomb_id,
+                    unit_id,
+                    spawn_tile,
+                    is_in_order,
+                    frame_number,
+                )| {
+                    let start_position = start_on_path.map(|on_path| match attacker_path.first() {
+                        Some(&start) if on_path => start,
+                        _ => bomb_position,
+                    });
+                    let bomb_position = match attacker_path.last() {
+                        Some(&end) if bomb_on_path => end,
+                        _ => bomb_position,
+                    };
+                    Step {
+                        spawn_tile: spawn_tile.filter(|_| action_type == ActionType::PlaceAttacker),
+                        request: SocketRequest {
+                            frame_number,
+                            action_type,
+                            attacker_id,
+                            bomb_id: Some(bomb_id),
+                            start_position,
+                            attacker_path,
+                            bomb_position,
+                            is_game_over: None,
+                            unit_id,
+                            unit_paths: unit_paths.unwrap_or_default(),
+                        },
+                        is_in_order: is_in_order.is_some(),
+                    }
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn game_handler_survives_any_request_sequence(
+            steps in prop::collection::vec(step(), 1..60),
+            has_fog in any::<bool>(),
+        ) {
+            let road_list = roads();
+            let roads: HashSet<(i32, i32)> = road_list.iter().copied().collect();
+            let mut pathfinder = DefenderPathfinder {
+                terrain: TerrainGrid::new(&roads, &buildings(), &defenders(), &mines()),
+                road_paths: NextHopMatrix::new(&road_list),
+            };
+            let attacker_types = attacker_types();
+            let bomb_types = bomb_types();
+            //the attacker owns every attacker type but the last
+            let placement = Placement::new(&roads, HashSet::from([1, 2, 3]));
+            let spawn_zone: Vec<Coords> = placement.spawn_zone.iter().copied().collect();
+
+            let mut game_state = State::new(1, 2, defenders(), mines(), buildings());
+            game_state.set_total_hp_buildings();
+            game_state.set_placement(placement);
+            if has_fog {
+                game_state.set_fog_of_war(FogOfWar::new(Vec::new()).0);
+            }
+            let mut game_log = game_log();
+
+            for (frame_number, step) in (1..).zip(steps) {
+                let mut request = step.request;
+                if step.is_in_order {
+                    request.frame_number = frame_number;
+                }
+                if let Some(tile) = step.spawn_tile.filter(|_| !spawn_zone.is_empty()) {
+                    request.start_position = Some(spawn_zone[tile.index(spawn_zone.len())]);
+                }
+
+                let is_game_over = game_handler(
+                    &attacker_types,
+                    request,
+                    &mut game_state,
+                    &mut pathfinder,
+                    &roads,
+                    &bomb_types,
+                    &mut game_log,
+                )
+                .and_then(|response| response.ok())
+                .is_some_and(|response| response.is_game_over);
+                if is_game_over {
+                    break;
+                }
+            }
+        }
+    }
+
+    //one sprinting unit on a straight road, away from every defender and mine
+    fn game_with_unit() -> State {
+        let attacker = attacker_types()[&1].clone();
+        let mut game_state = State::new(1, 2, Vec::new(), Vec::new(), Vec::new());
+        game_state.attackers.push(Attacker {
+            id: attacker.id,
+            unit_id: 0,
+            path_in_current_frame: Vec::new(),
+            attacker_pos: Coords { x: 0, y: 0 },
+            attacker_health: attacker.max_health,
+            attacker_speed: attacker.speed,
+            bombs: Vec::new(),
+            trigger_defender: false,
+            bomb_count: 0,
+            status_effects: Vec::new(),
+            max_health: attacker.max_health,
+            ability: AbilityState::new(&attacker),
+            bomb_type: None,
+        });
+        game_state
+    }
+
+    fn send(
+        game_state: &mut State,
+        action_type: ActionType,
+        frame_number: i32,
+        attacker_path: Vec<Coords>,
+    ) -> SocketResponse {
+        let roads: HashSet<(i32, i32)> = (0..MAP_SIZE as i32).map(|x| (x, 0)).collect();
+        let mut pathfinder = DefenderPathfinder {
+            terrain: TerrainGrid::new(&roads, &[], &[], &[]),
+            road_paths: NextHopMatrix::new(&[]),
+        };
+        let request = SocketRequest {
+            frame_number,
+            action_type,
+            attacker_id: None,
+            bomb_id: None,
+            start_position: attacker_path.first().copied(),
+            attacker_path,
+            bomb_position: Coords { x: 0, y: 0 },
+            is_game_over: None,
+            unit_id: Some(0),
+            unit_paths: Vec::new(),
+        };
+        game_handler(
+            &attacker_types(),
+            request,
+            game_state,
+            &mut pathfinder,
+            &roads,
+            &bomb_types(),
+            &mut game_log(),
+        )
+        .unwrap()
+        .unwrap()
+    }
+
+    fn road_path(tiles: i32) -> Vec<Coords> {
+        (0..=tiles).map(|x| Coords { x, y: 0 }).collect()
+    }
+
+    #[test]
+    fn idle_frames_do_not_wear_off_effects() {
+        let mut game_state = game_with_unit();
+        game_state.apply_status_effect(0, MineEffect::Root, 3);
+
+        send(&mut game_state, ActionType::Idle, 1_000_000, Vec::new());
+        assert!(game_state.has_status_effect(0, MineEffect::Root));
+
+        let response = send(&mut game_state, ActionType::MoveAttacker, 1, road_path(2));
+        assert!(response.is_game_over);
+    }
+
+    #[test]
+    fn skipped_frames_do_not_bring_an_ability_back_early() {
+        let mut game_state = game_with_unit();
+        send(&mut game_state, ActionType::UseAbility, 0, Vec::new());
+        let cooldown = game_state.attackers[0].ability.cooldown_left;
+        assert!(cooldown > 0);
+
+        send(&mut game_state, ActionType::Idle, 1_000_000, Vec::new());
+        let response = send(
+            &mut game_state,
+            ActionType::UseAbility,
+            1_000_000,
+            Vec::new(),
+        );
+        assert!(response.is_game_over);
+        assert_eq!(response.message.as_deref(), Some("Ability on cooldown"));
+
+        let mut game_state = game_with_unit();
+        send(&mut game_state, ActionType::UseAbility, 0, Vec::new());
+        send(&mut game_state, ActionType::MoveAttacker, 50, road_path(2));
+        assert_eq!(game_state.attackers[0].ability.cooldown_left, cooldown);
+    }
+}
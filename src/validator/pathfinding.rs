@@ -0,0 +1,151 @@
+use super::util::{tile_index, BuildingDetails, Coords, DefenderDetails, MineDetails};
+use crate::api::defense::shortest_path::NextHopMatrix;
+use crate::constants::{MAP_SIZE, OPEN_TILE_COST, ROAD_TILE_COST};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+//(estimated total cost, cost so far, x, y) of a tile waiting in the open set
+type OpenTile = Reverse<(u32, u32, i32, i32)>;
+
+//cost of entering every tile of the map, None for tiles covered by buildings
+pub struct TerrainGrid {
+    costs: Vec<Option<u32>>,
+    //A* scratch buffers reused across searches
+    best_cost: Vec<u32>,
+    first_step: Vec<Option<Coords>>,
+    open: BinaryHeap<OpenTile>,
+}
+
+impl TerrainGrid {
+    pub fn new(
+        roads: &HashSet<(i32, i32)>,
+        buildings: &[BuildingDetails],
+        defenders: &[DefenderDetails],
+        mines: &[MineDetails],
+    ) -> Self {
+        let mut costs = vec![Some(OPEN_TILE_COST); MAP_SIZE * MAP_SIZE];
+        for &(x, y) in roads {
+            if let Some(tile) = tile_index(x, y) {
+                costs[tile] = Some(ROAD_TILE_COST);
+            }
+        }
+
+        //defender huts and mines are placed as blocks too, but can be walked over
+        let walkable: HashSet<Coords> = defenders
+            .iter()
+            .map(|defender| defender.defender_pos)
+            .chain(mines.iter().map(|mine| mine.position))
+            .collect();
+        for building in buildings {
+            if walkable.contains(&building.tile) {
+                continue;
+            }
+            for x in building.tile.x..building.tile.x + building.width {
+                for y in building.tile.y..building.tile.y + building.height {
+                    if let Some(tile) = tile_index(x, y) {
+                        costs[tile] = None;
+                    }
+                }
+            }
+        }
+
+        TerrainGrid {
+            costs,
+            best_cost: vec![u32::MAX; MAP_SIZE * MAP_SIZE],
+            first_step: vec![None; MAP_SIZE * MAP_SIZE],
+            open: BinaryHeap::new(),
+        }
+    }
+
+    //a destroyed building or wall no longer blocks its footprint
+    pub fn clear_building(&mut self, building: &BuildingDetails) {
+        for x in building.tile.x..building.tile.x + building.width {
+            for y in building.tile.y..building.tile.y + building.height {
+                if let Some(tile) = tile_index(x, y) {
+                    self.costs[tile] = Some(OPEN_TILE_COST);
+                }
+            }
+        }
+    }
+
+    //first step of the cheapest path found by A*, the manhattan distance is admissible
+    //because no tile is cheaper than a road
+    pub fn next_hop(&mut self, source: Coords, dest: Coords) -> Option<Coords> {
+        let (source_tile, dest_tile) =
+            (tile_index(source.x, source.y)?, tile_index(dest.x, dest.y)?);
+        if source_tile == dest_tile {
+            return None;
+        }
+
+        let heuristic = |x: i32, y: i32| {
+            ((x - dest.x).unsigned_abs() + (y - dest.y).unsigned_abs()) * ROAD_TILE_COST
+        };
+
+        let costs = &self.costs;
+        let best_cost = &mut self.best_cost;
+        let first_step = &mut self.first_step;
+        let open = &mut self.open;
+        best_cost.fill(u32::MAX);
+        first_step.fill(None);
+        open.clear();
+
+        best_cost[source_tile] = 0;
+        open.push(Reverse((
+            heuristic(source.x, source.y),
+            0,
+            source.x,
+            source.y,
+        )));
+
+        while let Some(Reverse((_, cost, x, y))) = open.pop() {
+            let tile = x as usize * MAP_SIZE + y as usize;
+            if tile == dest_tile {
+                return first_step[tile];
+            }
+            if cost > best_cost[tile] {
+                continue;
+            }
+
+            for (dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                let (nx, ny) = (x + dx, y + dy);
+                let neighbor = match tile_index(nx, ny) {
+                    Some(neighbor) => neighbor,
+                    None => continue,
+                };
+                //the attacker may stand on a tile the defender could not path through
+                let step_cost = match costs[neighbor] {
+                    Some(step_cost) => step_cost,
+                    None if neighbor == dest_tile => OPEN_TILE_COST,
+                    None => continue,
+                };
+
+                let next_cost = cost + step_cost;
+                if next_cost < best_cost[neighbor] {
+                    best_cost[neighbor] = next_cost;
+                    first_step[neighbor] = if tile == source_tile {
+                        Some(Coords { x: nx, y: ny })
+                    } else {
+                        first_step[tile]
+                    };
+                    open.push(Reverse((next_cost + heuristic(nx, ny), next_cost, nx, ny)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+//road to road hops come from the precomputed matrix, everything else goes through A*
+pub struct DefenderPathfinder {
+    pub road_paths: NextHopMatrix,
+    pub terrain: TerrainGrid,
+}
+
+impl DefenderPathfinder {
+    pub fn next_hop(&mut self, source: Coords, dest: Coords) -> Option<Coords> {
+        self.road_paths
+            .next_hop(source, dest)
+            .or_else(|| self.terrain.next_hop(source, dest))
+    }
+}
@@ -0,0 +1,52 @@
+use super::util::Coords;
+use crate::constants::{MAP_SIZE, SPAWN_ZONE_DEPTH};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+//where the attacker may enter the base and which attackers they may send in
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Placement {
+    pub spawn_zone: HashSet<Coords>,
+    pub owned_attackers: HashSet<i32>,
+}
+
+impl Placement {
+    pub fn new(roads: &HashSet<(i32, i32)>, owned_attackers: HashSet<i32>) -> Self {
+        Placement {
+            spawn_zone: spawn_zone(roads),
+            owned_attackers,
+        }
+    }
+
+    //reason the placement is not allowed, if any
+    pub fn check(&self, attacker_type_id: i32, position: Coords) -> Option<String> {
+        if !self.owned_attackers.contains(&attacker_type_id) {
+            return Some("Attacker not owned".to_string());
+        }
+        if !self.spawn_zone.contains(&position) {
+            return Some("Attacker placed outside the spawn zone".to_string());
+        }
+        None
+    }
+}
+
+//roads near the edge of the map, or every road of a base whose roads never get near it
+pub fn spawn_zone(roads: &HashSet<(i32, i32)>) -> HashSet<Coords> {
+    let size = MAP_SIZE as i32;
+    let near_edge = |&&(x, y): &&(i32, i32)| {
+        x < SPAWN_ZONE_DEPTH
+            || y < SPAWN_ZONE_DEPTH
+            || x >= size - SPAWN_ZONE_DEPTH
+            || y >= size - SPAWN_ZONE_DEPTH
+    };
+
+    let mut zone: HashSet<Coords> = roads
+        .iter()
+        .filter(near_edge)
+        .map(|&(x, y)| Coords { x, y })
+        .collect();
+    if zone.is_empty() {
+        zone = roads.iter().map(|&(x, y)| Coords { x, y }).collect();
+    }
+    zone
+}
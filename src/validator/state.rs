@@ -1,19 +1,28 @@
 use std::{
-    cmp::max,
+    cmp::{max, min},
     collections::{HashMap, HashSet},
 };
 
-use crate::constants::{BOMB_DAMAGE_MULTIPLIER, LIVES, PERCENTANGE_ARTIFACTS_OBTAINABLE};
+use crate::constants::{
+    BOMB_DAMAGE_MULTIPLIER, FOG_OF_WAR_REVEAL_RADIUS, FOG_OF_WAR_SCOUT_RADIUS, LIVES,
+    MAX_SQUAD_SIZE, PERCENTANGE_ARTIFACTS_OBTAINABLE, SLOW_EFFECT_SPEED_PERCENTAGE,
+};
+use crate::models::{AttackerAbility, MapSpaces, MineEffect};
 use crate::{
-    api::attack::socket::{BuildingResponse, DefenderResponse},
+    api::attack::socket::{
+        BuildingResponse, DefenderResponse, DisabledResponse, UnitPath, UnitResponse,
+    },
     validator::util::{
-        Attacker, BuildingDetails, Coords, DefenderDetails, DefenderReturnType, InValidation,
-        MineDetails, SourceDestXY,
+        AbilityState, Attacker, BombBlast, BuildingDetails, Coords, DefenderDetails,
+        DefenderReturnType, InValidation, MineDetails, StatusEffect,
     },
 };
 
 use serde::{Deserialize, Serialize};
 
+use super::fog::FogOfWar;
+use super::pathfinding::DefenderPathfinder;
+use super::placement::Placement;
 use super::util::BombType;
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -21,9 +30,8 @@ pub struct State {
     pub frame_no: i32,
     pub attacker_user_id: i32,
     pub defender_user_id: i32,
-    pub attacker: Option<Attacker>,
+    pub attackers: Vec<Attacker>,
     pub attacker_death_count: i32,
-    pub bombs: BombType,
     pub damage_percentage: f32,
     pub artifacts: i32,
     pub defenders: Vec<DefenderDetails>,
@@ -31,6 +39,8 @@ pub struct State {
     pub buildings: Vec<BuildingDetails>,
     pub total_hp_buildings: i32,
     pub in_validation: InValidation,
+    pub fog_of_war: Option<FogOfWar>,
+    pub placement: Placement,
 }
 
 impl State {
@@ -45,14 +55,8 @@ impl State {
             frame_no: 0,
             attacker_user_id,
             defender_user_id,
-            attacker: None,
+            attackers: Vec::new(),
             attacker_death_count: 0,
-            bombs: BombType {
-                id: -1,
-                radius: 0,
-                damage: 0,
-                total_count: 0,
-            },
             damage_percentage: 0.0,
             artifacts: 0,
             defenders,
@@ -63,110 +67,356 @@ impl State {
                 message: "".to_string(),
                 is_invalidated: false,
             },
+            fog_of_war: None,
+            placement: Placement::default(),
+        }
+    }
+
+    pub fn self_destruct(&mut self, unit_index: usize) {
+        if self.attackers[unit_index].attacker_health == 0 {
+            self.in_validation = InValidation {
+                message: "Dead unit self destructed".to_string(),
+                is_invalidated: true,
+            };
+            return;
         }
+        self.attackers[unit_index].attacker_health = 0;
+        self.unit_died(unit_index);
     }
 
-    pub fn self_destruct(&mut self) {
+    //dead units stay in the squad so their ids keep resolving, but lose their bombs and chasers
+    fn unit_died(&mut self, unit_index: usize) {
         self.attacker_death_count += 1;
-        self.attacker.as_mut().unwrap().attacker_health = 0;
+        let unit = &mut self.attackers[unit_index];
+        unit.bomb_count = 0;
+        unit.attacker_pos = Coords { x: -1, y: -1 };
+        unit.status_effects.clear();
+
+        let unit_id = unit.unit_id;
         for defender in self.defenders.iter_mut() {
-            defender.target_id = None;
+            if defender.target_unit == Some(unit_id) {
+                defender.target_id = None;
+                defender.target_unit = None;
+            }
+        }
+    }
+
+    //index of the given unit, or of the last one placed when none is given
+    pub fn unit_index(&self, unit_id: Option<i32>) -> Option<usize> {
+        match unit_id {
+            Some(unit_id) => self
+                .attackers
+                .iter()
+                .position(|unit| unit.unit_id == unit_id),
+            None => self.attackers.len().checked_sub(1),
         }
     }
 
+    pub fn alive_units(&self) -> usize {
+        self.attackers
+            .iter()
+            .filter(|unit| unit.attacker_health > 0)
+            .count()
+    }
+
+    pub fn bombs_left(&self) -> i32 {
+        self.attackers.iter().map(|unit| unit.bomb_count).sum()
+    }
+
+    pub fn squad_response(&self) -> Vec<UnitResponse> {
+        self.attackers
+            .iter()
+            .map(|unit| UnitResponse {
+                unit_id: unit.unit_id,
+                attacker_id: unit.id,
+                position: unit.attacker_pos,
+                health: unit.attacker_health,
+                bomb_count: unit.bomb_count,
+                bomb_id: unit.bomb_type.as_ref().map(|bomb_type| bomb_type.id),
+                is_alive: unit.attacker_health > 0,
+                ability: unit.ability,
+            })
+            .collect()
+    }
+
+    //walls absorb blasts but do not count towards the destruction percentage
     pub fn set_total_hp_buildings(&mut self) {
         let mut total_hp = 0;
-        for building in self.buildings.iter() {
+        for building in self.buildings.iter().filter(|building| !building.is_wall) {
             total_hp += building.total_hp;
         }
         self.total_hp_buildings = total_hp;
     }
 
-    pub fn set_bombs(&mut self, bomb_type: BombType, bombs: i32) {
-        self.bombs = BombType {
-            id: bomb_type.id,
-            radius: bomb_type.radius,
-            damage: bomb_type.damage,
-            total_count: bombs,
+    pub fn set_fog_of_war(&mut self, fog_of_war: FogOfWar) {
+        self.fog_of_war = Some(fog_of_war);
+    }
+    pub fn set_placement(&mut self, placement: Placement) {
+        self.placement = placement;
+    }
+
+    //blocks uncovered around the attacker's path, None when the game has no fog of war
+    pub fn reveal_path(&mut self, path: &[Coords]) -> Option<Vec<MapSpaces>> {
+        self.fog_of_war
+            .as_mut()
+            .map(|fog| fog.reveal(path, FOG_OF_WAR_REVEAL_RADIUS))
+    }
+
+    //spends a bomb of the acting unit, or any unit with bombs left, to reveal an area of the map
+    pub fn scout(&mut self, unit_index: Option<usize>, position: Coords) -> Vec<MapSpaces> {
+        if self.fog_of_war.is_none() {
+            self.in_validation = InValidation {
+                message: "Scouting without fog of war".to_string(),
+                is_invalidated: true,
+            };
+            return Vec::new();
+        }
+
+        let has_bombs = |unit: &Attacker| unit.attacker_health > 0 && unit.bomb_count > 0;
+        let unit_index = unit_index
+            .filter(|&index| has_bombs(&self.attackers[index]))
+            .or_else(|| self.attackers.iter().position(has_bombs));
+        let unit_index = match unit_index {
+            Some(unit_index) => unit_index,
+            _ => {
+                self.in_validation = InValidation {
+                    message: "Bomb Count forged".to_string(),
+                    is_invalidated: true,
+                };
+                return Vec::new();
+            }
         };
+
+        self.attackers[unit_index].bomb_count -= 1;
+
+        self.fog_of_war
+            .as_mut()
+            .map(|fog| fog.reveal(&[position], FOG_OF_WAR_SCOUT_RADIUS))
+            .unwrap_or_default()
     }
-    pub fn place_attacker(&mut self, attacker: Attacker) {
-        self.attacker = Some(attacker);
-        // println!("defnders: {:?}",self.defenders);
+
+    //units join the squad while it has room, every unit deployed uses up a life,
+    //returns the new unit's id
+    pub fn place_attacker(&mut self, mut attacker: Attacker) -> Option<i32> {
+        let message =
+            if let Some(message) = self.placement.check(attacker.id, attacker.attacker_pos) {
+                Some(message)
+            } else if self.alive_units() >= MAX_SQUAD_SIZE {
+                Some("Squad is full".to_string())
+            } else if self.attackers.len() >= LIVES {
+                Some("No lives left".to_string())
+            } else {
+                None
+            };
+
+        if let Some(message) = message {
+            self.in_validation = InValidation {
+                message,
+                is_invalidated: true,
+            };
+            return None;
+        }
+
+        let unit_id = self.attackers.len() as i32;
+        attacker.unit_id = unit_id;
+        self.attackers.push(attacker);
+        Some(unit_id)
     }
 
-    pub fn mine_blast_update(&mut self, _id: i32, damage_to_attacker: i32) {
-        let attacker = self.attacker.as_mut().unwrap();
+    pub fn mine_blast_update(&mut self, unit_index: usize, _id: i32, damage_to_attacker: i32) {
+        let attacker = &mut self.attackers[unit_index];
 
         if attacker.attacker_health > 0 {
+            let damage_to_attacker = attacker.ability.absorb(damage_to_attacker);
             attacker.attacker_health =
                 std::cmp::max(0, attacker.attacker_health - damage_to_attacker);
             if attacker.attacker_health == 0 {
-                self.attacker_death_count += 1;
-                for defender in self.defenders.iter_mut() {
-                    defender.target_id = None;
-                }
-                attacker.attacker_pos = Coords { x: -1, y: -1 };
+                self.unit_died(unit_index);
             }
         }
 
         self.mines.retain(|mine| mine.id != _id);
     }
 
+    //a retriggered effect keeps the longer of the two durations
+    pub fn apply_status_effect(&mut self, unit_index: usize, effect: MineEffect, duration: i32) {
+        let attacker = &mut self.attackers[unit_index];
+        if attacker.attacker_health == 0 {
+            return;
+        }
+        match attacker
+            .status_effects
+            .iter_mut()
+            .find(|status| status.effect == effect)
+        {
+            Some(status) => status.frames_left = max(status.frames_left, duration),
+            None => attacker.status_effects.push(StatusEffect {
+                effect,
+                frames_left: duration,
+            }),
+        }
+    }
+
+    //charges and cooldowns are the type's, a shield starts absorbing right away
+    pub fn use_ability(&mut self, unit_index: usize) {
+        let unit = &mut self.attackers[unit_index];
+        let message = if unit.attacker_health == 0 {
+            Some("Ability used by a dead unit")
+        } else if unit.ability.ability == AttackerAbility::None {
+            Some("Attacker has no ability")
+        } else if unit.ability.charges_left <= 0 {
+            Some("No ability charges left")
+        } else if unit.ability.cooldown_left > 0 {
+            Some("Ability on cooldown")
+        } else {
+            None
+        };
+
+        if let Some(message) = message {
+            self.in_validation = InValidation {
+                message: message.to_string(),
+                is_invalidated: true,
+            };
+            return;
+        }
+
+        let ability = &mut unit.ability;
+        ability.charges_left -= 1;
+        ability.frames_active = ability.duration;
+        ability.cooldown_left = ability.cooldown;
+        if ability.ability == AttackerAbility::Shield {
+            ability.shield_left = ability.power;
+        }
+    }
+
+    pub fn has_status_effect(&self, unit_index: usize, effect: MineEffect) -> bool {
+        self.attackers[unit_index]
+            .status_effects
+            .iter()
+            .any(|status| status.effect == effect)
+    }
+
     pub fn update_frame_number(&mut self, frame_no: i32) {
         self.frame_no = frame_no;
     }
 
+    //effects wear off one frame at a time, and only for frames the validator accepted
+    fn tick_effects(&mut self) {
+        self.tick_status_effects();
+        self.tick_emp_effects();
+        self.tick_abilities();
+    }
+
+    //moves every unit along its path, each unit at most once per frame
     pub fn attacker_movement(
         &mut self,
         frame_no: i32,
         roads: &HashSet<(i32, i32)>,
-        attacker_current: Attacker,
-    ) -> Option<Attacker> {
-        if (frame_no - self.frame_no) != 1 {
+        unit_paths: &[UnitPath],
+    ) -> Vec<Attacker> {
+        if frame_no.checked_sub(self.frame_no) != Some(1) {
             self.in_validation = InValidation {
                 message: "Frame number mismatch".to_string(),
                 is_invalidated: true,
             };
             // GAME_OVER
+        } else {
+            self.tick_effects();
         }
 
-        if self.attacker_death_count == LIVES {
-            self.in_validation = InValidation {
-                message: "Attacker Lives forged!".to_string(),
-                is_invalidated: true,
+        let mut moved_units: Vec<Attacker> = Vec::new();
+        for unit_path in unit_paths {
+            let message = match self.unit_index(Some(unit_path.unit_id)) {
+                None => Some("Unknown unit"),
+                Some(unit_index) if self.attackers[unit_index].attacker_health == 0 => {
+                    Some("Dead unit moved")
+                }
+                Some(_)
+                    if moved_units
+                        .iter()
+                        .any(|unit| unit.unit_id == unit_path.unit_id) =>
+                {
+                    Some("Unit moved twice in a frame")
+                }
+                Some(unit_index) => {
+                    let moved_unit = self.unit_movement(frame_no, roads, unit_index, unit_path);
+                    moved_units.push(moved_unit);
+                    None
+                }
             };
+            if let Some(message) = message {
+                self.in_validation = InValidation {
+                    message: message.to_string(),
+                    is_invalidated: true,
+                };
+            }
         }
 
-        for coord in attacker_current.path_in_current_frame.clone().into_iter() {
+        self.frame_no = self.frame_no.saturating_add(1);
+
+        moved_units
+    }
+
+    fn unit_movement(
+        &mut self,
+        frame_no: i32,
+        roads: &HashSet<(i32, i32)>,
+        unit_index: usize,
+        unit_path: &UnitPath,
+    ) -> Attacker {
+        for coord in unit_path.path.iter() {
             if !roads.contains(&(coord.x, coord.y)) {
                 // GAME_OVER
 
                 println!("attacker out of road at {} frame", frame_no);
             }
-        }
 
-        let mut attacker = attacker_current.clone();
+            //standing walls cannot be walked through
+            if self.buildings.iter().any(|building| {
+                building.is_wall
+                    && building.current_hp > 0
+                    && (building.tile.x..building.tile.x + building.width).contains(&coord.x)
+                    && (building.tile.y..building.tile.y + building.height).contains(&coord.y)
+            }) {
+                self.in_validation = InValidation {
+                    message: "attacker walked through a wall".to_string(),
+                    is_invalidated: true,
+                };
+            }
+        }
 
-        if attacker.attacker_speed + 1 != attacker.path_in_current_frame.len() as i32 {
+        //stats and mine effects live on the placed unit, the request only carries its path
+        let mut attacker = self.attackers[unit_index].clone();
+        attacker.path_in_current_frame = unit_path.path.clone();
+        attacker.trigger_defender = false;
+        let speed = effective_speed(
+            attacker.attacker_speed,
+            &attacker.status_effects,
+            &attacker.ability,
+        );
+
+        if speed + 1 != attacker.path_in_current_frame.len() as i32 {
             println!(
                 "attacker speed abuse at {} frame --- speed  :{}, length: {}",
                 frame_no,
-                attacker.attacker_speed,
+                speed,
                 attacker.path_in_current_frame.len()
             );
         }
 
-        let mut coord_temp: Coords = Coords {
-            x: attacker_current.path_in_current_frame[0].x,
-            y: attacker_current.path_in_current_frame[0].y,
-        };
-
-        for (i, coord) in attacker_current
-            .path_in_current_frame
-            .into_iter()
-            .enumerate()
+        //an effect sets the exact number of tiles moved, a rooted unit stays put
+        if speed != attacker.attacker_speed
+            && attacker.path_in_current_frame.len() as i32 != speed + 1
         {
+            self.in_validation = InValidation {
+                message: "attacker path does not match its speed under an effect".to_string(),
+                is_invalidated: true,
+            };
+        }
+
+        let mut coord_temp: Coords = unit_path.path[0];
+
+        for (i, &coord) in unit_path.path.iter().enumerate() {
             if (coord_temp.x - coord.x > 1)
                 || (coord_temp.y - coord.y > 1)
                 || ((coord_temp.x - coord.x).abs() == 1 && coord_temp.y != coord.y)
@@ -182,6 +432,7 @@ impl State {
 
             let new_pos = coord;
 
+            //a defender chases the first unit that comes within its radius
             for defender in self.defenders.iter_mut() {
                 if defender.target_id.is_none()
                     && defender.is_alive
@@ -193,7 +444,8 @@ impl State {
                     //     "defender triggered when attacker was at ---- x:{}, y:{} and defender id: {}",
                     //     new_pos.x, new_pos.y, defender.id
                     // );
-                    defender.target_id = Some((i) as f32 / attacker.attacker_speed as f32);
+                    defender.target_id = Some((i) as f32 / max(speed, 1) as f32);
+                    defender.target_unit = Some(attacker.unit_id);
                     attacker.trigger_defender = true;
                 }
             }
@@ -201,41 +453,49 @@ impl State {
             coord_temp = coord;
         }
 
-        self.frame_no += 1;
-
-        let attacker_result = Attacker {
-            id: attacker.id,
-            attacker_pos: *attacker.path_in_current_frame.last().unwrap(),
-            attacker_health: attacker.attacker_health,
-            attacker_speed: attacker.attacker_speed,
-            path_in_current_frame: attacker.path_in_current_frame.clone(),
-            bombs: attacker.bombs.clone(),
-            trigger_defender: attacker.trigger_defender,
-            bomb_count: attacker.bomb_count,
-        };
-        Some(attacker_result)
+        attacker.attacker_pos = *attacker.path_in_current_frame.last().unwrap();
+        attacker
     }
 
     pub fn place_bombs(
         &mut self,
+        unit_index: usize,
         current_pos: Coords,
         bomb_position: Coords,
-    ) -> Vec<BuildingResponse> {
+    ) -> BombBlast {
         // if attacker_current.bombs.len() - attacker.bombs.len() > 1 {
 
         // }
 
-        if self.bombs.total_count <= 0 {
+        if self.attackers[unit_index].attacker_health == 0 {
             self.in_validation = InValidation {
-                message: "Bomb Count forged".to_string(),
+                message: "Bomb placed by a dead unit".to_string(),
                 is_invalidated: true,
             };
+            return BombBlast::default();
         }
 
-        if let Some(attacker) = &mut self.attacker {
-            attacker.bomb_count -= 1;
+        if self.has_status_effect(unit_index, MineEffect::Disarm) {
+            self.in_validation = InValidation {
+                message: "Bomb placed while disarmed".to_string(),
+                is_invalidated: true,
+            };
+            return BombBlast::default();
         }
 
+        let unit = &mut self.attackers[unit_index];
+        let bomb = match unit.bomb_type.clone() {
+            Some(bomb) if unit.bomb_count > 0 => bomb,
+            _ => {
+                self.in_validation = InValidation {
+                    message: "Bomb Count forged".to_string(),
+                    is_invalidated: true,
+                };
+                return BombBlast::default();
+            }
+        };
+        unit.bomb_count -= 1;
+
         if current_pos.x != bomb_position.x || current_pos.y != bomb_position.y {
             //GAME_OVER
             println!("Bomb placed out of path");
@@ -245,183 +505,262 @@ impl State {
             };
         }
 
-        self.bomb_blast(bomb_position)
+        let mut blast = BombBlast::default();
+        if bomb.effect.disables() {
+            (blast.stunned_defenders, blast.disarmed_mines) = self.emp_blast(&bomb, bomb_position);
+        }
+        if bomb.effect.deals_damage() {
+            blast.buildings = self.bomb_blast(&bomb, bomb_position);
+        }
+        blast
     }
 
+    //defenders chase their target unit along its path, or where it stands when it did not move
     pub fn defender_movement(
         &mut self,
-        attacker_delta: Vec<Coords>,
-        shortest_path: &HashMap<SourceDestXY, Coords>,
+        unit_paths: &[UnitPath],
+        shortest_path: &mut DefenderPathfinder,
     ) -> DefenderReturnType {
-        let attacker = self.attacker.as_mut().unwrap();
         let mut defenders_damaged: Vec<DefenderResponse> = Vec::new();
 
-        // if attacker is dead, no need to move the defenders
-        if attacker.attacker_health == 0 {
-            return DefenderReturnType {
-                attacker_health: attacker.attacker_health,
-                defender_response: defenders_damaged,
-                state: self.clone(),
-            };
-        }
-
-        let mut collision_array: Vec<(usize, f32)> = Vec::new();
+        //(defender index, unit index, frame fraction of the collision or projectile hit)
+        let mut collision_array: Vec<(usize, usize, f32)> = Vec::new();
 
         for (index, defender) in self.defenders.iter_mut().enumerate() {
             if !defender.is_alive || defender.target_id.is_none() {
                 continue;
             }
 
-            let attacker_ratio = attacker.attacker_speed as f32 / defender.speed as f32;
-            let mut attacker_float_coords = (
-                attacker.attacker_pos.x as f32,
-                attacker.attacker_pos.y as f32,
-            );
-            let mut attacker_delta_index = 1;
-
-            defender.path_in_current_frame.clear();
-            defender.path_in_current_frame.push(defender.defender_pos);
-
-            // for every tile of defender's movement
-            for i in 1..=defender.speed {
-                let next_hop = shortest_path
-                    .get(&SourceDestXY {
-                        source_x: defender.defender_pos.x,
-                        source_y: defender.defender_pos.y,
-                        dest_x: attacker.attacker_pos.x,
-                        dest_y: attacker.attacker_pos.y,
-                    })
-                    .unwrap_or(&defender.defender_pos);
-
-                let mut attacker_tiles_covered_fract = (((i - 1) as f32) * attacker_ratio).fract();
-
-                let mut attacker_mov_x = 0.0;
-                let mut attacker_mov_y = 0.0;
-
-                let mut attacker_tiles_left = attacker_ratio;
-                while attacker_tiles_left > 1e-6 {
-                    let attacker_tiles_fract_left = attacker_tiles_left
-                        .min(1.0)
-                        .min(1.0 - attacker_tiles_covered_fract);
-
-                    attacker_mov_x += attacker_tiles_fract_left
-                        * ((attacker_delta[attacker_delta_index].x
-                            - attacker_delta[attacker_delta_index - 1].x)
-                            as f32);
-                    attacker_mov_y += attacker_tiles_fract_left
-                        * ((attacker_delta[attacker_delta_index].y
-                            - attacker_delta[attacker_delta_index - 1].y)
-                            as f32);
-
-                    attacker_tiles_left -= attacker_tiles_fract_left;
-                    attacker_tiles_covered_fract =
-                        (attacker_tiles_covered_fract + attacker_tiles_fract_left).fract();
-                    if attacker_tiles_covered_fract == 0.0 {
-                        attacker_delta_index += 1;
-                    }
+            //a projectile already in flight lands whatever the defender does now
+            if let Some(hit_time) = defender.pending_hit.take() {
+                if hit_time > 1.0 {
+                    defender.pending_hit = Some(hit_time - 1.0);
+                } else if let Some(unit_index) = defender.target_unit.and_then(|unit_id| {
+                    self.attackers
+                        .iter()
+                        .position(|unit| unit.unit_id == unit_id && unit.attacker_health > 0)
+                }) {
+                    collision_array.push((index, unit_index, hit_time));
                 }
+            }
 
-                attacker_float_coords.0 += attacker_mov_x;
-                attacker_float_coords.1 += attacker_mov_y;
-
-                attacker.attacker_pos = Coords {
-                    x: attacker_float_coords.0.round() as i32,
-                    y: attacker_float_coords.1.round() as i32,
-                };
+            // a stunned defender stays frozen but keeps its target for when it wakes up
+            if defender.stunned_frames > 0 {
+                continue;
+            }
 
-                // if defender lags
-                if defender.target_id.unwrap() >= ((i as f32) / (defender.speed as f32)) {
-                    defender.path_in_current_frame.push(defender.defender_pos);
+            // if the target is dead, no need to move the defender
+            let unit_index = match defender.target_unit.and_then(|unit_id| {
+                self.attackers
+                    .iter()
+                    .position(|unit| unit.unit_id == unit_id && unit.attacker_health > 0)
+            }) {
+                Some(unit_index) => unit_index,
+                None => {
+                    defender.target_id = None;
+                    defender.target_unit = None;
                     continue;
                 }
-                defender.defender_pos = *next_hop;
-                defender.path_in_current_frame.push(defender.defender_pos);
+            };
+            let unit = &self.attackers[unit_index];
+            let attacker_delta: Vec<Coords> = unit_paths
+                .iter()
+                .find(|unit_path| unit_path.unit_id == unit.unit_id)
+                .map_or_else(
+                    || vec![unit.attacker_pos],
+                    |unit_path| unit_path.path.clone(),
+                );
+            let attacker_positions =
+                unit_positions(unit.attacker_pos, &attacker_delta, defender.speed);
 
-                // if defender and attacker are on the same tile, add the defender to the collision_array
-                if (defender.defender_pos == attacker.attacker_pos)
-                    || (defender.path_in_current_frame[(i - 1) as usize] == attacker.attacker_pos)
-                {
-                    collision_array.push((index, (i as f32) / (defender.speed as f32)));
-                    defender.damage_dealt = true;
-                    break;
+            defender.path_in_current_frame.clear();
+            defender.path_in_current_frame.push(defender.defender_pos);
+
+            match defender.ranged {
+                Some(ranged) => {
+                    // close in until the unit is in range, then fire a projectile that lands once it has flown the distance
+                    for i in 0..=defender.speed {
+                        let time = (i as f32) / (defender.speed as f32);
+                        if i > 0 {
+                            // if defender lags
+                            let lags = defender.target_id.unwrap() >= time;
+                            let previous_pos = attacker_positions[(i - 1) as usize];
+                            if !lags
+                                && tile_distance(defender.defender_pos, previous_pos) > ranged.range
+                            {
+                                defender.defender_pos = shortest_path
+                                    .next_hop(defender.defender_pos, previous_pos)
+                                    .unwrap_or(defender.defender_pos);
+                            }
+                            defender.path_in_current_frame.push(defender.defender_pos);
+                        }
+                        if defender.target_id.unwrap() > time {
+                            continue;
+                        }
+
+                        let distance =
+                            tile_distance(defender.defender_pos, attacker_positions[i as usize]);
+                        // one projectile in flight at a time
+                        if distance <= ranged.range && defender.pending_hit.is_none() {
+                            let hit_time =
+                                time + distance as f32 / max(1, ranged.projectile_speed) as f32;
+                            // a projectile that lands after this frame is carried over to the next ones
+                            if hit_time <= 1.0 {
+                                collision_array.push((index, unit_index, hit_time));
+                            } else {
+                                defender.pending_hit = Some(hit_time - 1.0);
+                            }
+                            break;
+                        }
+                    }
+                }
+                None => {
+                    // for every tile of defender's movement
+                    for i in 1..=defender.speed {
+                        let next_hop = shortest_path
+                            .next_hop(defender.defender_pos, attacker_positions[(i - 1) as usize])
+                            .unwrap_or(defender.defender_pos);
+                        let attacker_pos = attacker_positions[i as usize];
+
+                        // if defender lags
+                        if defender.target_id.unwrap() >= ((i as f32) / (defender.speed as f32)) {
+                            defender.path_in_current_frame.push(defender.defender_pos);
+                            continue;
+                        }
+                        defender.defender_pos = next_hop;
+                        defender.path_in_current_frame.push(defender.defender_pos);
+
+                        // if defender and attacker are on the same tile, add the defender to the collision_array
+                        if (defender.defender_pos == attacker_pos)
+                            || (defender.path_in_current_frame[(i - 1) as usize] == attacker_pos)
+                        {
+                            collision_array.push((
+                                index,
+                                unit_index,
+                                (i as f32) / (defender.speed as f32),
+                            ));
+                            defender.damage_dealt = true;
+                            break;
+                        }
+                    }
+                    if !defender.damage_dealt {
+                        collision_array.push((index, unit_index, 2.0));
+                    }
                 }
             }
             defender.target_id = Some(0.0);
-            if !defender.damage_dealt {
-                collision_array.push((index, 2.0));
+        }
+
+        for unit_path in unit_paths {
+            if let Some(unit_index) = self.unit_index(Some(unit_path.unit_id)) {
+                if self.attackers[unit_index].attacker_health > 0 {
+                    self.attackers[unit_index].attacker_pos = *unit_path.path.last().unwrap();
+                }
             }
-            attacker.attacker_pos = *attacker_delta.first().unwrap();
         }
 
-        attacker.attacker_pos = *attacker_delta.last().unwrap();
         // sort the collision_array by the time of collision
-        collision_array.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        let mut attacker_death_time = 0.0; // frame fraction at which attacker dies
-        for (index, time) in collision_array {
-            self.defenders[index].target_id = None;
+        collision_array.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+        // frame fraction at which each unit dies
+        let mut unit_death_times: HashMap<usize, f32> = HashMap::new();
+        for (index, unit_index, time) in collision_array {
+            // ranged defenders keep firing at their target until it dies
+            let ranged = self.defenders[index].ranged;
+            if ranged.is_none() {
+                self.defenders[index].target_id = None;
+                self.defenders[index].target_unit = None;
+            }
             if time > 1.0 {
                 break;
             }
-            if attacker.attacker_health == 0 {
-                self.defenders[index].defender_pos = self.defenders[index].path_in_current_frame
-                    [(attacker_death_time * (self.defenders[index].speed as f32)) as usize];
+            if let Some(&attacker_death_time) = unit_death_times.get(&unit_index) {
+                let defender = &mut self.defenders[index];
+                let death_tile = (attacker_death_time * (defender.speed as f32)) as usize;
+                if let Some(position) = defender.path_in_current_frame.get(death_tile) {
+                    defender.defender_pos = *position;
+                }
                 continue;
             }
+            let damage = ranged.map_or(self.defenders[index].damage, |ranged| ranged.damage);
+            let attacker = &mut self.attackers[unit_index];
             defenders_damaged.push(DefenderResponse {
                 id: self.defenders[index].id,
                 position: self.defenders[index].defender_pos,
-                damage: self.defenders[index].damage,
+                damage,
+                unit_id: attacker.unit_id,
+                ranged: ranged.is_some(),
             });
-            self.defenders[index].damage_dealt = true;
             attacker.trigger_defender = true;
-            attacker.attacker_health =
-                max(0, attacker.attacker_health - self.defenders[index].damage);
-            self.defenders[index].is_alive = false;
+            let damage = attacker.ability.absorb(damage);
+            attacker.attacker_health = max(0, attacker.attacker_health - damage);
+            if ranged.is_none() {
+                self.defenders[index].damage_dealt = true;
+                self.defenders[index].is_alive = false;
+            }
 
             if attacker.attacker_health == 0 {
-                attacker_death_time = time;
-                self.attacker_death_count += 1;
+                unit_death_times.insert(unit_index, time);
+                self.unit_died(unit_index);
             }
         }
 
+        //health of the first unit moved, for clients that only track one
+        let attacker_health = unit_paths
+            .first()
+            .and_then(|unit_path| self.unit_index(Some(unit_path.unit_id)))
+            .map_or(0, |unit_index| self.attackers[unit_index].attacker_health);
+
         DefenderReturnType {
-            attacker_health: attacker.attacker_health,
+            attacker_health,
             defender_response: defenders_damaged,
             state: self.clone(),
         }
     }
 
-    pub fn mine_blast(&mut self, start_pos: Option<Coords>) -> Vec<MineDetails> {
+    pub fn mine_blast(&mut self, unit_index: usize, start_pos: Option<Coords>) -> Vec<MineDetails> {
         let mut damage_to_attacker;
         let attack_current_pos = start_pos.unwrap();
 
         let mut triggered_mines: Vec<MineDetails> = Vec::new();
 
+        if self.attackers[unit_index].attacker_health == 0 {
+            return triggered_mines;
+        }
+
         for mine in self.mines.clone().iter_mut() {
-            if attack_current_pos.x == mine.position.x && attack_current_pos.y == mine.position.y {
+            if attack_current_pos.x == mine.position.x
+                && attack_current_pos.y == mine.position.y
+                && mine.disarmed_frames == 0
+            {
                 damage_to_attacker = mine.damage;
                 triggered_mines.push(MineDetails {
                     id: mine.id,
                     position: mine.position,
                     radius: mine.radius,
                     damage: mine.damage,
+                    effect: mine.effect,
+                    effect_duration: mine.effect_duration,
+                    disarmed_frames: 0,
                 });
-                self.mine_blast_update(mine.id, damage_to_attacker);
+                self.mine_blast_update(unit_index, mine.id, damage_to_attacker);
+                if mine.effect != MineEffect::None && mine.effect_duration > 0 {
+                    self.apply_status_effect(unit_index, mine.effect, mine.effect_duration);
+                }
             }
         }
 
         triggered_mines
     }
 
-    pub fn bomb_blast(&mut self, bomb_position: Coords) -> Vec<BuildingResponse> {
-        let bomb = &mut self.bombs;
+    pub fn bomb_blast(&mut self, bomb: &BombType, bomb_position: Coords) -> Vec<BuildingResponse> {
         let mut buildings_damaged: Vec<BuildingResponse> = Vec::new();
         for building in self.buildings.iter_mut() {
             if building.current_hp > 0 {
                 let mut artifacts_taken_by_destroying_building: i32 = 0;
 
+                //footprint spans width along x and height along y
                 let building_matrix: HashSet<Coords> = (building.tile.y
-                    ..building.tile.y + building.width)
+                    ..building.tile.y + building.height)
                     .flat_map(|y| {
                         (building.tile.x..building.tile.x + building.width)
                             .map(move |x| Coords { x, y })
@@ -456,9 +795,9 @@ impl State {
                             (building.artifacts_obtained as f32 * PERCENTANGE_ARTIFACTS_OBTAINABLE)
                                 .floor() as i32;
                         self.artifacts += artifacts_taken_by_destroying_building;
-                        self.damage_percentage +=
-                            (current_damage as f32 / self.total_hp_buildings as f32) * 100.0_f32;
-                    } else {
+                    }
+
+                    if !building.is_wall {
                         self.damage_percentage +=
                             (current_damage as f32 / self.total_hp_buildings as f32) * 100.0_f32;
                     }
@@ -475,8 +814,377 @@ impl State {
             }
         }
 
-        self.bombs.total_count -= 1;
-
         buildings_damaged
     }
+
+    //freezes the defenders and disarms the mines caught in the blast, a longer disable wins
+    fn emp_blast(
+        &mut self,
+        bomb: &BombType,
+        bomb_position: Coords,
+    ) -> (Vec<DisabledResponse>, Vec<DisabledResponse>) {
+        let radius = bomb.radius;
+        let duration = bomb.disable_duration;
+        if duration <= 0 {
+            return (Vec::new(), Vec::new());
+        }
+        let in_blast = |position: Coords| tile_distance(position, bomb_position) <= radius;
+
+        let mut stunned_defenders: Vec<DisabledResponse> = Vec::new();
+        for defender in self
+            .defenders
+            .iter_mut()
+            .filter(|defender| defender.is_alive && in_blast(defender.defender_pos))
+        {
+            defender.stunned_frames = max(defender.stunned_frames, duration);
+            stunned_defenders.push(DisabledResponse {
+                id: defender.id,
+                position: defender.defender_pos,
+                frames: defender.stunned_frames,
+            });
+        }
+
+        let mut disarmed_mines: Vec<DisabledResponse> = Vec::new();
+        for mine in self.mines.iter_mut().filter(|mine| in_blast(mine.position)) {
+            mine.disarmed_frames = max(mine.disarmed_frames, duration);
+            disarmed_mines.push(DisabledResponse {
+                id: mine.id,
+                position: mine.position,
+                frames: mine.disarmed_frames,
+            });
+        }
+
+        (stunned_defenders, disarmed_mines)
+    }
+
+    fn tick_status_effects(&mut self) {
+        for unit in self.attackers.iter_mut() {
+            for status in unit.status_effects.iter_mut() {
+                status.frames_left -= 1;
+            }
+            unit.status_effects.retain(|status| status.frames_left > 0);
+        }
+    }
+
+    //abilities heal, wear off and cool down
+    fn tick_abilities(&mut self) {
+        for unit in self
+            .attackers
+            .iter_mut()
+            .filter(|unit| unit.attacker_health > 0)
+        {
+            let ability = &mut unit.ability;
+            if ability.is_active(AttackerAbility::Heal) {
+                unit.attacker_health = max(
+                    unit.attacker_health,
+                    min(unit.max_health, unit.attacker_health + ability.power),
+                );
+            }
+            if ability.frames_active > 0 {
+                ability.frames_active -= 1;
+                if ability.frames_active == 0 {
+                    ability.shield_left = 0;
+                }
+            }
+            ability.cooldown_left = max(0, ability.cooldown_left - 1);
+        }
+    }
+
+    fn tick_emp_effects(&mut self) {
+        for defender in self.defenders.iter_mut() {
+            defender.stunned_frames = max(0, defender.stunned_frames - 1);
+        }
+        for mine in self.mines.iter_mut() {
+            mine.disarmed_frames = max(0, mine.disarmed_frames - 1);
+        }
+    }
+}
+
+//speed left after mine effects and a sprint, a root overrides both
+fn effective_speed(speed: i32, status_effects: &[StatusEffect], ability: &AbilityState) -> i32 {
+    let has_effect =
+        |effect: MineEffect| status_effects.iter().any(|status| status.effect == effect);
+    let sprint_tiles = if ability.is_active(AttackerAbility::Sprint) {
+        ability.power
+    } else {
+        0
+    };
+    if has_effect(MineEffect::Root) {
+        0
+    } else if has_effect(MineEffect::Slow) {
+        max(1, speed * SLOW_EFFECT_SPEED_PERCENTAGE / 100) + sprint_tiles
+    } else {
+        speed + sprint_tiles
+    }
+}
+
+//square distance, so a range reaches as far as a blast of the same radius
+fn tile_distance(a: Coords, b: Coords) -> i32 {
+    max((a.x - b.x).abs(), (a.y - b.y).abs())
+}
+
+//where a unit stands after each of a defender's steps in the frame, starting from where it stood
+fn unit_positions(start: Coords, attacker_delta: &[Coords], steps: i32) -> Vec<Coords> {
+    //tiles actually covered this frame, which effects take away from or add to the base speed
+    let attacker_tiles = attacker_delta.len().saturating_sub(1);
+    let attacker_ratio = attacker_tiles as f32 / steps as f32;
+    let mut attacker_float_coords = (start.x as f32, start.y as f32);
+    let mut attacker_delta_index = 1;
+
+    let mut positions = vec![start];
+    for i in 1..=steps {
+        let mut attacker_tiles_covered_fract = (((i - 1) as f32) * attacker_ratio).fract();
+
+        let mut attacker_mov_x = 0.0;
+        let mut attacker_mov_y = 0.0;
+
+        let mut attacker_tiles_left = attacker_ratio;
+        while attacker_tiles_left > 1e-6 && attacker_delta_index < attacker_delta.len() {
+            let attacker_tiles_fract_left = attacker_tiles_left
+                .min(1.0)
+                .min(1.0 - attacker_tiles_covered_fract);
+
+            attacker_mov_x += attacker_tiles_fract_left
+                * ((attacker_delta[attacker_delta_index].x
+                    - attacker_delta[attacker_delta_index - 1].x) as f32);
+            attacker_mov_y += attacker_tiles_fract_left
+                * ((attacker_delta[attacker_delta_index].y
+                    - attacker_delta[attacker_delta_index - 1].y) as f32);
+
+            attacker_tiles_left -= attacker_tiles_fract_left;
+            attacker_tiles_covered_fract =
+                (attacker_tiles_covered_fract + attacker_tiles_fract_left).fract();
+            if attacker_tiles_covered_fract == 0.0 {
+                attacker_delta_index += 1;
+            }
+        }
+
+        attacker_float_coords.0 += attacker_mov_x;
+        attacker_float_coords.1 += attacker_mov_y;
+
+        positions.push(Coords {
+            x: attacker_float_coords.0.round() as i32,
+            y: attacker_float_coords.1.round() as i32,
+        });
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::defense::shortest_path::NextHopMatrix;
+    use crate::models::EmpEffect;
+    use crate::validator::pathfinding::TerrainGrid;
+    use crate::validator::util::RangedAttack;
+
+    //a building two tiles wide along x and three tiles tall along y
+    fn state_with_tall_building() -> State {
+        let building = BuildingDetails {
+            id: 1,
+            current_hp: 1000,
+            total_hp: 1000,
+            artifacts_obtained: 0,
+            tile: Coords { x: 10, y: 10 },
+            width: 2,
+            height: 3,
+            is_wall: false,
+        };
+        let mut state = State::new(1, 2, Vec::new(), Vec::new(), vec![building]);
+        state.total_hp_buildings = 1000;
+        state
+    }
+
+    fn bomb() -> BombType {
+        BombType {
+            id: 1,
+            radius: 0,
+            damage: 10,
+            total_count: 1,
+            effect: EmpEffect::Damage,
+            disable_duration: 0,
+        }
+    }
+
+    fn is_damaged(bomb_position: Coords) -> bool {
+        !state_with_tall_building()
+            .bomb_blast(&bomb(), bomb_position)
+            .is_empty()
+    }
+
+    #[test]
+    fn bomb_hits_every_tile_of_the_footprint() {
+        for x in 10..12 {
+            for y in 10..13 {
+                assert!(is_damaged(Coords { x, y }), "({x}, {y}) is not hit");
+            }
+        }
+    }
+
+    #[test]
+    fn bomb_misses_tiles_past_the_width_and_height() {
+        assert!(!is_damaged(Coords { x: 12, y: 10 }));
+        assert!(!is_damaged(Coords { x: 10, y: 13 }));
+        assert!(!is_damaged(Coords { x: 9, y: 12 }));
+    }
+
+    #[test]
+    fn damage_scales_with_the_share_of_the_footprint_hit() {
+        //a radius 0 bomb covers one of the six tiles
+        let mut state = state_with_tall_building();
+        let damaged = state.bomb_blast(&bomb(), Coords { x: 11, y: 12 });
+        let expected_damage = (10.0 * BOMB_DAMAGE_MULTIPLIER / 6.0).round() as i32;
+
+        assert_eq!(damaged.len(), 1);
+        assert_eq!(damaged[0].hp, 1000 - expected_damage);
+    }
+
+    fn unit(unit_id: i32, bomb_type: Option<BombType>) -> Attacker {
+        Attacker {
+            id: 1,
+            unit_id,
+            attacker_pos: Coords { x: 0, y: 0 },
+            attacker_health: 100,
+            attacker_speed: 4,
+            path_in_current_frame: Vec::new(),
+            bombs: Vec::new(),
+            trigger_defender: false,
+            bomb_count: if bomb_type.is_some() { 1 } else { 0 },
+            status_effects: Vec::new(),
+            max_health: 100,
+            ability: AbilityState::default(),
+            bomb_type,
+        }
+    }
+
+    fn state_with_slowed_unit() -> State {
+        let mut state = State::new(1, 2, Vec::new(), Vec::new(), Vec::new());
+        state.attackers.push(unit(1, None));
+        state.apply_status_effect(0, MineEffect::Slow, 2);
+        state
+    }
+
+    fn straight_path(tiles: i32) -> UnitPath {
+        UnitPath {
+            unit_id: 1,
+            path: (0..=tiles).map(|x| Coords { x, y: 0 }).collect(),
+        }
+    }
+
+    #[test]
+    fn effects_wear_off_with_every_accepted_frame() {
+        let roads: HashSet<(i32, i32)> = (0..5).map(|x| (x, 0)).collect();
+        let mut state = state_with_slowed_unit();
+        state.attacker_movement(1, &roads, &[straight_path(2)]);
+        assert!(state.has_status_effect(0, MineEffect::Slow));
+        state.attacker_movement(2, &roads, &[straight_path(4)]);
+        assert!(!state.has_status_effect(0, MineEffect::Slow));
+        assert!(!state.in_validation.is_invalidated);
+    }
+
+    #[test]
+    fn slowed_unit_must_move_its_exact_speed() {
+        let roads: HashSet<(i32, i32)> = (0..5).map(|x| (x, 0)).collect();
+        for tiles in [0, 1, 3] {
+            let mut state = state_with_slowed_unit();
+            state.unit_movement(1, &roads, 0, &straight_path(tiles));
+            assert!(state.in_validation.is_invalidated, "{tiles} tiles accepted");
+        }
+
+        let mut state = state_with_slowed_unit();
+        state.unit_movement(1, &roads, 0, &straight_path(2));
+        assert!(!state.in_validation.is_invalidated);
+    }
+
+    #[test]
+    fn each_unit_blasts_with_its_own_bomb_type() {
+        let mut state = state_with_tall_building();
+        let emp = BombType {
+            effect: EmpEffect::Disable,
+            disable_duration: 2,
+            ..bomb()
+        };
+        state.attackers.push(unit(0, Some(emp)));
+        state.attackers.push(unit(1, Some(bomb())));
+        let target = Coords { x: 10, y: 10 };
+
+        assert!(state.place_bombs(0, target, target).buildings.is_empty());
+        assert_eq!(state.place_bombs(1, target, target).buildings.len(), 1);
+        assert_eq!(state.bombs_left(), 0);
+        assert!(!state.in_validation.is_invalidated);
+    }
+
+    #[test]
+    fn every_unit_deployed_uses_up_a_life() {
+        let mut state = State::new(1, 2, Vec::new(), Vec::new(), Vec::new());
+        state.set_placement(Placement::new(&HashSet::from([(0, 0)]), HashSet::from([1])));
+        for _ in 0..LIVES {
+            assert!(state.place_attacker(unit(0, None)).is_some());
+        }
+        state.self_destruct(0);
+
+        assert!(state.place_attacker(unit(0, None)).is_none());
+        assert_eq!(state.in_validation.message, "No lives left");
+    }
+
+    #[test]
+    fn slow_projectile_lands_in_a_later_frame() {
+        //three tiles away at one tile per frame, the hit lands three frames after firing
+        let defender = DefenderDetails {
+            id: 1,
+            radius: 3,
+            speed: 1,
+            damage: 0,
+            defender_pos: Coords { x: 3, y: 3 },
+            is_alive: true,
+            damage_dealt: false,
+            target_id: Some(0.0),
+            target_unit: Some(0),
+            path_in_current_frame: Vec::new(),
+            stunned_frames: 0,
+            ranged: Some(RangedAttack {
+                range: 3,
+                damage: 10,
+                projectile_speed: 1,
+            }),
+            pending_hit: None,
+        };
+        let mut state = State::new(1, 2, vec![defender], Vec::new(), Vec::new());
+        state.attackers.push(unit(0, None));
+        let mut pathfinder = DefenderPathfinder {
+            road_paths: NextHopMatrix::new(&[]),
+            terrain: TerrainGrid::new(&HashSet::new(), &[], &[], &[]),
+        };
+
+        for _ in 0..2 {
+            state.defender_movement(&[], &mut pathfinder);
+            assert_eq!(state.attackers[0].attacker_health, 100);
+        }
+        state.defender_movement(&[], &mut pathfinder);
+        assert_eq!(state.attackers[0].attacker_health, 90);
+    }
+
+    #[test]
+    fn unit_cannot_walk_through_a_standing_wall() {
+        let wall = BuildingDetails {
+            id: 1,
+            current_hp: 100,
+            total_hp: 100,
+            artifacts_obtained: 0,
+            tile: Coords { x: 2, y: 0 },
+            width: 1,
+            height: 1,
+            is_wall: true,
+        };
+        let mut state = State::new(1, 2, Vec::new(), Vec::new(), vec![wall]);
+        state.attackers.push(unit(1, None));
+        let roads: HashSet<(i32, i32)> = (0..5).map(|x| (x, 0)).collect();
+
+        state.unit_movement(1, &roads, 0, &straight_path(4));
+        assert!(state.in_validation.is_invalidated);
+        assert_eq!(
+            state.in_validation.message,
+            "attacker walked through a wall"
+        );
+    }
 }
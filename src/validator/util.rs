@@ -1,5 +1,7 @@
 use crate::api::attack::socket::DefenderResponse;
-use crate::api::attack::socket::{ResultType, SocketResponse};
+use crate::api::attack::socket::{BuildingResponse, DisabledResponse, ResultType, SocketResponse};
+use crate::constants::MAP_SIZE;
+use crate::models::{AttackerAbility, AttackerType, EmpEffect, MineEffect};
 use crate::validator::state::State;
 use serde::{Deserialize, Serialize};
 
@@ -23,6 +25,9 @@ pub struct Bomb {
 #[derive(Serialize, Clone, Deserialize)]
 pub struct Attacker {
     pub id: i32,
+    //the unit within the squad, id is the attacker type
+    #[serde(default)]
+    pub unit_id: i32,
     pub attacker_pos: Coords,
     pub attacker_health: i32,
     pub attacker_speed: i32,
@@ -30,6 +35,63 @@ pub struct Attacker {
     pub bombs: Vec<Bomb>,
     pub trigger_defender: bool,
     pub bomb_count: i32,
+    #[serde(default)]
+    pub status_effects: Vec<StatusEffect>,
+    #[serde(default)]
+    pub max_health: i32,
+    #[serde(default)]
+    pub ability: AbilityState,
+    //the emp the unit brought, None when it came without bombs
+    #[serde(default)]
+    pub bomb_type: Option<BombType>,
+}
+
+//the attacker type's ability and what is left of it during the attack
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub struct AbilityState {
+    pub ability: AttackerAbility,
+    //health healed per frame, damage absorbed or extra tiles per frame
+    pub power: i32,
+    pub duration: i32,
+    pub cooldown: i32,
+    pub charges_left: i32,
+    pub frames_active: i32,
+    pub cooldown_left: i32,
+    //damage the active shield can still absorb
+    pub shield_left: i32,
+}
+
+impl AbilityState {
+    pub fn new(attacker_type: &AttackerType) -> Self {
+        AbilityState {
+            ability: attacker_type.ability,
+            power: attacker_type.ability_power,
+            duration: attacker_type.ability_duration,
+            cooldown: attacker_type.ability_cooldown,
+            charges_left: attacker_type.ability_charges,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_active(&self, ability: AttackerAbility) -> bool {
+        self.ability == ability && self.frames_active > 0
+    }
+
+    //damage left over once the shield has taken what it can
+    pub fn absorb(&mut self, damage: i32) -> i32 {
+        if !self.is_active(AttackerAbility::Shield) {
+            return damage;
+        }
+        let absorbed = damage.min(self.shield_left);
+        self.shield_left -= absorbed;
+        damage - absorbed
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Deserialize)]
+pub struct StatusEffect {
+    pub effect: MineEffect,
+    pub frames_left: i32,
 }
 
 #[derive(Serialize, Clone, Deserialize)]
@@ -47,7 +109,27 @@ pub struct DefenderDetails {
     pub is_alive: bool,
     pub damage_dealt: bool,
     pub target_id: Option<f32>,
+    //the squad unit being chased
+    #[serde(default)]
+    pub target_unit: Option<i32>,
     pub path_in_current_frame: Vec<Coords>,
+    //frames left frozen by an emp
+    #[serde(default)]
+    pub stunned_frames: i32,
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+    //frames until a projectile fired in an earlier frame lands
+    #[serde(default)]
+    pub pending_hit: Option<f32>,
+}
+
+//a defender that stops within range of its target and fires a projectile once a frame
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct RangedAttack {
+    pub range: i32,
+    pub damage: i32,
+    //tiles per frame
+    pub projectile_speed: i32,
 }
 
 // Structs for sending response
@@ -57,6 +139,11 @@ pub struct MineDetails {
     pub position: Coords,
     pub radius: i32,
     pub damage: i32,
+    pub effect: MineEffect,
+    pub effect_duration: i32,
+    //frames left before an emp disarmed mine goes off again
+    #[serde(default)]
+    pub disarmed_frames: i32,
 }
 
 #[derive(Serialize, Clone, Deserialize)]
@@ -65,6 +152,8 @@ pub struct BombType {
     pub radius: i32,
     pub damage: i32,
     pub total_count: i32,
+    pub effect: EmpEffect,
+    pub disable_duration: i32,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -75,6 +164,8 @@ pub struct BuildingDetails {
     pub artifacts_obtained: i32,
     pub tile: Coords,
     pub width: i32,
+    pub height: i32,
+    pub is_wall: bool,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -102,6 +193,14 @@ pub struct DefenderReturnType {
     pub state: State,
 }
 
+//what a single bomb did to the base
+#[derive(Serialize, Clone, Default)]
+pub struct BombBlast {
+    pub buildings: Vec<BuildingResponse>,
+    pub stunned_defenders: Vec<DisabledResponse>,
+    pub disarmed_mines: Vec<DisabledResponse>,
+}
+
 #[derive(Serialize)]
 pub struct ValidatorResponse {
     pub frame_no: i32,
@@ -122,9 +221,22 @@ pub fn send_terminate_game_message(frame_number: i32, message: String) -> Socket
         exploded_mines: None,
         defender_damaged: None,
         damaged_buildings: None,
+        revealed_blocks: None,
+        squad: None,
+        stunned_defenders: None,
+        disarmed_mines: None,
         total_damage_percentage: None,
         is_sync: false,
         is_game_over: true,
         message: Some(message),
     }
 }
+
+//row major index of a tile, None for tiles outside the map
+pub fn tile_index(x: i32, y: i32) -> Option<usize> {
+    if (0..MAP_SIZE as i32).contains(&x) && (0..MAP_SIZE as i32).contains(&y) {
+        Some(x as usize * MAP_SIZE + y as usize)
+    } else {
+        None
+    }
+}